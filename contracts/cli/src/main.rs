@@ -3,9 +3,15 @@ use std::str::FromStr;
 use anyhow::Result;
 use borsh::BorshSerialize;
 use clap::{Parser, Subcommand};
+use deal_platform::state::{seeds, AccountState, DealGate, DiscountKind, PriceCurve, RedemptionSchedule};
 use solana_client::rpc_client::RpcClient;
+use spl_associated_token_account::get_associated_token_address;
 use solana_sdk::{
 	commitment_config::CommitmentConfig,
+	hash::Hash,
+	message::Message,
+	packet::PACKET_DATA_SIZE,
+	program_pack::Pack,
 	signature::{read_keypair_file, Keypair, Signer},
 	transaction::Transaction,
 };
@@ -26,16 +32,85 @@ struct Cli {
 enum Commands {
 	RegisterMerchant { #[arg(long)] name: String, #[arg(long)] uri: String },
 	CreateDeal {
-		#[arg(long)] deal_id: u64,
 		#[arg(long)] title: String,
 		#[arg(long)] description: String,
-		#[arg(long)] discount: u8,
+		#[arg(long)] discount: String, // percent:<1-100>, fixed:<amount>:<ISO currency>, bogo or free:<sku>
 		#[arg(long)] expiry: i64,
 		#[arg(long, default_value_t = 1)] total_supply: u32,
+		#[arg(long, default_value = "")] image_uri: String,
+		#[arg(long, default_value = "")] metadata_uri: String,
+	},
+	MintCoupon {
+		#[arg(long)] deal_id: u64,
+		#[arg(long)] merchant: Option<String>, // defaults to the payer
+		#[arg(long)] coupon_mint: Option<String>, // required for paid deals and for coupons that will be redeemed
+		#[arg(long)] allowlist: Option<String>, // JSON written by allowlist-build, for allowlisted deals
+		#[arg(long)] gate_nft: Option<String>, // NFT of the required collection, for collection-gated deals
+	},
+	// Run by the coupon's holder; coupons with an escrowed payment are burned, releasing it to the merchant
+	RedeemCoupon { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] mint: String },
+	ClaimRefund { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] mint: String, #[arg(long)] group: bool }, // --group refunds a group deal that missed its threshold
+	SetGroupBuy { #[arg(long)] deal_id: u64, #[arg(long)] min_participants: u32, #[arg(long, default_value_t = 0)] funding_deadline: i64 }, // 0 participants turns it off
+	SetRaffle { #[arg(long)] deal_id: u64, #[arg(long)] winners: u32, #[arg(long, default_value_t = 0)] entry_deadline: i64 }, // 0 winners turns it off
+	EnterRaffle { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String },
+	DrawRaffle { #[arg(long)] deal_id: u64, #[arg(long)] merchant: Option<String> },
+	// Build a Merkle allowlist from a CSV of `pubkey[,allowance]` lines and write the root and proofs as JSON
+	AllowlistBuild { #[arg(long)] csv: String, #[arg(long)] out: String },
+	// Pass --token-mint with --min-amount, or --collection; neither removes the gate
+	SetGate {
+		#[arg(long)] deal_id: u64,
+		#[arg(long)] token_mint: Option<String>,
+		#[arg(long, default_value_t = 1)] min_amount: u64,
+		#[arg(long)] collection: Option<String>,
+	},
+	// Mint a coupon to every wallet in a file (one pubkey per line), packing as many as fit in each transaction
+	AirdropCoupons { #[arg(long)] deal_id: u64, #[arg(long)] recipients: String },
+	// Mint a coupon, park it behind a one-time secret and print the secret to share as a claim link
+	CreateClaim { #[arg(long)] deal_id: u64, #[arg(long)] expires_at: i64 },
+	Claim { #[arg(long)] secret: String, #[arg(long)] recipient: Option<String> }, // recipient defaults to the payer
+	ReclaimExpired { #[arg(long)] claim: String }, // claim account printed by create-claim
+	SetAllowlist { #[arg(long)] deal_id: u64, #[arg(long)] root: Option<String> }, // omit the root to open the deal to everyone
+	FinalizeGroupDeal { #[arg(long)] deal_id: u64, #[arg(long, value_delimiter = ',')] coupon_mints: Vec<String> },
+	AddReview {
+		#[arg(long)] deal_id: u64,
+		#[arg(long)] merchant: Option<String>, // defaults to the payer
+		#[arg(long)] mint: String, // coupon the payer redeemed or still holds
+		#[arg(long)] rating: u8,
+		#[arg(long)] comment: String,
+	},
+	UpdateReview { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] rating: u8, #[arg(long)] comment: String },
+	DeleteReview { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String },
+	ReplyToReview { #[arg(long)] deal_id: u64, #[arg(long)] user: String, #[arg(long)] comment: String }, // user = the reviewer
+	// Print a deal's reviews, skipping ones the admin hid unless --include-hidden
+	ListReviews { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] include_hidden: bool },
+	// Print the DealEvents a confirmed transaction emitted
+	Events { #[arg(long)] signature: String },
+	FlagReview { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] user: String },
+	// Admin only; --unhide restores the review
+	HideReview { #[arg(long)] deal_id: u64, #[arg(long)] merchant: String, #[arg(long)] user: String, #[arg(long)] unhide: bool },
+	// Run by the program's upgrade authority
+	SetAdmin { #[arg(long)] admin: String },
+	SetDealStatus { #[arg(long)] deal_id: u64, #[arg(long)] status: String }, // active, paused or cancelled
+	RecountDeals,
+	// Rewrites the payer's merchant account, one of their deals with --deal-id, or any account with --account, in the current layout
+	MigrateAccount { #[arg(long)] deal_id: Option<u64>, #[arg(long)] account: Option<String> },
+	SetMintPrice { #[arg(long)] deal_id: u64, #[arg(long)] lamports: u64 },
+	SetTokenMintPrice { #[arg(long)] deal_id: u64, #[arg(long)] payment_mint: Option<String>, #[arg(long, default_value_t = 0)] amount: u64 }, // no mint clears the token price
+	SetRedemptionSchedule {
+		#[arg(long)] deal_id: u64,
+		#[arg(long, default_value_t = 0x7f)] weekdays: u8, // bit 0 = Monday .. bit 6 = Sunday
+		#[arg(long)] start_minute: Option<u16>,
+		#[arg(long)] end_minute: Option<u16>,
+		#[arg(long, default_value_t = 0, allow_hyphen_values = true)] utc_offset_minutes: i16,
+	},
+	SetPriceCurve {
+		#[arg(long)] deal_id: u64,
+		#[arg(long)] start_price: Option<u64>, // omit to clear the curve
+		#[arg(long, default_value_t = 0)] end_price: u64,
+		#[arg(long, default_value_t = 0)] start_ts: i64,
+		#[arg(long, default_value_t = 0)] end_ts: i64,
+		#[arg(long, default_value_t = 60)] step_seconds: u32,
 	},
-	MintCoupon { #[arg(long)] deal_id: u64 },
-	RedeemCoupon { #[arg(long)] mint: String },
-	AddReview { #[arg(long)] deal_id: u64, #[arg(long)] rating: u8, #[arg(long)] comment: String },
 }
 
 fn rpc_url(cluster: &str) -> String {
@@ -49,19 +124,263 @@ fn payer_path() -> String {
 	std::env::var("SOLANA_KEYPAIR").unwrap_or_else(|_| format!("{}/.config/solana/id.json", std::env::var("HOME").unwrap()))
 }
 
+fn parse_discount(s: &str) -> Result<DiscountKind> {
+	let parts: Vec<&str> = s.split(':').collect();
+	Ok(match parts.as_slice() {
+		["percent", p] => DiscountKind::Percent(p.parse()?),
+		["fixed", amount, currency] => {
+			let currency_code: [u8; 3] = currency.to_ascii_uppercase().as_bytes().try_into().map_err(|_| anyhow::anyhow!("currency must be a 3-letter code"))?;
+			DiscountKind::FixedAmount { amount: amount.parse()?, currency_code }
+		}
+		["bogo"] => DiscountKind::Bogo,
+		["free", sku] => DiscountKind::FreeItem { sku: sku.to_string() },
+		_ => anyhow::bail!("unknown discount: {}", s),
+	})
+}
+
+fn merchant_pda(program_id: &Pubkey, merchant: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::MERCHANT, merchant.as_ref()], program_id).0
+}
+
+fn deal_pda(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::DEAL, merchant.as_ref(), &deal_id.to_le_bytes()], program_id).0
+}
+
+fn review_pda(program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REVIEW, deal.as_ref(), user.as_ref()], program_id).0
+}
+
+fn redeem_pda(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REDEEM, mint.as_ref()], program_id).0
+}
+
+fn vault_pda(program_id: &Pubkey, deal: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::VAULT, deal.as_ref()], program_id).0
+}
+
+fn coupon_pda(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::COUPON, mint.as_ref()], program_id).0
+}
+
+fn allowance_pda(program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::ALLOWANCE, deal.as_ref(), user.as_ref()], program_id).0
+}
+
+fn entry_pda(program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::ENTRY, deal.as_ref(), user.as_ref()], program_id).0
+}
+
+fn read_allowlist_csv(path: &str) -> Result<Vec<(Pubkey, u32)>> {
+	let mut rows = Vec::new();
+	for (n, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') { continue; }
+		let mut fields = line.split(',').map(str::trim);
+		let user = fields.next().unwrap_or_default();
+		let Ok(user) = Pubkey::from_str(user) else {
+			// Allow a header row
+			if n == 0 { continue; }
+			anyhow::bail!("line {}: invalid pubkey {}", n + 1, user);
+		};
+		let allowance = match fields.next() { Some("") | None => 0, Some(a) => a.parse()? };
+		rows.push((user, allowance));
+	}
+	Ok(rows)
+}
+
+fn allowlist_proof(path: &str, user: &Pubkey) -> Result<deal_platform::merkle::AllowlistProof> {
+	let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+	let entry = &json["entries"][user.to_string()];
+	let Some(allowance) = entry["allowance"].as_u64() else { anyhow::bail!("{} is not on the allowlist", user) };
+	let proof = entry["proof"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		.map(|h| Ok(Hash::from_str(h.as_str().unwrap_or_default())?.to_bytes()))
+		.collect::<Result<Vec<_>>>()?;
+	Ok(deal_platform::merkle::AllowlistProof { allowance: u32::try_from(allowance)?, proof })
+}
+
+// Recipients per transaction are capped by size in practice; this also keeps compute well under the limit
+const MAX_AIRDROPS_PER_TX: usize = 8;
+
+fn airdrop_coupons(client: &RpcClient, program_id: &Pubkey, payer: &Keypair, deal_id: u64, recipients_path: &str) -> Result<()> {
+	let mut recipients = Vec::new();
+	for line in std::fs::read_to_string(recipients_path)?.lines().map(str::trim) {
+		if line.is_empty() || line.starts_with('#') { continue; }
+		recipients.push(Pubkey::from_str(line)?);
+	}
+	let deal_pda = deal_pda(program_id, &payer.pubkey(), deal_id);
+	let deal = deal_platform::state::Deal::unpack(&client.get_account_data(&deal_pda)?)?;
+	let remaining = deal.total_supply.saturating_sub(deal.minted) as usize;
+	if recipients.len() > remaining { anyhow::bail!("{} recipients but only {} coupons left", recipients.len(), remaining); }
+	let mint_rent = client.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)?;
+
+	// Each coupon is a fresh 0-decimal mint with one token in the recipient's ATA and no mint authority left
+	let coupon_ixs = |mint: &Pubkey, recipient: &Pubkey| -> Result<Vec<Instruction>> {
+		Ok(vec![
+			solana_sdk::system_instruction::create_account(&payer.pubkey(), mint, mint_rent, spl_token::state::Mint::LEN as u64, &spl_token::id()),
+			spl_token::instruction::initialize_mint2(&spl_token::id(), mint, &payer.pubkey(), None, 0)?,
+			spl_associated_token_account::instruction::create_associated_token_account_idempotent(&payer.pubkey(), recipient, mint, &spl_token::id()),
+			spl_token::instruction::mint_to(&spl_token::id(), mint, &get_associated_token_address(recipient, mint), &payer.pubkey(), &[], 1)?,
+			spl_token::instruction::set_authority(&spl_token::id(), mint, None, spl_token::instruction::AuthorityType::MintTokens, &payer.pubkey(), &[])?,
+		])
+	};
+	let batch_ixs = |batch: &[(Keypair, Pubkey)]| -> Result<Vec<Instruction>> {
+		let mut ixs = Vec::new();
+		let mut accounts = vec![
+			solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+			solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+			solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+		];
+		for (mint, recipient) in batch {
+			ixs.extend(coupon_ixs(&mint.pubkey(), recipient)?);
+			accounts.extend([
+				solana_sdk::instruction::AccountMeta::new_readonly(get_associated_token_address(recipient, &mint.pubkey()), false),
+				solana_sdk::instruction::AccountMeta::new_readonly(mint.pubkey(), false),
+				solana_sdk::instruction::AccountMeta::new(coupon_pda(program_id, &mint.pubkey()), false),
+			]);
+		}
+		let data = deal_platform::instruction::DealInstruction::AirdropCoupons { deal_id }.try_to_vec()?;
+		ixs.push(Instruction { program_id: *program_id, accounts, data });
+		Ok(ixs)
+	};
+	// Serialized size: signature count, one signature per signer (payer + mints), then the message
+	let fits = |batch: &[(Keypair, Pubkey)]| -> Result<bool> {
+		let message = Message::new(&batch_ixs(batch)?, Some(&payer.pubkey()));
+		Ok(1 + 64 * (batch.len() + 1) + message.serialize().len() <= PACKET_DATA_SIZE)
+	};
+	let send_batch = |batch: &[(Keypair, Pubkey)]| -> Result<()> {
+		let mut signers: Vec<&Keypair> = vec![payer];
+		signers.extend(batch.iter().map(|(mint, _)| mint));
+		let tx = Transaction::new_signed_with_payer(&batch_ixs(batch)?, Some(&payer.pubkey()), &signers, client.get_latest_blockhash()?);
+		let sig = client.send_and_confirm_transaction(&tx)?;
+		println!("Airdropped {} coupons: {}", batch.len(), sig);
+		Ok(())
+	};
+
+	let mut batch: Vec<(Keypair, Pubkey)> = Vec::new();
+	for recipient in recipients {
+		batch.push((Keypair::new(), recipient));
+		if batch.len() > MAX_AIRDROPS_PER_TX || !fits(&batch)? {
+			let next = batch.pop().unwrap();
+			if batch.is_empty() { anyhow::bail!("a single airdrop does not fit in a transaction"); }
+			send_batch(&batch)?;
+			batch = vec![next];
+		}
+	}
+	if !batch.is_empty() { send_batch(&batch)?; }
+	Ok(())
+}
+
+// Accounts of UpdateReview and DeleteReview
+fn review_accounts(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, deal_id: u64) -> Vec<solana_sdk::instruction::AccountMeta> {
+	let deal_pda = deal_pda(program_id, merchant, deal_id);
+	vec![
+		solana_sdk::instruction::AccountMeta::new(*user, true),
+		solana_sdk::instruction::AccountMeta::new(merchant_pda(program_id, merchant), false),
+		solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+		solana_sdk::instruction::AccountMeta::new(review_pda(program_id, &deal_pda, user), false),
+	]
+}
+
+fn reply_pda(program_id: &Pubkey, review: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REPLY, review.as_ref()], program_id).0
+}
+
+fn config_pda(program_id: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::CONFIG], program_id).0
+}
+
+fn claim_pda(program_id: &Pubkey, claim_hash: &[u8; 32]) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::CLAIM, claim_hash], program_id).0
+}
+
+fn create_claim(client: &RpcClient, program_id: &Pubkey, payer: &Keypair, deal_id: u64, expires_at: i64) -> Result<()> {
+	let ephemeral = Keypair::new();
+	let claim_hash = solana_sdk::hash::hash(ephemeral.pubkey().as_ref()).to_bytes();
+	let claim = claim_pda(program_id, &claim_hash);
+	let mint = Keypair::new();
+	let mint_rent = client.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)?;
+	let merchant_token = get_associated_token_address(&payer.pubkey(), &mint.pubkey());
+	let claim_token = get_associated_token_address(&claim, &mint.pubkey());
+	let data = deal_platform::instruction::DealInstruction::CreateClaim { deal_id, claim_hash, expires_at }.try_to_vec()?;
+	let ixs = [
+		solana_sdk::system_instruction::create_account(&payer.pubkey(), &mint.pubkey(), mint_rent, spl_token::state::Mint::LEN as u64, &spl_token::id()),
+		spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer.pubkey(), None, 0)?,
+		spl_associated_token_account::instruction::create_associated_token_account_idempotent(&payer.pubkey(), &payer.pubkey(), &mint.pubkey(), &spl_token::id()),
+		spl_token::instruction::mint_to(&spl_token::id(), &mint.pubkey(), &merchant_token, &payer.pubkey(), &[], 1)?,
+		spl_token::instruction::set_authority(&spl_token::id(), &mint.pubkey(), None, spl_token::instruction::AuthorityType::MintTokens, &payer.pubkey(), &[])?,
+		spl_associated_token_account::instruction::create_associated_token_account_idempotent(&payer.pubkey(), &claim, &mint.pubkey(), &spl_token::id()),
+		Instruction {
+			program_id: *program_id,
+			accounts: vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(deal_pda(program_id, &payer.pubkey(), deal_id), false),
+				solana_sdk::instruction::AccountMeta::new(claim, false),
+				solana_sdk::instruction::AccountMeta::new(merchant_token, false),
+				solana_sdk::instruction::AccountMeta::new(claim_token, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(mint.pubkey(), false),
+				solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+				solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				solana_sdk::instruction::AccountMeta::new(coupon_pda(program_id, &mint.pubkey()), false),
+			],
+			data,
+		},
+	];
+	let tx = Transaction::new_signed_with_payer(&ixs, Some(&payer.pubkey()), &[payer, &mint], client.get_latest_blockhash()?);
+	let sig = client.send_and_confirm_transaction(&tx)?;
+	println!("Claim account: {}", claim);
+	println!("Claim secret: {}", ephemeral.to_base58_string());
+	println!("Signature: {}", sig);
+	Ok(())
+}
+
+// The payer covers the fee and the recipient's token account; the ephemeral keypair only signs
+fn claim_coupon(client: &RpcClient, program_id: &Pubkey, payer: &Keypair, secret: &str, recipient: Option<String>) -> Result<()> {
+	let ephemeral = Keypair::from_base58_string(secret);
+	let claim_hash = solana_sdk::hash::hash(ephemeral.pubkey().as_ref()).to_bytes();
+	let claim_pda = claim_pda(program_id, &claim_hash);
+	let claim = deal_platform::state::ClaimLink::unpack(&client.get_account_data(&claim_pda)?)?;
+	let recipient = match recipient { Some(r) => Pubkey::from_str(&r)?, None => payer.pubkey() };
+	let destination = get_associated_token_address(&recipient, &claim.coupon_mint);
+	let data = deal_platform::instruction::DealInstruction::Claim { claim_hash }.try_to_vec()?;
+	let ixs = [
+		spl_associated_token_account::instruction::create_associated_token_account_idempotent(&payer.pubkey(), &recipient, &claim.coupon_mint, &spl_token::id()),
+		Instruction {
+			program_id: *program_id,
+			accounts: vec![
+				solana_sdk::instruction::AccountMeta::new_readonly(ephemeral.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(claim_pda, false),
+				solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&claim_pda, &claim.coupon_mint), false),
+				solana_sdk::instruction::AccountMeta::new(destination, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(recipient, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(claim.coupon_mint, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+				solana_sdk::instruction::AccountMeta::new(claim.merchant, false),
+			],
+			data,
+		},
+	];
+	let tx = Transaction::new_signed_with_payer(&ixs, Some(&payer.pubkey()), &[payer, &ephemeral], client.get_latest_blockhash()?);
+	let sig = client.send_and_confirm_transaction(&tx)?;
+	println!("Coupon {} sent to {}", claim.coupon_mint, recipient);
+	println!("Signature: {}", sig);
+	Ok(())
+}
+
 fn main() -> Result<()> {
 	let cli = Cli::parse();
 	let url = rpc_url(&cli.cluster);
 	let client = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
 	let program_id = Pubkey::from_str(&cli.program_id)?;
-	let payer = read_keypair_file(payer_path())?;
+	let payer = read_keypair_file(payer_path()).map_err(|e| anyhow::anyhow!("failed to read keypair: {}", e))?;
 
+	// Setup instructions sent in the same transaction ahead of `ix`
+	let mut setup: Vec<Instruction> = Vec::new();
 	let ix: Instruction = match cli.command {
 		Commands::RegisterMerchant { name, uri } => {
-			let merchant_pda = {
-				let seeds = [b"merchant", payer.pubkey().as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
-			};
+			let merchant_pda = merchant_pda(&program_id, &payer.pubkey());
 			let data = deal_platform::instruction::DealInstruction::RegisterMerchant { name, uri }.try_to_vec()?;
 			Instruction {
 				program_id,
@@ -73,85 +392,613 @@ fn main() -> Result<()> {
 				data,
 			}
 		}
-		Commands::CreateDeal { deal_id, title, description, discount, expiry, total_supply } => {
-			let merchant_pda = {
-				let seeds = [b"merchant", payer.pubkey().as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+		Commands::CreateDeal { title, description, discount, expiry, total_supply, image_uri, metadata_uri } => {
+			let merchant_pda = merchant_pda(&program_id, &payer.pubkey());
+			// The program assigns the id from the merchant's counter; derive the PDA it will create
+			let merchant_data = client.get_account_data(&merchant_pda)?;
+			let merchant = deal_platform::state::Merchant::unpack(&merchant_data)?;
+			// Legacy deals left out of the merchant's migration can occupy the next ids; once migrated, passing them skips past
+			let mut deal_id = merchant.next_deal_id;
+			let mut taken = Vec::new();
+			while let Ok(account) = client.get_account(&deal_pda(&program_id, &payer.pubkey(), deal_id)) {
+				if deal_platform::state::Deal::unpack(&account.data).is_err() {
+					anyhow::bail!("deal {} still uses a legacy layout; run `migrate-account --deal-id {}` first", deal_id, deal_id);
+				}
+				taken.push(solana_sdk::instruction::AccountMeta::new_readonly(deal_pda(&program_id, &payer.pubkey(), deal_id), false));
+				deal_id += 1;
+			}
+			println!("Deal id: {}", deal_id);
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::CreateDeal { title, description, discount: parse_discount(&discount)?, expiry, total_supply, image_uri, metadata_uri }.try_to_vec()?;
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(merchant_pda, false),
+				solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+			];
+			accounts.extend(taken);
+			Instruction { program_id, accounts, data }
+		}
+		Commands::MintCoupon { deal_id, merchant, coupon_mint, allowlist, gate_nft } => {
+			let merchant = match merchant { Some(m) => Pubkey::from_str(&m)?, None => payer.pubkey() };
+			let merchant_pda = merchant_pda(&program_id, &merchant);
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let deal_data = client.get_account_data(&deal_pda)?;
+			let deal = deal_platform::state::Deal::unpack(&deal_data)?;
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new_readonly(merchant_pda, false),
+				solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+			];
+			// Raffle winners prove their win with their entry PDA
+			if deal.raffle.is_some() {
+				accounts.push(solana_sdk::instruction::AccountMeta::new(entry_pda(&program_id, &deal_pda, &payer.pubkey()), false));
+			}
+			let allowlist_proof = match allowlist {
+				Some(path) if deal.allowlist_root != [0; 32] => Some(allowlist_proof(&path, &payer.pubkey())?),
+				_ => None,
+			};
+			// Capped allowances are counted in a per-user PDA
+			if allowlist_proof.as_ref().is_some_and(|p| p.allowance > 0) {
+				accounts.extend([
+					solana_sdk::instruction::AccountMeta::new(allowance_pda(&program_id, &deal_pda, &payer.pubkey()), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				]);
+			}
+			match deal.gate {
+				Some(DealGate::TokenBalance { mint, .. }) => {
+					accounts.push(solana_sdk::instruction::AccountMeta::new_readonly(get_associated_token_address(&payer.pubkey(), &mint), false));
+				}
+				Some(DealGate::Collection { .. }) => {
+					let Some(gate_nft) = gate_nft else { anyhow::bail!("--gate-nft is required for collection-gated deals") };
+					let gate_nft = Pubkey::from_str(&gate_nft)?;
+					accounts.extend([
+						solana_sdk::instruction::AccountMeta::new_readonly(get_associated_token_address(&payer.pubkey(), &gate_nft), false),
+						solana_sdk::instruction::AccountMeta::new_readonly(deal_platform::metaplex::metadata_pda(&gate_nft), false),
+					]);
+				}
+				None => {}
+			}
+			if let Some(coupon_mint) = coupon_mint {
+				// The coupon mint is recorded against the deal, and paid deals escrow the price in the deal vault
+				let coupon_mint = Pubkey::from_str(&coupon_mint)?;
+				let vault = vault_pda(&program_id, &deal_pda);
+				accounts.push(solana_sdk::instruction::AccountMeta::new(coupon_pda(&program_id, &coupon_mint), false));
+				if deal.is_paid() {
+					let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+					println!("Current price: {}", deal.mint_price_at(now));
+					accounts.push(solana_sdk::instruction::AccountMeta::new(vault, false));
+				}
+				accounts.push(solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false));
+				if let Some(price) = deal.token_price {
+					accounts.extend([
+						solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&payer.pubkey(), &price.mint), false),
+						solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&vault, &price.mint), false),
+						solana_sdk::instruction::AccountMeta::new_readonly(price.mint, false),
+						solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+					]);
+				}
+				let data = deal_platform::instruction::DealInstruction::VerifyAndCountMint { deal_id, mint: coupon_mint.to_bytes(), allowlist_proof }.try_to_vec()?;
+				Instruction { program_id, accounts, data }
+			} else if !deal.is_paid() {
+				// Counted without a mint, so the coupon can't be redeemed or reviewed
+				let data = deal_platform::instruction::DealInstruction::MintCouponNft { deal_id, allowlist_proof }.try_to_vec()?;
+				Instruction { program_id, accounts, data }
+			} else {
+				anyhow::bail!("--coupon-mint is required for paid deals")
+			}
+		}
+		Commands::RedeemCoupon { deal_id, merchant, mint } => {
+			let merchant = Pubkey::from_str(&merchant)?;
+			let mint_pk = Pubkey::from_str(&mint)?;
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let redeem_pda = redeem_pda(&program_id, &mint_pk);
+			let coupon_pda = coupon_pda(&program_id, &mint_pk);
+			let holder_token = get_associated_token_address(&payer.pubkey(), &mint_pk);
+			let escrowed = client
+				.get_account_data(&coupon_pda)
+				.ok()
+				.and_then(|data| deal_platform::state::Coupon::unpack(&data).ok())
+				.is_some_and(|coupon| coupon.state == deal_platform::state::CouponState::Escrowed);
+			let mut accounts = if escrowed {
+				vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(holder_token, false),
+					solana_sdk::instruction::AccountMeta::new(mint_pk, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+					solana_sdk::instruction::AccountMeta::new(redeem_pda, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(deal_pda, false),
+				]
+			} else {
+				vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(redeem_pda, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(holder_token, false),
+				]
+			};
+			accounts.push(solana_sdk::instruction::AccountMeta::new(coupon_pda, false));
+			let data = if escrowed {
+				// Burning releases the escrowed payment to the merchant
+				let deal_data = client.get_account_data(&deal_pda)?;
+				let deal = deal_platform::state::Deal::unpack(&deal_data)?;
+				let vault = vault_pda(&program_id, &deal_pda);
+				accounts.push(solana_sdk::instruction::AccountMeta::new(vault, false));
+				match deal.token_price {
+					Some(price) => accounts.extend([
+						solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&vault, &price.mint), false),
+						solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&merchant, &price.mint), false),
+						solana_sdk::instruction::AccountMeta::new_readonly(price.mint, false),
+						solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+					]),
+					None => accounts.push(solana_sdk::instruction::AccountMeta::new(merchant, false)),
+				}
+				deal_platform::instruction::DealInstruction::RedeemAndBurn { mint: mint_pk.to_bytes() }.try_to_vec()?
+			} else {
+				deal_platform::instruction::DealInstruction::RedeemCoupon { mint: mint_pk.to_bytes() }.try_to_vec()?
 			};
-			let deal_pda = {
-				let seeds = [b"deal", payer.pubkey().as_ref(), &deal_id.to_le_bytes()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+			Instruction { program_id, accounts, data }
+		}
+		Commands::ClaimRefund { deal_id, merchant, mint, group } => {
+			let merchant = Pubkey::from_str(&merchant)?;
+			let mint_pk = Pubkey::from_str(&mint)?;
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let vault = vault_pda(&program_id, &deal_pda);
+			let deal_data = client.get_account_data(&deal_pda)?;
+			let deal = deal_platform::state::Deal::unpack(&deal_data)?;
+			let data = if group {
+				deal_platform::instruction::DealInstruction::RefundGroupDeal { mint: mint_pk.to_bytes() }.try_to_vec()?
+			} else {
+				deal_platform::instruction::DealInstruction::ClaimRefund { mint: mint_pk.to_bytes() }.try_to_vec()?
 			};
-			let data = deal_platform::instruction::DealInstruction::CreateDeal { deal_id, title, description, discount_percent: discount, expiry, total_supply }.try_to_vec()?;
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&payer.pubkey(), &mint_pk), false),
+				solana_sdk::instruction::AccountMeta::new(mint_pk, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+				solana_sdk::instruction::AccountMeta::new_readonly(deal_pda, false),
+				solana_sdk::instruction::AccountMeta::new(coupon_pda(&program_id, &mint_pk), false),
+				solana_sdk::instruction::AccountMeta::new(vault, false),
+			];
+			if let Some(price) = deal.token_price {
+				accounts.extend([
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&vault, &price.mint), false),
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&payer.pubkey(), &price.mint), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(price.mint, false),
+				]);
+			}
+			Instruction { program_id, accounts, data }
+		}
+		Commands::SetGroupBuy { deal_id, min_participants, funding_deadline } => {
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetGroupBuy { deal_id, min_participants, funding_deadline }.try_to_vec()?;
 			Instruction {
 				program_id,
 				accounts: vec![
 					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
-					solana_sdk::instruction::AccountMeta::new(merchant_pda, false),
 					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::SetRaffle { deal_id, winners, entry_deadline } => {
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetRaffle { deal_id, winners, entry_deadline }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::EnterRaffle { deal_id, merchant } => {
+			let merchant = Pubkey::from_str(&merchant)?;
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let data = deal_platform::instruction::DealInstruction::EnterRaffle { deal_id }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new_readonly(merchant_pda(&program_id, &merchant), false),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new(entry_pda(&program_id, &deal_pda, &payer.pubkey()), false),
 					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
 				],
 				data,
 			}
 		}
-		Commands::MintCoupon { deal_id } => {
-			let merchant_pda = {
-				let seeds = [b"merchant", payer.pubkey().as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
-			};
-			let deal_pda = {
-				let seeds = [b"deal", payer.pubkey().as_ref(), &deal_id.to_le_bytes()];
-				Pubkey::find_program_address(&seeds, &program_id).0
-			};
-			let data = deal_platform::instruction::DealInstruction::MintCouponNft { deal_id }.try_to_vec()?;
+		Commands::DrawRaffle { deal_id, merchant } => {
+			// Any wallet can draw once entries close
+			let merchant = match merchant { Some(m) => Pubkey::from_str(&m)?, None => payer.pubkey() };
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let data = deal_platform::instruction::DealInstruction::DrawRaffle { deal_id }.try_to_vec()?;
 			Instruction {
 				program_id,
 				accounts: vec![
 					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
-					solana_sdk::instruction::AccountMeta::new_readonly(merchant_pda, false),
 					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::sysvar::slot_hashes::id(), false),
 				],
 				data,
 			}
 		}
-		Commands::RedeemCoupon { mint } => {
-			let mint_pk = Pubkey::from_str(&mint)?;
-			let redeem_pda = {
-				let seeds = [b"redeem", mint_pk.as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+		Commands::AllowlistBuild { csv, out } => {
+			let rows = read_allowlist_csv(&csv)?;
+			if rows.is_empty() { anyhow::bail!("{} has no allowlist rows", csv); }
+			let leaves: Vec<[u8; 32]> = rows.iter().map(|(user, allowance)| deal_platform::merkle::leaf(user, *allowance)).collect();
+			let levels = deal_platform::merkle::build(&leaves);
+			let root = Hash::new_from_array(deal_platform::merkle::root(&levels));
+			let mut entries = serde_json::Map::new();
+			for (i, (user, allowance)) in rows.iter().enumerate() {
+				let proof: Vec<String> = deal_platform::merkle::proof(&levels, i).into_iter().map(|h| Hash::new_from_array(h).to_string()).collect();
+				entries.insert(user.to_string(), serde_json::json!({ "allowance": allowance, "proof": proof }));
+			}
+			std::fs::write(&out, serde_json::to_string_pretty(&serde_json::json!({ "root": root.to_string(), "entries": entries }))?)?;
+			println!("Root: {} ({} wallets, proofs in {})", root, rows.len(), out);
+			return Ok(());
+		}
+		Commands::SetGate { deal_id, token_mint, min_amount, collection } => {
+			let gate = match (token_mint, collection) {
+				(Some(mint), None) => Some(DealGate::TokenBalance { mint: Pubkey::from_str(&mint)?, min_amount }),
+				(None, Some(collection)) => Some(DealGate::Collection { collection: Pubkey::from_str(&collection)? }),
+				(None, None) => None,
+				(Some(_), Some(_)) => anyhow::bail!("pass either --token-mint or --collection"),
 			};
-			let data = deal_platform::instruction::DealInstruction::RedeemCoupon { mint: mint_pk.to_bytes() }.try_to_vec()?;
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetGate { deal_id, gate }.try_to_vec()?;
 			Instruction {
 				program_id,
 				accounts: vec![
 					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
-					solana_sdk::instruction::AccountMeta::new(redeem_pda, false),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::AirdropCoupons { deal_id, recipients } => {
+			return airdrop_coupons(&client, &program_id, &payer, deal_id, &recipients);
+		}
+		Commands::CreateClaim { deal_id, expires_at } => {
+			return create_claim(&client, &program_id, &payer, deal_id, expires_at);
+		}
+		Commands::Claim { secret, recipient } => {
+			return claim_coupon(&client, &program_id, &payer, &secret, recipient);
+		}
+		Commands::ReclaimExpired { claim } => {
+			let claim_pda = Pubkey::from_str(&claim)?;
+			let claim = deal_platform::state::ClaimLink::unpack(&client.get_account_data(&claim_pda)?)?;
+			let data = deal_platform::instruction::DealInstruction::ReclaimExpired { claim_hash: claim.claim_hash }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(claim_pda, false),
+					solana_sdk::instruction::AccountMeta::new(claim.deal, false),
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&claim_pda, &claim.coupon_mint), false),
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&payer.pubkey(), &claim.coupon_mint), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(claim.coupon_mint, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+					solana_sdk::instruction::AccountMeta::new(coupon_pda(&program_id, &claim.coupon_mint), false),
+				],
+				data,
+			}
+		}
+		Commands::SetAllowlist { deal_id, root } => {
+			let root = root.map(|r| Hash::from_str(&r)).transpose()?.map(|h| h.to_bytes()).unwrap_or_default();
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetAllowlist { deal_id, root }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::FinalizeGroupDeal { deal_id, coupon_mints } => {
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let vault = vault_pda(&program_id, &deal_pda);
+			let deal_data = client.get_account_data(&deal_pda)?;
+			let deal = deal_platform::state::Deal::unpack(&deal_data)?;
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				solana_sdk::instruction::AccountMeta::new(vault, false),
+			];
+			if let Some(price) = deal.token_price {
+				accounts.extend([
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&vault, &price.mint), false),
+					solana_sdk::instruction::AccountMeta::new(get_associated_token_address(&payer.pubkey(), &price.mint), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(price.mint, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(spl_token::id(), false),
+				]);
+			}
+			// Coupons left out can be released by running this again or when they are redeemed
+			for mint in coupon_mints {
+				accounts.push(solana_sdk::instruction::AccountMeta::new(coupon_pda(&program_id, &Pubkey::from_str(&mint)?), false));
+			}
+			let data = deal_platform::instruction::DealInstruction::FinalizeGroupDeal { deal_id }.try_to_vec()?;
+			Instruction { program_id, accounts, data }
+		}
+		Commands::AddReview { deal_id, merchant, mint, rating, comment } => {
+			let merchant = match merchant { Some(m) => Pubkey::from_str(&m)?, None => payer.pubkey() };
+			let mint = Pubkey::from_str(&mint)?;
+			let merchant_pda = merchant_pda(&program_id, &merchant);
+			let deal_pda = deal_pda(&program_id, &merchant, deal_id);
+			let review_pda = review_pda(&program_id, &deal_pda, &payer.pubkey());
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(merchant_pda, false),
+				solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				solana_sdk::instruction::AccountMeta::new(review_pda, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+			];
+			// Prove the purchase with the redemption log, or else the held coupon, then the coupon's record
+			let redeem_pda = redeem_pda(&program_id, &mint);
+			let proof = if client.get_account(&redeem_pda).is_ok() { redeem_pda } else { get_associated_token_address(&payer.pubkey(), &mint) };
+			accounts.extend([
+				solana_sdk::instruction::AccountMeta::new_readonly(proof, false),
+				solana_sdk::instruction::AccountMeta::new_readonly(coupon_pda(&program_id, &mint), false),
+			]);
+			let data = deal_platform::instruction::DealInstruction::AddReview { deal_id, rating, comment }.try_to_vec()?;
+			Instruction { program_id, accounts, data }
+		}
+		Commands::UpdateReview { deal_id, merchant, rating, comment } => {
+			let merchant = Pubkey::from_str(&merchant)?;
+			let data = deal_platform::instruction::DealInstruction::UpdateReview { deal_id, rating, comment }.try_to_vec()?;
+			Instruction { program_id, accounts: review_accounts(&program_id, &payer.pubkey(), &merchant, deal_id), data }
+		}
+		Commands::DeleteReview { deal_id, merchant } => {
+			let merchant = Pubkey::from_str(&merchant)?;
+			let data = deal_platform::instruction::DealInstruction::DeleteReview { deal_id }.try_to_vec()?;
+			let mut accounts = review_accounts(&program_id, &payer.pubkey(), &merchant, deal_id);
+			// The merchant's reply is closed along with the review and its rent goes back to the merchant
+			accounts.push(solana_sdk::instruction::AccountMeta::new(reply_pda(&program_id, &accounts[3].pubkey), false));
+			accounts.push(solana_sdk::instruction::AccountMeta::new(merchant, false));
+			Instruction { program_id, accounts, data }
+		}
+		Commands::ReplyToReview { deal_id, user, comment } => {
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let review_pda = review_pda(&program_id, &deal_pda, &Pubkey::from_str(&user)?);
+			let data = deal_platform::instruction::DealInstruction::ReplyToReview { comment }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new_readonly(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new(review_pda, false),
+					solana_sdk::instruction::AccountMeta::new(reply_pda(&program_id, &review_pda), false),
 					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
 				],
 				data,
 			}
 		}
-		Commands::AddReview { deal_id, rating, comment } => {
-			let merchant_pda = {
-				let seeds = [b"merchant", payer.pubkey().as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+		Commands::ListReviews { deal_id, merchant, include_hidden } => {
+			let deal_pda = deal_pda(&program_id, &Pubkey::from_str(&merchant)?, deal_id);
+			let config = solana_client::rpc_config::RpcProgramAccountsConfig {
+				filters: Some(vec![
+					solana_client::rpc_filter::RpcFilterType::DataSize(deal_platform::state::Review::space() as u64),
+					solana_client::rpc_filter::RpcFilterType::Memcmp(solana_client::rpc_filter::Memcmp::new_base58_encoded(deal_platform::state::HEADER_LEN + 32, deal_pda.as_ref())),
+				]),
+				account_config: solana_client::rpc_config::RpcAccountInfoConfig {
+					encoding: Some(solana_account_decoder::UiAccountEncoding::Base64),
+					..Default::default()
+				},
+				..Default::default()
 			};
-			let deal_pda = {
-				let seeds = [b"deal", payer.pubkey().as_ref(), &deal_id.to_le_bytes()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+			for (key, account) in client.get_program_accounts_with_config(&program_id, config)? {
+				let review = deal_platform::state::Review::unpack(&account.data)?;
+				if review.hidden && !include_hidden { continue; }
+				let mut line = format!("{} {} {}/5 \"{}\"", key, review.user, review.rating, review.comment);
+				if review.verified_purchase { line.push_str(" [verified]"); }
+				if review.edited_at > 0 { line.push_str(" [edited]"); }
+				if review.flags > 0 { line.push_str(&format!(" [{} flags]", review.flags)); }
+				if review.hidden { line.push_str(" [hidden]"); }
+				println!("{}", line);
+				if let Ok(data) = client.get_account_data(&reply_pda(&program_id, &key)) {
+					let reply = deal_platform::state::ReviewReply::unpack(&data)?;
+					println!("  merchant: \"{}\"", reply.comment);
+				}
+			}
+			return Ok(());
+		}
+		Commands::Events { signature } => {
+			let signature = solana_sdk::signature::Signature::from_str(&signature)?;
+			let tx = client.get_transaction(&signature, solana_transaction_status::UiTransactionEncoding::Json)?;
+			let logs: Vec<String> = tx.transaction.meta.and_then(|meta| meta.log_messages.into()).unwrap_or_default();
+			for event in deal_platform::event::parse_logs(&program_id, &logs) {
+				println!("{:?}", event);
+			}
+			return Ok(());
+		}
+		Commands::FlagReview { deal_id, merchant, user } => {
+			let deal_pda = deal_pda(&program_id, &Pubkey::from_str(&merchant)?, deal_id);
+			let review_pda = review_pda(&program_id, &deal_pda, &Pubkey::from_str(&user)?);
+			let flag_pda = Pubkey::find_program_address(&[seeds::FLAG, review_pda.as_ref(), payer.pubkey().as_ref()], &program_id).0;
+			let data = deal_platform::instruction::DealInstruction::FlagReview.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(review_pda, false),
+					solana_sdk::instruction::AccountMeta::new(flag_pda, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				],
+				data,
+			}
+		}
+		Commands::HideReview { deal_id, merchant, user, unhide } => {
+			let deal_pda = deal_pda(&program_id, &Pubkey::from_str(&merchant)?, deal_id);
+			let review_pda = review_pda(&program_id, &deal_pda, &Pubkey::from_str(&user)?);
+			let data = deal_platform::instruction::DealInstruction::HideReview { hidden: !unhide }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new_readonly(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new_readonly(config_pda(&program_id), false),
+					solana_sdk::instruction::AccountMeta::new(review_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::SetAdmin { admin } => {
+			let data = deal_platform::instruction::DealInstruction::SetAdmin { admin: Pubkey::from_str(&admin)?.to_bytes() }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(config_pda(&program_id), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(program_id, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(Pubkey::find_program_address(&[program_id.as_ref()], &solana_sdk::bpf_loader_upgradeable::id()).0, false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				],
+				data,
+			}
+		}
+		Commands::SetDealStatus { deal_id, status } => {
+			let status = match status.as_str() {
+				"active" => deal_platform::state::DealStatus::Active,
+				"paused" => deal_platform::state::DealStatus::Paused,
+				"cancelled" => deal_platform::state::DealStatus::Cancelled,
+				other => anyhow::bail!("unknown deal status: {}", other),
 			};
-			let review_pda = {
-				let seeds = [b"review", deal_pda.as_ref(), payer.pubkey().as_ref()];
-				Pubkey::find_program_address(&seeds, &program_id).0
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetDealStatus { deal_id, status }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::RecountDeals => {
+			let merchant_pda = merchant_pda(&program_id, &payer.pubkey());
+			let merchant_data = client.get_account_data(&merchant_pda)?;
+			let merchant = deal_platform::state::Merchant::unpack(&merchant_data)?;
+			// Pass every deal PDA that still exists for ids handed out so far
+			let candidates: Vec<Pubkey> = (0..merchant.next_deal_id).map(|id| deal_pda(&program_id, &payer.pubkey(), id)).collect();
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(merchant_pda, false),
+			];
+			for chunk in candidates.chunks(100) {
+				let found = client.get_multiple_accounts(chunk)?;
+				accounts.extend(
+					chunk.iter().zip(found).filter(|(_, a)| a.as_ref().is_some_and(|a| a.owner == program_id)).map(|(k, _)| solana_sdk::instruction::AccountMeta::new_readonly(*k, false)),
+				);
+			}
+			// All deals must fit in one transaction since the recount replaces the stored total
+			if accounts.len() > 32 { anyhow::bail!("{} deals do not fit in a single RecountDeals transaction", accounts.len() - 2); }
+			let data = deal_platform::instruction::DealInstruction::RecountDeals.try_to_vec()?;
+			Instruction { program_id, accounts, data }
+		}
+		Commands::MigrateAccount { deal_id, account } => {
+			let mut accounts = vec![
+				solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+				solana_sdk::instruction::AccountMeta::new(Pubkey::default(), false),
+				solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+			];
+			if let Some(account) = account {
+				accounts[1].pubkey = Pubkey::from_str(&account)?;
+			} else if let Some(deal_id) = deal_id {
+				accounts[1].pubkey = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			} else {
+				accounts[1].pubkey = merchant_pda(&program_id, &payer.pubkey());
+				// Legacy deal ids were chosen freely, so find every deal of this merchant in either layout
+				let layouts = [
+					(deal_platform::state::DealV0::space(), 8),
+					(deal_platform::state::Deal::space(), deal_platform::state::HEADER_LEN + 8),
+				];
+				for (size, merchant_offset) in layouts {
+					let config = solana_client::rpc_config::RpcProgramAccountsConfig {
+						filters: Some(vec![
+							solana_client::rpc_filter::RpcFilterType::DataSize(size as u64),
+							solana_client::rpc_filter::RpcFilterType::Memcmp(solana_client::rpc_filter::Memcmp::new_base58_encoded(merchant_offset, payer.pubkey().as_ref())),
+						]),
+						account_config: solana_client::rpc_config::RpcAccountInfoConfig {
+							encoding: Some(solana_account_decoder::UiAccountEncoding::Base64),
+							..Default::default()
+						},
+						..Default::default()
+					};
+					accounts.extend(
+						client.get_program_accounts_with_config(&program_id, config)?.into_iter().map(|(k, _)| solana_sdk::instruction::AccountMeta::new_readonly(k, false)),
+					);
+				}
+				if accounts.len() > 32 { anyhow::bail!("{} deals do not fit in a single MigrateAccount transaction", accounts.len() - 3); }
+			}
+			let data = deal_platform::instruction::DealInstruction::MigrateAccount.try_to_vec()?;
+			Instruction { program_id, accounts, data }
+		}
+		Commands::SetRedemptionSchedule { deal_id, weekdays, start_minute, end_minute, utc_offset_minutes } => {
+			// Omitting the window clears the schedule
+			let schedule = match (start_minute, end_minute) {
+				(Some(start_minute), Some(end_minute)) => Some(RedemptionSchedule { weekdays, start_minute, end_minute, utc_offset_minutes }),
+				(None, None) => None,
+				_ => anyhow::bail!("pass both --start-minute and --end-minute, or neither to clear the schedule"),
 			};
-			let data = deal_platform::instruction::DealInstruction::AddReview { deal_id, rating, comment }.try_to_vec()?;
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetRedemptionSchedule { deal_id, schedule }.try_to_vec()?;
 			Instruction {
 				program_id,
 				accounts: vec![
 					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
-					solana_sdk::instruction::AccountMeta::new_readonly(merchant_pda, false),
-					solana_sdk::instruction::AccountMeta::new_readonly(deal_pda, false),
-					solana_sdk::instruction::AccountMeta::new(review_pda, false),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+				],
+				data,
+			}
+		}
+		Commands::SetPriceCurve { deal_id, start_price, end_price, start_ts, end_ts, step_seconds } => {
+			let curve = start_price.map(|start_price| PriceCurve { start_price, end_price, start_ts, end_ts, step_seconds });
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetPriceCurve { deal_id, curve }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new(vault_pda(&program_id, &deal_pda), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				],
+				data,
+			}
+		}
+		Commands::SetMintPrice { deal_id, lamports } => {
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let data = deal_platform::instruction::DealInstruction::SetMintPrice { deal_id, mint_price_lamports: lamports }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new(vault_pda(&program_id, &deal_pda), false),
+					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+				],
+				data,
+			}
+		}
+		Commands::SetTokenMintPrice { deal_id, payment_mint, amount } => {
+			let payment_mint = payment_mint.map(|m| Pubkey::from_str(&m)).transpose()?;
+			let deal_pda = deal_pda(&program_id, &payer.pubkey(), deal_id);
+			let vault = vault_pda(&program_id, &deal_pda);
+			// The vault's associated token account receives escrowed payments
+			if let Some(mint) = payment_mint {
+				setup.push(spl_associated_token_account::instruction::create_associated_token_account_idempotent(&payer.pubkey(), &vault, &mint, &spl_token::id()));
+			}
+			let data = deal_platform::instruction::DealInstruction::SetTokenMintPrice { deal_id, payment_mint: payment_mint.map(|m| m.to_bytes()), amount }.try_to_vec()?;
+			Instruction {
+				program_id,
+				accounts: vec![
+					solana_sdk::instruction::AccountMeta::new(payer.pubkey(), true),
+					solana_sdk::instruction::AccountMeta::new(deal_pda, false),
+					solana_sdk::instruction::AccountMeta::new(vault, false),
 					solana_sdk::instruction::AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
 				],
 				data,
@@ -160,7 +1007,8 @@ fn main() -> Result<()> {
 	};
 
 	let recent_blockhash = client.get_latest_blockhash()?;
-	let mut tx = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+	setup.push(ix);
+	let mut tx = Transaction::new_with_payer(&setup, Some(&payer.pubkey()));
 	tx.sign(&[&payer], recent_blockhash);
 	let sig = client.send_and_confirm_transaction(&tx)?;
 	println!("Signature: {}", sig);
@@ -19,6 +19,58 @@ pub enum DealError {
 	AlreadyRedeemed,
 	#[error("Invalid input")]
 	InvalidInput,
+	#[error("Deal is not active")]
+	DealNotActive,
+	#[error("Deal has been cancelled")]
+	DealCancelled,
+	#[error("Invalid discount")]
+	InvalidDiscount,
+	#[error("Outside the deal's redemption window")]
+	OutsideRedemptionWindow,
+	#[error("Deal already has minted coupons")]
+	DealHasMints,
+	#[error("Refund not available until the deal expires or is cancelled")]
+	RefundNotAvailable,
+	#[error("Coupon payment already released or refunded")]
+	PaymentSettled,
+	#[error("Group deal has not reached its minimum participants")]
+	ThresholdNotMet,
+	#[error("Group deal funding deadline has passed")]
+	FundingClosed,
+	#[error("Group deal is not finalized yet")]
+	GroupDealNotFinalized,
+	#[error("Raffle entries are closed")]
+	RaffleClosed,
+	#[error("Raffle cannot be drawn before its entry deadline")]
+	RaffleStillOpen,
+	#[error("Raffle has not been drawn yet")]
+	RaffleNotDrawn,
+	#[error("Raffle entry did not win or already minted")]
+	NotRaffleWinner,
+	#[error("Wallet is not on the deal allowlist")]
+	NotAllowlisted,
+	#[error("Allowlist allowance used up")]
+	AllowanceExceeded,
+	#[error("Wallet does not hold the tokens required by the deal gate")]
+	GateNotSatisfied,
+	#[error("Claim link has expired")]
+	ClaimExpired,
+	#[error("Claim link has not expired yet")]
+	ClaimNotExpired,
+	#[error("Reviewer has not redeemed or held a coupon of this deal")]
+	ReviewerNotVerified,
+	#[error("Account is not owned by the program")]
+	IncorrectOwner,
+	#[error("Account holds a different type")]
+	AccountKindMismatch,
+	#[error("Account layout is outdated")]
+	AccountVersionMismatch,
+	#[error("Coupons with an escrowed payment are redeemed with RedeemAndBurn")]
+	CouponMustBeBurned,
+	#[error("Hidden reviews can't be deleted")]
+	ReviewHidden,
+	#[error("Coupon was not counted for this deal")]
+	CouponNotOfDeal,
 }
 
 impl From<DealError> for ProgramError {
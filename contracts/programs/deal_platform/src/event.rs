@@ -0,0 +1,100 @@
+extern crate alloc;
+
+use alloc::string::String;
+use borsh::BorshSerialize;
+use borsh_derive::{BorshDeserialize as BorshDeserializeDerive, BorshSerialize as BorshSerializeDerive};
+use solana_program::{entrypoint::ProgramResult, log::sol_log_data, pubkey::Pubkey};
+
+use crate::state::DealStatus;
+
+// Decoding is for indexers and the CLI, so it stays out of the on-chain build
+#[cfg(not(target_os = "solana"))]
+use {
+	alloc::vec::Vec,
+	base64::{engine::general_purpose::STANDARD, Engine},
+	borsh::BorshDeserialize,
+};
+
+// Logged as a single borsh-encoded `sol_log_data` field, which shows up as a `Program data: <base64>` log line.
+// Variants are only ever appended so existing indexers keep decoding older transactions.
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub enum DealEvent {
+	// 0
+	MerchantRegistered { merchant: Pubkey, name: String, uri: String },
+	// 1
+	DealCreated { merchant: Pubkey, deal: Pubkey, deal_id: u64, title: String, total_supply: u32, expiry: i64 },
+	// 2 - once per coupon counted against the deal's supply; `mint` is None for MintCouponNft, which doesn't take one.
+	// `paid` is in lamports, or in base units of `payment_mint`.
+	CouponMinted { deal: Pubkey, mint: Option<Pubkey>, owner: Pubkey, paid: u64, payment_mint: Option<Pubkey> },
+	// 3
+	CouponRedeemed { deal: Pubkey, mint: Pubkey, user: Pubkey, burned: bool },
+	// 4
+	ReviewAdded { deal: Pubkey, review: Pubkey, user: Pubkey, rating: u8 },
+	// 5
+	Listed { listing: Pubkey, nft_mint: Pubkey, seller: Pubkey, price: u64, payment_mint: Option<Pubkey> },
+	// 6
+	Sold { listing: Pubkey, nft_mint: Pubkey, seller: Pubkey, buyer: Pubkey, price: u64, payment_mint: Option<Pubkey> },
+	// 7 - a coupon parked by CreateClaim, whose CouponMinted named the claim PDA as owner, reaches `owner`
+	Claimed { deal: Pubkey, mint: Pubkey, owner: Pubkey },
+	// 8
+	ReviewUpdated { deal: Pubkey, review: Pubkey, user: Pubkey, rating: u8 },
+	// 9 - the merchant's reply, if any, is closed with the review
+	ReviewDeleted { deal: Pubkey, review: Pubkey, user: Pubkey },
+	// 10 - by ClaimRefund or RefundGroupDeal; the coupon was burned. `amount` is in lamports, or in base units of
+	// `payment_mint`.
+	Refunded { deal: Pubkey, mint: Pubkey, holder: Pubkey, amount: u64, payment_mint: Option<Pubkey> },
+	// 11 - an expired claim link's coupon went back to the merchant and no longer counts as minted
+	CouponReclaimed { deal: Pubkey, mint: Pubkey },
+	// 12 - once per FinalizeGroupDeal call; `released` is what this call paid the merchant from the vault
+	GroupFinalized { deal: Pubkey, participants: u32, released: u64, payment_mint: Option<Pubkey> },
+	// 13
+	DealStatusChanged { deal: Pubkey, status: DealStatus },
+	// 14
+	ReviewReplied { review: Pubkey, reply: Pubkey, merchant: Pubkey },
+	// 15 - `flags` is the review's count after this flag
+	ReviewFlagged { review: Pubkey, flagger: Pubkey, flags: u32 },
+	// 16 - the admin hid the review, or restored it when `hidden` is false
+	ReviewHidden { review: Pubkey, hidden: bool },
+}
+
+impl DealEvent {
+	pub fn emit(&self) -> ProgramResult {
+		sol_log_data(&[&self.try_to_vec()?]);
+		Ok(())
+	}
+
+	// Decodes the base64 payload of a `Program data:` line
+	#[cfg(not(target_os = "solana"))]
+	pub fn decode(data: &str) -> Option<Self> {
+		let bytes = STANDARD.decode(data).ok()?;
+		Self::try_from_slice(&bytes).ok()
+	}
+}
+
+// Events the program at `program_id` emitted, in order, from a transaction's log messages. Data lines logged while
+// another program runs, including programs it invokes, are skipped, as are payloads that don't decode.
+#[cfg(not(target_os = "solana"))]
+pub fn parse_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<DealEvent> {
+	let program_id = alloc::format!("{}", program_id);
+	let mut stack: Vec<&str> = Vec::new();
+	let mut events = Vec::new();
+	for line in logs {
+		let line = line.as_ref();
+		if let Some(data) = line.strip_prefix("Program data: ") {
+			if stack.last() == Some(&program_id.as_str()) {
+				events.extend(DealEvent::decode(data));
+			}
+			continue;
+		}
+		let Some(rest) = line.strip_prefix("Program ") else { continue };
+		let mut words = rest.split(' ');
+		match (words.next(), words.next()) {
+			(Some(id), Some("invoke")) => stack.push(id),
+			(Some(_), Some("success" | "failed:")) => {
+				stack.pop();
+			}
+			_ => {}
+		}
+	}
+	events
+}
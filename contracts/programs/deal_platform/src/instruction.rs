@@ -1,38 +1,95 @@
 extern crate alloc;
 
 use alloc::string::String;
-use borsh::{BorshDeserialize, BorshSerialize};
 use borsh_derive::{BorshDeserialize as BorshDeserializeDerive, BorshSerialize as BorshSerializeDerive};
 
+use crate::{
+	merkle::AllowlistProof,
+	state::{DealGate, DealStatus, DiscountKind, PriceCurve, RedemptionSchedule},
+};
+
 #[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
 pub enum DealInstruction {
 	// 0
 	RegisterMerchant { name: String, uri: String },
-	// 1
+	// 1 - deal id is taken from Merchant.next_deal_id, skipping occupied (migrated) deal accounts passed after the system program
 	CreateDeal {
-		deal_id: u64,
 		title: String,
 		description: String,
-		discount_percent: u8,
+		discount: DiscountKind,
 		expiry: i64,
 		total_supply: u32,
 		image_uri: String,
 		metadata_uri: String,
 	},
-	// 2
-	MintCouponNft { deal_id: u64 },
-	// 3
+	// 2 - Counts a coupon without a mint, so it can't be redeemed or reviewed; VerifyAndCountMint records the mint
+	MintCouponNft { deal_id: u64, allowlist_proof: Option<AllowlistProof> },
+	// 3 - Signed by the coupon's holder, whose mint must have been counted for the deal; coupons with an escrowed
+	// payment are redeemed with RedeemAndBurn
 	RedeemCoupon { mint: [u8; 32] },
 	// 4
 	AddReview { deal_id: u64, rating: u8, comment: String },
-	// 5
-	VerifyAndCountMint { deal_id: u64, mint: [u8; 32] },
+	// 5 - Counts a coupon and records its mint against the deal
+	VerifyAndCountMint { deal_id: u64, mint: [u8; 32], allowlist_proof: Option<AllowlistProof> },
 	// 6
 	RedeemAndBurn { mint: [u8; 32] },
 	// 7 (new, placed last to avoid shifting earlier discriminants)
 	SetCollectionMint { collection_mint: [u8; 32] },
-	// 8 - List NFT for sale
-	ListNft { price: u64 },
+	// 8 - List NFT for sale, priced in lamports or in base units of payment_mint
+	ListNft { price: u64, payment_mint: Option<[u8; 32]> },
 	// 9 - Buy NFT from listing
 	BuyNft,
+	// 10 - Pause, resume or cancel a deal (merchant only)
+	SetDealStatus { deal_id: u64, status: DealStatus },
+	// 11 - Recompute Merchant.total_deals from the deal PDAs passed as remaining accounts
+	RecountDeals,
+	// 12 - Restrict redemptions to a weekly time window; None removes the restriction
+	SetRedemptionSchedule { deal_id: u64, schedule: Option<RedemptionSchedule> },
+	// 13 - Price charged per coupon mint; only while nothing has been minted yet
+	SetMintPrice { deal_id: u64, mint_price_lamports: u64 },
+	// 14 - SPL token price per coupon mint (None clears it); only while nothing has been minted yet
+	SetTokenMintPrice { deal_id: u64, payment_mint: Option<[u8; 32]>, amount: u64 },
+	// 15 - Burn an unredeemed paid coupon after expiry or cancellation and get its payment back
+	ClaimRefund { mint: [u8; 32] },
+	// 16 - Make a deal a group buy (min_participants 0 turns it off); only while nothing has been minted yet
+	SetGroupBuy { deal_id: u64, min_participants: u32, funding_deadline: i64 },
+	// 17 - Mark a group deal as tipped and release the escrowed payments of the coupon PDAs passed as remaining accounts
+	FinalizeGroupDeal { deal_id: u64 },
+	// 18 - Burn a coupon of a group deal that missed its threshold and get its payment back
+	RefundGroupDeal { mint: [u8; 32] },
+	// 19 - Declining mint price in the deal's currency (None goes back to the fixed price); only before the first mint
+	SetPriceCurve { deal_id: u64, curve: Option<PriceCurve> },
+	// 20 - Turn a deal into a raffle with this many winners (0 turns it off); only before the first entry or mint
+	SetRaffle { deal_id: u64, winners: u32, entry_deadline: i64 },
+	// 21 - Register the signer's raffle entry PDA before the entry deadline
+	EnterRaffle { deal_id: u64 },
+	// 22 - After the entry deadline, record the most recent SlotHashes entry as the raffle seed; any wallet can draw
+	DrawRaffle { deal_id: u64 },
+	// 23 - Limit minting to a Merkle allowlist; an all-zero root removes the limit
+	SetAllowlist { deal_id: u64, root: [u8; 32] },
+	// 24 - Require holding a token balance or an NFT of a verified collection to mint; None removes it
+	SetGate { deal_id: u64, gate: Option<DealGate> },
+	// 25 - Count coupons the merchant minted straight to customers; remaining accounts are, per recipient, the coupon token
+	// account, its one-of-one mint and the coupon's record PDA
+	AirdropCoupons { deal_id: u64 },
+	// 26 - Lock one of the merchant's coupons under sha256(ephemeral pubkey) until expires_at
+	CreateClaim { deal_id: u64, claim_hash: [u8; 32], expires_at: i64 },
+	// 27 - Signed by the ephemeral keypair from the link; sends the coupon to any wallet
+	Claim { claim_hash: [u8; 32] },
+	// 28 - Merchant takes back a coupon whose link expired unclaimed; it no longer counts as minted
+	ReclaimExpired { claim_hash: [u8; 32] },
+	// 29 - Reviewer changes their rating and comment
+	UpdateReview { deal_id: u64, rating: u8, comment: String },
+	// 30 - Reviewer removes their review and gets the rent back; the merchant's reply is closed with it
+	DeleteReview { deal_id: u64 },
+	// 31 - Deal's merchant answers a review publicly
+	ReplyToReview { comment: String },
+	// 32 - Program upgrade authority creates (or hands over) the config naming the admin
+	SetAdmin { admin: [u8; 32] },
+	// 33 - Any wallet reports a review, once
+	FlagReview,
+	// 34 - Admin hides or restores a review
+	HideReview { hidden: bool },
+	// 35 - Rewrite a legacy Deal, Merchant, Review, RedeemLog or Listing account in the current layout, growing it as needed
+	MigrateAccount,
 }
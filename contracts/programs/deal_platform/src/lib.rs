@@ -1,7 +1,6 @@
 #![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
-use alloc::format;
 
 use solana_program::{
 	account_info::AccountInfo,
@@ -11,7 +10,11 @@ use solana_program::{
 };
 
 pub mod error;
+pub mod event;
 pub mod instruction;
+pub mod merkle;
+pub mod metaplex;
+pub mod pda;
 pub mod processor;
 pub mod state;
 
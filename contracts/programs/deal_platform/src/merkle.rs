@@ -0,0 +1,53 @@
+use alloc::vec::Vec;
+use borsh_derive::{BorshDeserialize as BorshDeserializeDerive, BorshSerialize as BorshSerializeDerive};
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+// Allowlists are sha256 Merkle trees over (user, allowance) leaves. Pairs are sorted before hashing so a proof
+// is just the list of siblings, and leaves and nodes use different prefixes so a node can't pass as a leaf.
+
+// Sent with mint instructions for deals that have an allowlist_root
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct AllowlistProof {
+	// Coupons this user may mint; 0 for no per-user limit
+	pub allowance: u32,
+	pub proof: Vec<[u8; 32]>,
+}
+
+pub fn leaf(user: &Pubkey, allowance: u32) -> [u8; 32] {
+	hashv(&[&[0u8], user.as_ref(), &allowance.to_le_bytes()]).to_bytes()
+}
+
+fn parent(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+	let (left, right) = if a <= b { (a, b) } else { (b, a) };
+	hashv(&[&[1u8], left, right]).to_bytes()
+}
+
+pub fn verify(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+	proof.iter().fold(leaf, |node, sibling| parent(&node, sibling)) == *root
+}
+
+// Every level of the tree, leaves first; an odd node at the end of a level moves up unchanged
+pub fn build(leaves: &[[u8; 32]]) -> Vec<Vec<[u8; 32]>> {
+	let mut levels = alloc::vec![leaves.to_vec()];
+	while levels.last().is_some_and(|level| level.len() > 1) {
+		let level = levels.last().unwrap();
+		let next = level.chunks(2).map(|pair| if pair.len() == 2 { parent(&pair[0], &pair[1]) } else { pair[0] }).collect();
+		levels.push(next);
+	}
+	levels
+}
+
+pub fn root(levels: &[Vec<[u8; 32]>]) -> [u8; 32] {
+	levels.last().and_then(|level| level.first()).copied().unwrap_or_default()
+}
+
+pub fn proof(levels: &[Vec<[u8; 32]>], mut index: usize) -> Vec<[u8; 32]> {
+	let mut proof = Vec::new();
+	for level in &levels[..levels.len().saturating_sub(1)] {
+		if let Some(sibling) = level.get(index ^ 1) {
+			proof.push(*sibling);
+		}
+		index /= 2;
+	}
+	proof
+}
@@ -0,0 +1,51 @@
+use alloc::{string::String, vec::Vec};
+use borsh_derive::{BorshDeserialize as BorshDeserializeDerive, BorshSerialize as BorshSerializeDerive};
+use solana_program::{pubkey, pubkey::Pubkey};
+
+// Just enough of the Metaplex Token Metadata account layout to read an NFT's collection without the full crate
+
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+pub fn metadata_pda(mint: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[b"metadata", TOKEN_METADATA_PROGRAM_ID.as_ref(), mint.as_ref()], &TOKEN_METADATA_PROGRAM_ID).0
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct Creator {
+	pub address: Pubkey,
+	pub verified: bool,
+	pub share: u8,
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct Collection {
+	pub verified: bool,
+	pub key: Pubkey,
+}
+
+// Leading fields of a MetadataV1 account, up to the collection; later fields are not read
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct Metadata {
+	pub key: u8,
+	pub update_authority: Pubkey,
+	pub mint: Pubkey,
+	pub name: String,
+	pub symbol: String,
+	pub uri: String,
+	pub seller_fee_basis_points: u16,
+	pub creators: Option<Vec<Creator>>,
+	pub primary_sale_happened: bool,
+	pub is_mutable: bool,
+	pub edition_nonce: Option<u8>,
+	pub token_standard: Option<u8>,
+	pub collection: Option<Collection>,
+}
+
+impl Metadata {
+	pub const KEY_V1: u8 = 4;
+
+	// Collection the NFT was verified into by the collection authority, if any
+	pub fn verified_collection(&self) -> Option<Pubkey> {
+		self.collection.as_ref().filter(|c| c.verified).map(|c| c.key)
+	}
+}
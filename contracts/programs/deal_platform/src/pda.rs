@@ -0,0 +1,18 @@
+extern crate alloc;
+
+use alloc::vec::Vec;
+use solana_program::pubkey::Pubkey;
+
+// Canonical address and bump; only needed when an account is created or for data-less PDAs
+pub fn find(seeds: &[&[u8]], program_id: &Pubkey) -> (Pubkey, u8) {
+	Pubkey::find_program_address(seeds, program_id)
+}
+
+// Derives the address from a bump stored at creation, in a single attempt
+pub fn create(seeds: &[&[u8]], bump: u8, program_id: &Pubkey) -> Option<Pubkey> {
+	let bump = [bump];
+	let mut with_bump: Vec<&[u8]> = Vec::with_capacity(seeds.len() + 1);
+	with_bump.extend_from_slice(seeds);
+	with_bump.push(&bump);
+	Pubkey::create_program_address(&with_bump, program_id).ok()
+}
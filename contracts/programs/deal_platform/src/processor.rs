@@ -1,13 +1,14 @@
 extern crate alloc;
 
 use alloc::format;
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
 	account_info::{next_account_info, AccountInfo},
 	entrypoint::ProgramResult,
 	msg,
 	program::{invoke},
 	program_error::ProgramError,
+	program_pack::Pack,
 	pubkey::Pubkey,
 	system_instruction,
 	sysvar::{clock::Clock, Sysvar},
@@ -15,16 +16,58 @@ use solana_program::{
 
 use crate::{
 	error::DealError,
+	event::DealEvent,
 	instruction::DealInstruction,
-	state::{seeds, Deal, Listing, Merchant, RedeemLog, Review, MAX_COMMENT_LEN, MAX_DESC_LEN, MAX_NAME_LEN, MAX_TITLE_LEN, MAX_URI_LEN},
+	merkle::{self, AllowlistProof},
+	metaplex::{self, Metadata},
+	pda,
+	state::{seeds, AccountState, AllowlistMints, ClaimLink, Config, Coupon, CouponState, Deal, DealGate, DealStatus, DealV0, DiscountKind, Listing, ListingV0, PriceCurve, ProgramAddress, Raffle, RaffleEntry, RedemptionSchedule, TokenPrice, Merchant, MerchantV0, RedeemLog, RedeemLogV0, Review, ReviewFlag, ReviewV0, ReviewReply, MAX_COMMENT_LEN, MAX_DESC_LEN, MAX_NAME_LEN, MAX_REPLY_LEN, MAX_TITLE_LEN, MAX_URI_LEN},
 };
 
 pub struct Processor;
 
 impl Processor {
-	fn read_unpacked<T: BorshDeserialize>(data: &[u8]) -> Result<T, ProgramError> {
-		let mut cursor = borsh::maybestd::io::Cursor::new(data);
-		T::deserialize_reader(&mut cursor).map_err(|_| DealError::InvalidInput.into())
+	// Every program state account goes through here: data is only trusted once the program owns the account.
+	// The header is checked first so a wrong kind or an old layout gets its own error code.
+	fn load<T: AccountState>(program_id: &Pubkey, account: &AccountInfo) -> Result<T, ProgramError> {
+		if account.owner != program_id { return Err(DealError::IncorrectOwner.into()); }
+		let data = account.data.borrow();
+		T::check_header(&data)?;
+		T::unpack(&data).map_err(|_| DealError::InvalidInput.into())
+	}
+
+	// Checks a decoded account sits at the PDA of its own fields, using the bump stored at creation
+	fn assert_address<T: ProgramAddress>(program_id: &Pubkey, account: &AccountInfo, value: &T) -> ProgramResult {
+		let address = value.with_seeds(|seeds| pda::create(seeds, value.bump(), program_id));
+		if address != Some(*account.key) { return Err(DealError::PdaDerivationMismatch.into()); }
+		Ok(())
+	}
+
+	// `load` for accounts the caller picks: the account must also be the PDA its data describes
+	fn load_pda<T: AccountState + ProgramAddress>(program_id: &Pubkey, account: &AccountInfo) -> Result<T, ProgramError> {
+		let value: T = Self::load(program_id, account)?;
+		Self::assert_address(program_id, account, &value)?;
+		Ok(value)
+	}
+
+	// The merchant account of `merchant`; merchant-signed instructions pass the signer
+	fn load_merchant(program_id: &Pubkey, merchant_pda_ai: &AccountInfo, merchant: &Pubkey) -> Result<Merchant, ProgramError> {
+		let account: Merchant = Self::load_pda(program_id, merchant_pda_ai)?;
+		if account.merchant != *merchant { return Err(DealError::PdaDerivationMismatch.into()); }
+		Ok(account)
+	}
+
+	// Deal `deal_id` of `merchant`; merchant-signed instructions pass the signer
+	fn load_deal(program_id: &Pubkey, deal_pda_ai: &AccountInfo, merchant: &Pubkey, deal_id: u64) -> Result<Deal, ProgramError> {
+		let deal: Deal = Self::load_pda(program_id, deal_pda_ai)?;
+		if deal.merchant != *merchant || deal.deal_id != deal_id { return Err(DealError::PdaDerivationMismatch.into()); }
+		Ok(deal)
+	}
+
+	// For instructions signed by users rather than the merchant: the deal must belong to a verified merchant account
+	fn load_merchant_deal(program_id: &Pubkey, merchant_pda_ai: &AccountInfo, deal_pda_ai: &AccountInfo, deal_id: u64) -> Result<Deal, ProgramError> {
+		let merchant: Merchant = Self::load_pda(program_id, merchant_pda_ai)?;
+		Self::load_deal(program_id, deal_pda_ai, &merchant.merchant, deal_id)
 	}
 
 	pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
@@ -36,16 +79,53 @@ impl Processor {
 			DealInstruction::SetCollectionMint { collection_mint } => {
 				Self::process_set_collection_mint(program_id, accounts, Pubkey::new_from_array(collection_mint))
 			}
-			DealInstruction::CreateDeal { deal_id, title, description, discount_percent, expiry, total_supply, image_uri, metadata_uri } => {
-				Self::process_create_deal(program_id, accounts, deal_id, title, description, discount_percent, expiry, total_supply, image_uri, metadata_uri)
+			DealInstruction::CreateDeal { title, description, discount, expiry, total_supply, image_uri, metadata_uri } => {
+				Self::process_create_deal(program_id, accounts, title, description, discount, expiry, total_supply, image_uri, metadata_uri)
 			}
-			DealInstruction::MintCouponNft { deal_id } => Self::process_mint_coupon(program_id, accounts, deal_id),
+			DealInstruction::MintCouponNft { deal_id, allowlist_proof } => Self::process_mint_coupon(program_id, accounts, deal_id, allowlist_proof),
 			DealInstruction::RedeemCoupon { mint } => Self::process_redeem_coupon(program_id, accounts, Pubkey::new_from_array(mint)),
 			DealInstruction::AddReview { deal_id, rating, comment } => Self::process_add_review(program_id, accounts, deal_id, rating, comment),
-			DealInstruction::VerifyAndCountMint { deal_id, mint: _ } => Self::process_verify_and_count_mint(program_id, accounts, deal_id),
+			DealInstruction::UpdateReview { deal_id, rating, comment } => Self::process_update_review(program_id, accounts, deal_id, rating, comment),
+			DealInstruction::DeleteReview { deal_id } => Self::process_delete_review(program_id, accounts, deal_id),
+			DealInstruction::ReplyToReview { comment } => Self::process_reply_to_review(program_id, accounts, comment),
+			DealInstruction::SetAdmin { admin } => Self::process_set_admin(program_id, accounts, Pubkey::new_from_array(admin)),
+			DealInstruction::FlagReview => Self::process_flag_review(program_id, accounts),
+			DealInstruction::HideReview { hidden } => Self::process_hide_review(program_id, accounts, hidden),
+			DealInstruction::MigrateAccount => Self::process_migrate_account(program_id, accounts),
+			DealInstruction::VerifyAndCountMint { deal_id, mint, allowlist_proof } => {
+				Self::process_verify_and_count_mint(program_id, accounts, deal_id, Pubkey::new_from_array(mint), allowlist_proof)
+			}
 			DealInstruction::RedeemAndBurn { mint } => Self::process_redeem_and_burn(program_id, accounts, Pubkey::new_from_array(mint)),
-			DealInstruction::ListNft { price } => Self::process_list_nft(program_id, accounts, price),
+			DealInstruction::ListNft { price, payment_mint } => Self::process_list_nft(program_id, accounts, price, payment_mint.map(Pubkey::new_from_array)),
 			DealInstruction::BuyNft => Self::process_buy_nft(program_id, accounts),
+			DealInstruction::SetDealStatus { deal_id, status } => Self::process_set_deal_status(program_id, accounts, deal_id, status),
+			DealInstruction::RecountDeals => Self::process_recount_deals(program_id, accounts),
+			DealInstruction::SetRedemptionSchedule { deal_id, schedule } => Self::process_set_redemption_schedule(program_id, accounts, deal_id, schedule),
+			DealInstruction::SetMintPrice { deal_id, mint_price_lamports } => Self::process_set_mint_price(program_id, accounts, deal_id, mint_price_lamports),
+			DealInstruction::SetTokenMintPrice { deal_id, payment_mint, amount } => {
+				let token_price = payment_mint.map(|mint| TokenPrice { mint: Pubkey::new_from_array(mint), amount });
+				Self::process_set_token_mint_price(program_id, accounts, deal_id, token_price)
+			}
+			DealInstruction::ClaimRefund { mint } => Self::process_claim_refund(program_id, accounts, Pubkey::new_from_array(mint)),
+			DealInstruction::SetGroupBuy { deal_id, min_participants, funding_deadline } => {
+				Self::process_set_group_buy(program_id, accounts, deal_id, min_participants, funding_deadline)
+			}
+			DealInstruction::FinalizeGroupDeal { deal_id } => Self::process_finalize_group_deal(program_id, accounts, deal_id),
+			DealInstruction::RefundGroupDeal { mint } => Self::process_refund_group_deal(program_id, accounts, Pubkey::new_from_array(mint)),
+			DealInstruction::SetPriceCurve { deal_id, curve } => Self::process_set_price_curve(program_id, accounts, deal_id, curve),
+			DealInstruction::SetRaffle { deal_id, winners, entry_deadline } => {
+				Self::process_set_raffle(program_id, accounts, deal_id, winners, entry_deadline)
+			}
+			DealInstruction::EnterRaffle { deal_id } => Self::process_enter_raffle(program_id, accounts, deal_id),
+			DealInstruction::DrawRaffle { deal_id } => Self::process_draw_raffle(program_id, accounts, deal_id),
+			DealInstruction::SetAllowlist { deal_id, root } => Self::process_set_allowlist(program_id, accounts, deal_id, root),
+			DealInstruction::SetGate { deal_id, gate } => Self::process_set_gate(program_id, accounts, deal_id, gate),
+			DealInstruction::AirdropCoupons { deal_id } => Self::process_airdrop_coupons(program_id, accounts, deal_id),
+			DealInstruction::CreateClaim { deal_id, claim_hash, expires_at } => {
+				Self::process_create_claim(program_id, accounts, deal_id, claim_hash, expires_at)
+			}
+			DealInstruction::Claim { claim_hash } => Self::process_claim(program_id, accounts, claim_hash),
+			DealInstruction::ReclaimExpired { claim_hash } => Self::process_reclaim_expired(program_id, accounts, claim_hash),
 		}
 	}
 
@@ -54,6 +134,342 @@ impl Processor {
 		Ok(())
 	}
 
+	fn assert_deal_active(deal: &Deal, now: i64) -> Result<(), ProgramError> {
+		match deal.status {
+			DealStatus::Active => {}
+			DealStatus::Paused => return Err(DealError::DealNotActive.into()),
+			DealStatus::Cancelled => return Err(DealError::DealCancelled.into()),
+		}
+		if deal.is_group_pending() && now > deal.funding_deadline { return Err(DealError::FundingClosed.into()); }
+		Ok(())
+	}
+
+	// Redemption only needs the deal to exist, not be cancelled and be inside its schedule; paused deals stay redeemable
+	fn assert_deal_redeemable(program_id: &Pubkey, deal_pda_ai: &AccountInfo, now: i64) -> Result<Deal, ProgramError> {
+		let deal: Deal = Self::load(program_id, deal_pda_ai)?;
+		if deal.status == DealStatus::Cancelled { return Err(DealError::DealCancelled.into()); }
+		if deal.is_group_pending() { return Err(DealError::GroupDealNotFinalized.into()); }
+		if let Some(schedule) = deal.schedule {
+			if !schedule.is_open_at(now) { return Err(DealError::OutsideRedemptionWindow.into()); }
+		}
+		Ok(deal)
+	}
+
+	// Raffle deals take the user's entry PDA (write) right after the deal; it is marked so each win mints once
+	fn claim_raffle_win<'a, 'b: 'a>(
+		program_id: &Pubkey,
+		deal_key: &Pubkey,
+		deal: &Deal,
+		user: &Pubkey,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> ProgramResult {
+		let Some(raffle) = deal.raffle else { return Ok(()) };
+		let entry_ai = next_account_info(account_iter)?;
+		if !raffle.is_drawn() { return Err(DealError::RaffleNotDrawn.into()); }
+		if entry_ai.owner != program_id { return Err(DealError::NotRaffleWinner.into()); }
+		let mut entry: RaffleEntry = Self::load_pda(program_id, entry_ai)?;
+		if entry.deal != *deal_key || entry.user != *user { return Err(DealError::PdaDerivationMismatch.into()); }
+		if entry.minted || !raffle.is_winner(entry.index) { return Err(DealError::NotRaffleWinner.into()); }
+		entry.minted = true;
+		entry.pack(&mut entry_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	// Allowlisted deals need a proof for the user. A non-zero allowance is counted in the user's allowance PDA, which
+	// follows (write) together with the system program.
+	fn check_allowlist<'a, 'b: 'a>(
+		program_id: &Pubkey,
+		deal_key: &Pubkey,
+		deal: &Deal,
+		user: &AccountInfo<'b>,
+		allowlist_proof: Option<AllowlistProof>,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> ProgramResult {
+		if deal.allowlist_root == [0; 32] { return Ok(()); }
+		let Some(AllowlistProof { allowance, proof }) = allowlist_proof else { return Err(DealError::NotAllowlisted.into()) };
+		if !merkle::verify(&deal.allowlist_root, merkle::leaf(user.key, allowance), &proof) { return Err(DealError::NotAllowlisted.into()); }
+		if allowance == 0 { return Ok(()); }
+
+		let allowance_ai = next_account_info(account_iter)?;
+		let system_program = next_account_info(account_iter)?;
+		let mut mints = if allowance_ai.data_is_empty() {
+			let (allowance_pda, bump) = pda::find(&[seeds::ALLOWANCE, deal_key.as_ref(), user.key.as_ref()], program_id);
+			if allowance_pda != *allowance_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+			let rent = solana_program::rent::Rent::get()?;
+			let create_ix = system_instruction::create_account(
+				user.key,
+				allowance_ai.key,
+				rent.minimum_balance(AllowlistMints::space()),
+				AllowlistMints::space() as u64,
+				program_id,
+			);
+			solana_program::program::invoke_signed(
+				&create_ix,
+				&[user.clone(), allowance_ai.clone(), system_program.clone()],
+				&[&[seeds::ALLOWANCE, deal_key.as_ref(), user.key.as_ref(), &[bump]]],
+			)?;
+			AllowlistMints { deal: *deal_key, user: *user.key, minted: 0, bump }
+		} else {
+			let mints: AllowlistMints = Self::load_pda(program_id, allowance_ai)?;
+			if mints.deal != *deal_key || mints.user != *user.key { return Err(DealError::PdaDerivationMismatch.into()); }
+			mints
+		};
+		if mints.minted >= allowance { return Err(DealError::AllowanceExceeded.into()); }
+		mints.minted += 1;
+		mints.pack(&mut allowance_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	// Gated deals take the user's token account for the gate mint, or for a collection gate the user's token account
+	// of the NFT followed by that NFT's metadata account
+	fn check_gate<'a, 'b: 'a>(
+		deal: &Deal,
+		user: &Pubkey,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> ProgramResult {
+		let Some(gate) = deal.gate else { return Ok(()) };
+		let token_ai = next_account_info(account_iter)?;
+		if *token_ai.owner != spl_token::id() { return Err(DealError::GateNotSatisfied.into()); }
+		let token = spl_token::state::Account::unpack(&token_ai.data.borrow())?;
+		if token.owner != *user { return Err(DealError::GateNotSatisfied.into()); }
+		match gate {
+			DealGate::TokenBalance { mint, min_amount } => {
+				if token.mint != mint || token.amount < min_amount { return Err(DealError::GateNotSatisfied.into()); }
+			}
+			DealGate::Collection { collection } => {
+				let metadata_ai = next_account_info(account_iter)?;
+				if token.amount == 0 { return Err(DealError::GateNotSatisfied.into()); }
+				// Token Metadata only writes a mint's metadata at that mint's PDA, so the owner and the mint field stand in
+				// for deriving the address
+				if *metadata_ai.owner != metaplex::TOKEN_METADATA_PROGRAM_ID { return Err(DealError::GateNotSatisfied.into()); }
+				let metadata = Metadata::deserialize_reader(&mut borsh::maybestd::io::Cursor::new(&metadata_ai.data.borrow()[..])).map_err(|_| DealError::InvalidInput)?;
+				if metadata.key != Metadata::KEY_V1 || metadata.mint != token.mint || metadata.verified_collection() != Some(collection) {
+					return Err(DealError::GateNotSatisfied.into());
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn assert_vault(program_id: &Pubkey, deal_key: &Pubkey, deal: &Deal, vault_ai: &AccountInfo) -> Result<u8, ProgramError> {
+		let Some(bump) = deal.vault_bump else { return Err(DealError::InvalidInput.into()) };
+		if pda::create(&[seeds::VAULT, deal_key.as_ref()], bump, program_id) != Some(*vault_ai.key) { return Err(DealError::PdaDerivationMismatch.into()); }
+		if vault_ai.owner != program_id { return Err(DealError::InvalidInput.into()); }
+		Ok(bump)
+	}
+
+	// The vault is a data-less program-owned PDA, so escrowed lamports can be paid out without a CPI. Its bump is
+	// recorded in `deal`, which the caller writes back. Anyone can send lamports to the address beforehand, so a
+	// funded system account there is topped up and taken over instead of created.
+	fn create_vault_if_missing<'a>(
+		program_id: &Pubkey,
+		payer: &AccountInfo<'a>,
+		deal_key: &Pubkey,
+		deal: &mut Deal,
+		vault_ai: &AccountInfo<'a>,
+		system_program: &AccountInfo<'a>,
+	) -> ProgramResult {
+		if deal.vault_bump.is_some() { return Self::assert_vault(program_id, deal_key, deal, vault_ai).map(|_| ()); }
+		let (vault_pda, bump) = pda::find(&[seeds::VAULT, deal_key.as_ref()], program_id);
+		if vault_pda != *vault_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		let vault_seeds: &[&[u8]] = &[seeds::VAULT, deal_key.as_ref(), &[bump]];
+		let rent = solana_program::rent::Rent::get()?;
+		if vault_ai.lamports() == 0 {
+			let create_ix = system_instruction::create_account(payer.key, vault_ai.key, rent.minimum_balance(0), 0, program_id);
+			solana_program::program::invoke_signed(&create_ix, &[payer.clone(), vault_ai.clone(), system_program.clone()], &[vault_seeds])?;
+		} else if solana_program::system_program::check_id(vault_ai.owner) && vault_ai.data_is_empty() {
+			let shortfall = rent.minimum_balance(0).saturating_sub(vault_ai.lamports());
+			if shortfall > 0 {
+				invoke(&system_instruction::transfer(payer.key, vault_ai.key, shortfall), &[payer.clone(), vault_ai.clone(), system_program.clone()])?;
+			}
+			solana_program::program::invoke_signed(&system_instruction::allocate(vault_ai.key, 0), &[vault_ai.clone(), system_program.clone()], &[vault_seeds])?;
+			solana_program::program::invoke_signed(&system_instruction::assign(vault_ai.key, program_id), &[vault_ai.clone(), system_program.clone()], &[vault_seeds])?;
+		} else if vault_ai.owner != program_id {
+			return Err(DealError::InvalidInput.into());
+		}
+		deal.vault_bump = Some(bump);
+		Ok(())
+	}
+
+	fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> ProgramResult {
+		**from.lamports.borrow_mut() = from.lamports().checked_sub(amount).ok_or(DealError::Overflow)?;
+		**to.lamports.borrow_mut() = to.lamports().checked_add(amount).ok_or(DealError::Overflow)?;
+		Ok(())
+	}
+
+	// Lamports go to `destination` and the data is wiped; the runtime drops the empty account after the transaction
+	fn close_program_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+		Self::move_lamports(account, destination, account.lamports())?;
+		account.data.borrow_mut().fill(0);
+		Ok(())
+	}
+
+	fn read_coupon(program_id: &Pubkey, coupon_ai: &AccountInfo, deal: &Pubkey) -> Result<Coupon, ProgramError> {
+		let coupon: Coupon = Self::load_pda(program_id, coupon_ai)?;
+		if coupon.deal != *deal { return Err(DealError::InvalidInput.into()); }
+		if coupon.state != CouponState::Escrowed { return Err(DealError::PaymentSettled.into()); }
+		Ok(coupon)
+	}
+
+	// Moves `amount` of `mint` to a token account that must belong to `recipient`; PDA authorities pass their seeds
+	#[allow(clippy::too_many_arguments)]
+	fn transfer_tokens_checked<'a>(
+		authority: &AccountInfo<'a>,
+		source: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		mint_ai: &AccountInfo<'a>,
+		token_program: &AccountInfo<'a>,
+		expected_mint: &Pubkey,
+		recipient: &Pubkey,
+		amount: u64,
+		signer_seeds: &[&[&[u8]]],
+	) -> ProgramResult {
+		if *token_program.key != spl_token::id() || *mint_ai.key != *expected_mint { return Err(DealError::InvalidInput.into()); }
+		let dst = spl_token::state::Account::unpack(&destination.data.borrow())?;
+		if dst.owner != *recipient || dst.mint != *expected_mint { return Err(DealError::InvalidInput.into()); }
+		let decimals = spl_token::state::Mint::unpack(&mint_ai.data.borrow())?.decimals;
+		let transfer_ix = spl_token::instruction::transfer_checked(
+			&spl_token::id(),
+			source.key,
+			mint_ai.key,
+			destination.key,
+			authority.key,
+			&[],
+			amount,
+			decimals,
+		)?;
+		solana_program::program::invoke_signed(
+			&transfer_ix,
+			&[source.clone(), mint_ai.clone(), destination.clone(), authority.clone(), token_program.clone()],
+			signer_seeds,
+		)
+	}
+
+	// Records `coupon` at the PDA of its mint, so each mint is counted once and only for the deal it names
+	fn create_coupon_record<'a>(
+		program_id: &Pubkey,
+		payer: &AccountInfo<'a>,
+		coupon_ai: &AccountInfo<'a>,
+		system_program: &AccountInfo<'a>,
+		mut coupon: Coupon,
+	) -> ProgramResult {
+		let (coupon_pda, bump) = pda::find(&[seeds::COUPON, coupon.mint.as_ref()], program_id);
+		if coupon_pda != *coupon_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		if !coupon_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
+		let rent = solana_program::rent::Rent::get()?;
+		let create_ix = system_instruction::create_account(
+			payer.key,
+			coupon_ai.key,
+			rent.minimum_balance(Coupon::space()),
+			Coupon::space() as u64,
+			program_id,
+		);
+		solana_program::program::invoke_signed(
+			&create_ix,
+			&[payer.clone(), coupon_ai.clone(), system_program.clone()],
+			&[&[seeds::COUPON, coupon.mint.as_ref(), &[bump]]],
+		)?;
+		coupon.bump = bump;
+		coupon.pack(&mut coupon_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	// Counted mints take more accounts after the deal: coupon record PDA (write), the deal vault (write) for paid deals,
+	// system program, and for token prices the user token account (write), vault token account (write), payment mint
+	// and token program. Returns the amount escrowed, 0 for free deals.
+	fn record_minted_coupon<'a, 'b: 'a>(
+		program_id: &Pubkey,
+		deal_key: &Pubkey,
+		deal: &Deal,
+		user: &AccountInfo<'b>,
+		mint: &Pubkey,
+		now: i64,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> Result<u64, ProgramError> {
+		let coupon_ai = next_account_info(account_iter)?;
+		let vault_ai = if deal.is_paid() { Some(next_account_info(account_iter)?) } else { None };
+		let system_program = next_account_info(account_iter)?;
+		let Some(vault_ai) = vault_ai else {
+			let coupon = Coupon { mint: *mint, deal: *deal_key, paid: 0, state: CouponState::Released, bump: 0 };
+			Self::create_coupon_record(program_id, user, coupon_ai, system_program, coupon)?;
+			return Ok(0);
+		};
+		Self::assert_vault(program_id, deal_key, deal, vault_ai)?;
+		let paid = deal.mint_price_at(now);
+		let coupon = Coupon { mint: *mint, deal: *deal_key, paid, state: CouponState::Escrowed, bump: 0 };
+		Self::create_coupon_record(program_id, user, coupon_ai, system_program, coupon)?;
+
+		match deal.token_price {
+			Some(price) => {
+				let user_token = next_account_info(account_iter)?;
+				let vault_token = next_account_info(account_iter)?;
+				let payment_mint = next_account_info(account_iter)?;
+				let token_program = next_account_info(account_iter)?;
+				Self::transfer_tokens_checked(user, user_token, vault_token, payment_mint, token_program, &price.mint, vault_ai.key, paid, &[])?;
+			}
+			None => {
+				invoke(
+					&system_instruction::transfer(user.key, vault_ai.key, paid),
+					&[user.clone(), vault_ai.clone(), system_program.clone()],
+				)?;
+			}
+		}
+		if let Some(curve) = deal.price_curve {
+			msg!("Dutch auction price {} (floor {})", paid, curve.end_price);
+		}
+		Ok(paid)
+	}
+
+	// Redemption always takes the coupon record PDA after the deal. When it holds an escrowed payment the deal vault
+	// (write) follows, then the merchant wallet (write) for SOL prices, or the vault token account (write), merchant
+	// token account (write), payment mint and token program for token prices. Escrowed payments are only released
+	// when the coupon is `burned`, so a redeemed coupon cannot be refunded or passed on.
+	fn release_coupon_payment<'a, 'b: 'a>(
+		program_id: &Pubkey,
+		deal_key: &Pubkey,
+		deal: &Deal,
+		mint: &Pubkey,
+		burned: bool,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> ProgramResult {
+		let coupon_ai = next_account_info(account_iter)?;
+		// Only mints counted for this deal have a record, so a self-made mint can't be redeemed against it
+		if coupon_ai.owner != program_id { return Err(DealError::CouponNotOfDeal.into()); }
+		let mut coupon: Coupon = Self::load_pda(program_id, coupon_ai)?;
+		if coupon.mint != *mint { return Err(DealError::PdaDerivationMismatch.into()); }
+		if coupon.deal != *deal_key { return Err(DealError::CouponNotOfDeal.into()); }
+		match coupon.state {
+			// Free coupons, and group deals that released this payment when they were finalized
+			CouponState::Released => return Ok(()),
+			CouponState::Refunded => return Err(DealError::PaymentSettled.into()),
+			CouponState::Escrowed => {}
+		}
+		if !burned { return Err(DealError::CouponMustBeBurned.into()); }
+
+		let vault_ai = next_account_info(account_iter)?;
+		let vault_bump = Self::assert_vault(program_id, deal_key, deal, vault_ai)?;
+		match deal.token_price {
+			Some(price) => {
+				let vault_token = next_account_info(account_iter)?;
+				let merchant_token = next_account_info(account_iter)?;
+				let payment_mint = next_account_info(account_iter)?;
+				let token_program = next_account_info(account_iter)?;
+				Self::transfer_tokens_checked(
+					vault_ai, vault_token, merchant_token, payment_mint, token_program, &price.mint, &deal.merchant, coupon.paid,
+					&[&[seeds::VAULT, deal_key.as_ref(), &[vault_bump]]],
+				)?;
+			}
+			None => {
+				let merchant_wallet = next_account_info(account_iter)?;
+				if *merchant_wallet.key != deal.merchant { return Err(DealError::InvalidInput.into()); }
+				Self::move_lamports(vault_ai, merchant_wallet, coupon.paid)?;
+			}
+		}
+		coupon.state = CouponState::Released;
+		coupon.pack(&mut coupon_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
 	fn process_register_merchant(
 		program_id: &Pubkey,
 		accounts: &[AccountInfo],
@@ -70,7 +486,7 @@ impl Processor {
 
 		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
 
-		let (merchant_pda, bump) = Pubkey::find_program_address(&[seeds::MERCHANT, payer.key.as_ref()], program_id);
+		let (merchant_pda, bump) = pda::find(&[seeds::MERCHANT, payer.key.as_ref()], program_id);
 		if merchant_pda != *merchant_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
 		if merchant_pda_ai.data_len() > 0 && !merchant_pda_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
 
@@ -89,8 +505,9 @@ impl Processor {
 			&[&[seeds::MERCHANT, payer.key.as_ref(), &[bump]]],
 		)?;
 
-		let merchant = Merchant { merchant: *payer.key, name, uri, total_deals: 0, collection_mint: Pubkey::default() };
-		merchant.serialize(&mut &mut merchant_pda_ai.data.borrow_mut()[..])?;
+		let merchant = Merchant { merchant: *payer.key, name, uri, total_deals: 0, collection_mint: Pubkey::default(), next_deal_id: 0, rating_sum: 0, rating_count: 0, bump };
+		merchant.pack(&mut merchant_pda_ai.data.borrow_mut()[..])?;
+		DealEvent::MerchantRegistered { merchant: merchant.merchant, name: merchant.name, uri: merchant.uri }.emit()?;
 		Ok(())
 	}
 
@@ -104,26 +521,20 @@ impl Processor {
 		let merchant_pda_ai = next_account_info(account_iter)?; // write
 
 		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
-		let (merchant_pda, _bump) = Pubkey::find_program_address(&[seeds::MERCHANT, payer.key.as_ref()], program_id);
-		if merchant_pda != *merchant_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
-
-		let mut merchant: Merchant = {
-			let data = merchant_pda_ai.data.borrow();
-			Self::read_unpacked(&data)?
-		};
+		let mut merchant = Self::load_merchant(program_id, merchant_pda_ai, payer.key)?;
 		merchant.collection_mint = collection_mint;
 		let mut dst = merchant_pda_ai.data.borrow_mut();
-		merchant.serialize(&mut &mut dst[..])?;
+		merchant.pack(&mut dst[..])?;
 		Ok(())
 	}
 
+	#[allow(clippy::too_many_arguments)]
 	fn process_create_deal(
 		program_id: &Pubkey,
 		accounts: &[AccountInfo],
-		deal_id: u64,
 		title: alloc::string::String,
 		description: alloc::string::String,
-		discount_percent: u8,
+		discount: DiscountKind,
 		expiry: i64,
 		total_supply: u32,
 		image_uri: alloc::string::String,
@@ -133,22 +544,32 @@ impl Processor {
 		Self::assert_len(&description, MAX_DESC_LEN)?;
 		Self::assert_len(&image_uri, MAX_URI_LEN)?;
 		Self::assert_len(&metadata_uri, MAX_URI_LEN)?;
+		if !discount.is_valid() { return Err(DealError::InvalidDiscount.into()); }
 
 		let account_iter = &mut accounts.iter();
 		let payer = next_account_info(account_iter)?; // signer (merchant)
 		let merchant_pda_ai = next_account_info(account_iter)?;
 		let deal_pda_ai = next_account_info(account_iter)?;
 		let system_program = next_account_info(account_iter)?;
+		// remaining: migrated deal accounts already occupying the next ids, in id order
 
 		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
 
-		let (merchant_pda, _merchant_bump) = Pubkey::find_program_address(&[seeds::MERCHANT, payer.key.as_ref()], program_id);
-		if merchant_pda != *merchant_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
-
 		if merchant_pda_ai.data_is_empty() { return Err(DealError::Unauthorized.into()); }
 
+		let mut merchant = Self::load_merchant(program_id, merchant_pda_ai, payer.key)?;
+		// A legacy deal left out of the merchant's migration can sit at the id the counter hands out next. Migrating it
+		// and passing it moves the counter past it instead of failing on the occupied address forever; once migrated
+		// its stored bump proves its address.
+		let mut deal_id = merchant.next_deal_id;
+		for taken_ai in account_iter {
+			Self::load_deal(program_id, taken_ai, payer.key, deal_id)?;
+			deal_id = deal_id.checked_add(1).ok_or(DealError::Overflow)?;
+		}
+		merchant.next_deal_id = deal_id.checked_add(1).ok_or(DealError::Overflow)?;
+
 		let deal_seed_id = deal_id.to_le_bytes();
-		let (deal_pda, deal_bump) = Pubkey::find_program_address(&[seeds::DEAL, payer.key.as_ref(), &deal_seed_id], program_id);
+		let (deal_pda, deal_bump) = pda::find(&[seeds::DEAL, payer.key.as_ref(), &deal_seed_id], program_id);
 		if deal_pda != *deal_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
 		if deal_pda_ai.data_len() > 0 && !deal_pda_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
 
@@ -175,29 +596,40 @@ impl Processor {
 				merchant: *payer.key,
 				title,
 				description,
-				discount_percent,
+				discount,
 				expiry,
 				total_supply,
 				minted: 0,
 				image_uri,
 				metadata_uri,
+				status: DealStatus::Active,
+				schedule: None,
+				mint_price_lamports: 0,
+				token_price: None,
+				min_participants: 0,
+				funding_deadline: 0,
+				group_finalized: false,
+				price_curve: None,
+				raffle: None,
+				allowlist_root: [0; 32],
+				gate: None,
+				rating_sum: 0,
+				rating_count: 0,
+				vault_bump: None,
+				bump: deal_bump,
 			};
-			deal.serialize(&mut &mut dst[..])?;
+			deal.pack(&mut dst[..])?;
+			DealEvent::DealCreated { merchant: deal.merchant, deal: deal_pda, deal_id, title: deal.title, total_supply, expiry }.emit()?;
 		}
 
-		// Increment merchant.total_deals (best-effort), ensuring borrow scopes do not overlap
+		// Persist the advanced id counter and increment merchant.total_deals
 		{
-			let merchant_owned = {
-				let data = merchant_pda_ai.data.borrow();
-				Self::read_unpacked::<Merchant>(&data)
-			};
-			if let Ok(mut merchant) = merchant_owned {
-				if let Some(next) = merchant.total_deals.checked_add(1) { merchant.total_deals = next; }
-				let mut dst = merchant_pda_ai.data.borrow_mut();
-				let _ = merchant.serialize(&mut &mut dst[..]);
-			}
+			merchant.total_deals = merchant.total_deals.checked_add(1).ok_or(DealError::Overflow)?;
+			let mut dst = merchant_pda_ai.data.borrow_mut();
+			merchant.pack(&mut dst[..])?;
 		}
 
+		msg!("Deal {} created", deal_id);
 		Ok(())
 	}
 
@@ -205,6 +637,7 @@ impl Processor {
 		program_id: &Pubkey,
 		accounts: &[AccountInfo],
 		deal_id: u64,
+		allowlist_proof: Option<AllowlistProof>,
 	) -> ProgramResult {
 		let account_iter = &mut accounts.iter();
 		let user = next_account_info(account_iter)?; // signer
@@ -214,28 +647,26 @@ impl Processor {
 		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
 
 		msg!("mint: merchant data len {}", merchant_pda_ai.data_len());
-		let merchant: Merchant = {
-			let data = merchant_pda_ai.data.borrow();
-			Self::read_unpacked(&data)?
-		};
-		let (expected_deal_pda, _bump) = Pubkey::find_program_address(
-			&[seeds::DEAL, merchant.merchant.as_ref(), &deal_id.to_le_bytes()],
-			program_id,
-		);
-		if expected_deal_pda != *deal_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		let mut deal = Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
 
 		msg!("mint: deal data len {}", deal_pda_ai.data_len());
 		{
-			let mut deal: Deal = {
-				let data = deal_pda_ai.data.borrow();
-				Self::read_unpacked(&data)?
-			};
+			Self::assert_deal_active(&deal, Clock::get()?.unix_timestamp)?;
+			// Escrow is tracked per coupon mint, which only VerifyAndCountMint carries
+			if deal.is_paid() {
+				msg!("Paid deals are minted with VerifyAndCountMint");
+				return Err(DealError::InvalidInput.into());
+			}
+			Self::claim_raffle_win(program_id, deal_pda_ai.key, &deal, user.key, account_iter)?;
+			Self::check_allowlist(program_id, deal_pda_ai.key, &deal, user, allowlist_proof, account_iter)?;
+			Self::check_gate(&deal, user.key, account_iter)?;
 			deal.minted = deal.minted.checked_add(1).ok_or(DealError::Overflow)?;
 			if deal.minted > deal.total_supply { return Err(DealError::DealSoldOut.into()); }
 			let mut dst = deal_pda_ai.data.borrow_mut();
-			deal.serialize(&mut &mut dst[..])?;
+			deal.pack(&mut dst[..])?;
 		}
 
+		DealEvent::CouponMinted { deal: *deal_pda_ai.key, mint: None, owner: *user.key, paid: 0, payment_mint: None }.emit()?;
 		Ok(())
 	}
 
@@ -248,10 +679,18 @@ impl Processor {
 		let user = next_account_info(account_iter)?; // signer
 		let redeem_log_ai = next_account_info(account_iter)?;
 		let system_program = next_account_info(account_iter)?;
+		let deal_pda_ai = next_account_info(account_iter)?; // read
+		let user_token_ai = next_account_info(account_iter)?; // read, user's token account holding the coupon
 
 		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
+		let now = Clock::get()?.unix_timestamp;
+		let deal = Self::assert_deal_redeemable(program_id, deal_pda_ai, now)?;
+		// Only the holder redeems, so every RedeemLog records who held the coupon
+		if *user_token_ai.owner != spl_token::id() { return Err(DealError::InvalidInput.into()); }
+		let token = spl_token::state::Account::unpack(&user_token_ai.data.borrow())?;
+		if token.owner != *user.key || token.mint != mint || token.amount == 0 { return Err(DealError::Unauthorized.into()); }
 
-		let (redeem_pda, bump) = Pubkey::find_program_address(&[seeds::REDEEM, mint.as_ref()], program_id);
+		let (redeem_pda, bump) = pda::find(&[seeds::REDEEM, mint.as_ref()], program_id);
 		if redeem_pda != *redeem_log_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
 		if !redeem_log_ai.data_is_empty() { return Err(DealError::AlreadyRedeemed.into()); }
 
@@ -270,9 +709,11 @@ impl Processor {
 			&[&[seeds::REDEEM, mint.as_ref(), &[bump]]],
 		)?;
 
-		let now = Clock::get()?.unix_timestamp;
-		let log = RedeemLog { token_mint: mint, user: *user.key, redeemed_at: now };
-		log.serialize(&mut &mut redeem_log_ai.data.borrow_mut()[..])?;
+		let log = RedeemLog { token_mint: mint, user: *user.key, redeemed_at: now, deal: *deal_pda_ai.key, bump };
+		log.pack(&mut redeem_log_ai.data.borrow_mut()[..])?;
+
+		Self::release_coupon_payment(program_id, deal_pda_ai.key, &deal, &mint, false, account_iter)?;
+		DealEvent::CouponRedeemed { deal: *deal_pda_ai.key, mint, user: *user.key, burned: false }.emit()?;
 		Ok(())
 	}
 
@@ -283,26 +724,21 @@ impl Processor {
 		rating: u8,
 		comment: alloc::string::String,
 	) -> ProgramResult {
-		if rating == 0 || rating > 5 { return Err(DealError::InvalidInput.into()); }
-		if comment.len() > MAX_COMMENT_LEN { return Err(DealError::InvalidInput.into()); }
+		Self::assert_review_input(rating, &comment)?;
 
 		let account_iter = &mut accounts.iter();
 		let user = next_account_info(account_iter)?; // signer
-		let merchant_pda_ai = next_account_info(account_iter)?;
-		let deal_pda_ai = next_account_info(account_iter)?;
+		let merchant_pda_ai = next_account_info(account_iter)?; // write
+		let deal_pda_ai = next_account_info(account_iter)?; // write
 		let review_pda_ai = next_account_info(account_iter)?;
 		let system_program = next_account_info(account_iter)?;
+		// Then the reviewer's RedeemLog, or their coupon token account followed by the coupon's escrow record
 
 		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
+		Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
+		Self::assert_verified_purchase(program_id, user.key, deal_pda_ai.key, account_iter)?;
 
-		let merchant: Merchant = Self::read_unpacked(&merchant_pda_ai.data.borrow())?;
-		let (expected_deal_pda, _bump) = Pubkey::find_program_address(
-			&[seeds::DEAL, merchant.merchant.as_ref(), &deal_id.to_le_bytes()],
-			program_id,
-		);
-		if expected_deal_pda != *deal_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
-
-		let (review_pda, bump) = Pubkey::find_program_address(&[seeds::REVIEW, deal_pda_ai.key.as_ref(), user.key.as_ref()], program_id);
+		let (review_pda, bump) = pda::find(&[seeds::REVIEW, deal_pda_ai.key.as_ref(), user.key.as_ref()], program_id);
 		if review_pda != *review_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
 		if !review_pda_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
 
@@ -322,8 +758,366 @@ impl Processor {
 		)?;
 
 		let now = Clock::get()?.unix_timestamp;
-		let review = Review { user: *user.key, deal: *deal_pda_ai.key, rating, comment, created_at: now };
-		review.serialize(&mut &mut review_pda_ai.data.borrow_mut()[..])?;
+		let review = Review { user: *user.key, deal: *deal_pda_ai.key, rating, comment, created_at: now, verified_purchase: true, edited_at: 0, flags: 0, hidden: false, reply_bump: None, bump };
+		review.pack(&mut review_pda_ai.data.borrow_mut()[..])?;
+		Self::update_ratings(program_id, merchant_pda_ai, deal_pda_ai, None, Some(rating))?;
+		DealEvent::ReviewAdded { deal: *deal_pda_ai.key, review: *review_pda_ai.key, user: *user.key, rating }.emit()?;
+		Ok(())
+	}
+
+	// Swaps a review's rating in the deal and merchant totals; unverified legacy reviews were never counted
+	fn update_ratings(program_id: &Pubkey, merchant_pda_ai: &AccountInfo, deal_pda_ai: &AccountInfo, old: Option<u8>, new: Option<u8>) -> ProgramResult {
+		let mut merchant: Merchant = Self::load(program_id, merchant_pda_ai)?;
+		let mut deal: Deal = Self::load(program_id, deal_pda_ai)?;
+		for (sum, count) in [(&mut merchant.rating_sum, &mut merchant.rating_count), (&mut deal.rating_sum, &mut deal.rating_count)] {
+			if let Some(old) = old {
+				*sum = sum.checked_sub(old as u64).ok_or(DealError::Overflow)?;
+				*count = count.checked_sub(1).ok_or(DealError::Overflow)?;
+			}
+			if let Some(new) = new {
+				*sum = sum.checked_add(new as u64).ok_or(DealError::Overflow)?;
+				*count = count.checked_add(1).ok_or(DealError::Overflow)?;
+			}
+		}
+		merchant.pack(&mut merchant_pda_ai.data.borrow_mut()[..])?;
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	fn assert_review_input(rating: u8, comment: &str) -> ProgramResult {
+		if rating == 0 || rating > 5 { return Err(DealError::InvalidInput.into()); }
+		if comment.len() > MAX_COMMENT_LEN { return Err(DealError::InvalidInput.into()); }
+		Ok(())
+	}
+
+	// The user's existing review of the deal
+	fn read_review(program_id: &Pubkey, user: &Pubkey, deal_pda_ai: &AccountInfo, review_pda_ai: &AccountInfo) -> Result<Review, ProgramError> {
+		let review: Review = Self::load_pda(program_id, review_pda_ai)?;
+		if review.deal != *deal_pda_ai.key || review.user != *user { return Err(DealError::PdaDerivationMismatch.into()); }
+		Ok(review)
+	}
+
+	fn process_update_review(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		rating: u8,
+		comment: alloc::string::String,
+	) -> ProgramResult {
+		Self::assert_review_input(rating, &comment)?;
+
+		let account_iter = &mut accounts.iter();
+		let user = next_account_info(account_iter)?; // signer
+		let merchant_pda_ai = next_account_info(account_iter)?; // write
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let review_pda_ai = next_account_info(account_iter)?; // write
+
+		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
+		Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
+		let mut review = Self::read_review(program_id, user.key, deal_pda_ai, review_pda_ai)?;
+		if review.verified_purchase { Self::update_ratings(program_id, merchant_pda_ai, deal_pda_ai, Some(review.rating), Some(rating))?; }
+
+		review.rating = rating;
+		review.comment = comment;
+		review.edited_at = Clock::get()?.unix_timestamp;
+		review.pack(&mut review_pda_ai.data.borrow_mut()[..])?;
+		DealEvent::ReviewUpdated { deal: *deal_pda_ai.key, review: *review_pda_ai.key, user: *user.key, rating }.emit()?;
+		Ok(())
+	}
+
+	fn process_delete_review(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let user = next_account_info(account_iter)?; // signer, write
+		let merchant_pda_ai = next_account_info(account_iter)?; // write
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let review_pda_ai = next_account_info(account_iter)?; // write
+		let reply_pda_ai = next_account_info(account_iter)?; // write, only read when the review has a reply
+		let merchant_ai = next_account_info(account_iter)?; // write, receives the reply's rent
+
+		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
+		Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
+		let review = Self::read_review(program_id, user.key, deal_pda_ai, review_pda_ai)?;
+		// Deleting would let the author post the hidden review again, clean
+		if review.hidden { return Err(DealError::ReviewHidden.into()); }
+		if review.verified_purchase { Self::update_ratings(program_id, merchant_pda_ai, deal_pda_ai, Some(review.rating), None)?; }
+
+		// A reply answers this review only, so it can't be left behind for a later review at the same address
+		if let Some(bump) = review.reply_bump {
+			if pda::create(&[seeds::REPLY, review_pda_ai.key.as_ref()], bump, program_id) != Some(*reply_pda_ai.key) {
+				return Err(DealError::PdaDerivationMismatch.into());
+			}
+			let reply: ReviewReply = Self::load(program_id, reply_pda_ai)?;
+			if reply.merchant != *merchant_ai.key { return Err(DealError::Unauthorized.into()); }
+			Self::close_program_account(reply_pda_ai, merchant_ai)?;
+		}
+		Self::close_program_account(review_pda_ai, user)?;
+		DealEvent::ReviewDeleted { deal: *deal_pda_ai.key, review: *review_pda_ai.key, user: *user.key }.emit()
+	}
+
+	// Reviewers prove they redeemed a coupon of the deal with its RedeemLog, which is only written for the coupon's
+	// holder, or that they still hold one with their token account. Either is followed by the coupon's record, which
+	// ties the mint to the deal it was counted for, so a self-made mint proves nothing.
+	fn assert_verified_purchase<'a, 'b: 'a>(
+		program_id: &Pubkey,
+		user: &Pubkey,
+		deal: &Pubkey,
+		account_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+	) -> ProgramResult {
+		let proof_ai = next_account_info(account_iter)?;
+		let mint = if proof_ai.owner == program_id {
+			let log: RedeemLog = Self::load(program_id, proof_ai).map_err(|_| DealError::ReviewerNotVerified)?;
+			Self::assert_address(program_id, proof_ai, &log)?;
+			if log.user != *user || log.deal != *deal { return Err(DealError::ReviewerNotVerified.into()); }
+			log.token_mint
+		} else {
+			if *proof_ai.owner != spl_token::id() { return Err(DealError::ReviewerNotVerified.into()); }
+			let token = spl_token::state::Account::unpack(&proof_ai.data.borrow())?;
+			if token.owner != *user || token.amount == 0 { return Err(DealError::ReviewerNotVerified.into()); }
+			token.mint
+		};
+		let coupon_ai = next_account_info(account_iter)?;
+		if coupon_ai.owner != program_id { return Err(DealError::ReviewerNotVerified.into()); }
+		let coupon: Coupon = Self::load_pda(program_id, coupon_ai)?;
+		if coupon.mint != mint { return Err(DealError::PdaDerivationMismatch.into()); }
+		if coupon.deal != *deal || coupon.state == CouponState::Refunded { return Err(DealError::ReviewerNotVerified.into()); }
+		Ok(())
+	}
+
+	fn process_reply_to_review(program_id: &Pubkey, accounts: &[AccountInfo], comment: alloc::string::String) -> ProgramResult {
+		if comment.is_empty() || comment.len() > MAX_REPLY_LEN { return Err(DealError::InvalidInput.into()); }
+
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?;
+		let review_pda_ai = next_account_info(account_iter)?; // write, records the reply's bump
+		let reply_pda_ai = next_account_info(account_iter)?; // write
+		let system_program = next_account_info(account_iter)?;
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut review = Self::load_pda::<Review>(program_id, review_pda_ai)?;
+		if review.deal != *deal_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		let deal: Deal = Self::load(program_id, deal_pda_ai)?;
+		if deal.merchant != *payer.key { return Err(DealError::Unauthorized.into()); }
+		if review.reply_bump.is_some() { return Err(DealError::AlreadyInitialized.into()); }
+
+		let (reply_pda, bump) = pda::find(&[seeds::REPLY, review_pda_ai.key.as_ref()], program_id);
+		if reply_pda != *reply_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		if !reply_pda_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
+
+		let rent = solana_program::rent::Rent::get()?;
+		let create_ix = system_instruction::create_account(
+			payer.key,
+			reply_pda_ai.key,
+			rent.minimum_balance(ReviewReply::space()),
+			ReviewReply::space() as u64,
+			program_id,
+		);
+		solana_program::program::invoke_signed(
+			&create_ix,
+			&[payer.clone(), reply_pda_ai.clone(), system_program.clone()],
+			&[&[seeds::REPLY, review_pda_ai.key.as_ref(), &[bump]]],
+		)?;
+
+		let reply = ReviewReply { review: *review_pda_ai.key, merchant: *payer.key, comment, created_at: Clock::get()?.unix_timestamp, bump };
+		reply.pack(&mut reply_pda_ai.data.borrow_mut()[..])?;
+		// DeleteReview finds the reply through this bump instead of searching for it
+		review.reply_bump = Some(bump);
+		review.pack(&mut review_pda_ai.data.borrow_mut()[..])?;
+		DealEvent::ReviewReplied { review: *review_pda_ai.key, reply: *reply_pda_ai.key, merchant: *payer.key }.emit()
+	}
+
+	// Upgrade authority recorded in the ProgramData account of an upgradeable program
+	fn upgrade_authority(program_id: &Pubkey, program_ai: &AccountInfo, programdata_ai: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+		use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+		if program_ai.key != program_id || *program_ai.owner != bpf_loader_upgradeable::id() { return Err(DealError::InvalidInput.into()); }
+		match bincode::deserialize(&program_ai.data.borrow()) {
+			Ok(UpgradeableLoaderState::Program { programdata_address }) if programdata_address == *programdata_ai.key => {}
+			_ => return Err(DealError::InvalidInput.into()),
+		}
+		// The program's bytes follow the ProgramData header, and bincode ignores them
+		match bincode::deserialize(&programdata_ai.data.borrow()) {
+			Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address, .. }) => Ok(upgrade_authority_address),
+			_ => Err(DealError::InvalidInput.into()),
+		}
+	}
+
+	fn process_set_admin(program_id: &Pubkey, accounts: &[AccountInfo], admin: Pubkey) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer, program upgrade authority
+		let config_ai = next_account_info(account_iter)?; // write
+		let program_ai = next_account_info(account_iter)?; // this program
+		let programdata_ai = next_account_info(account_iter)?;
+		let system_program = next_account_info(account_iter)?;
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		if Self::upgrade_authority(program_id, program_ai, programdata_ai)? != Some(*payer.key) { return Err(DealError::Unauthorized.into()); }
+		let mut config = if config_ai.data_is_empty() {
+			let (config_pda, bump) = pda::find(&[seeds::CONFIG], program_id);
+			if config_pda != *config_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+			let rent = solana_program::rent::Rent::get()?;
+			let create_ix = system_instruction::create_account(payer.key, config_ai.key, rent.minimum_balance(Config::space()), Config::space() as u64, program_id);
+			solana_program::program::invoke_signed(&create_ix, &[payer.clone(), config_ai.clone(), system_program.clone()], &[&[seeds::CONFIG, &[bump]]])?;
+			Config { admin, bump }
+		} else {
+			Self::load_pda(program_id, config_ai)?
+		};
+		config.admin = admin;
+		config.pack(&mut config_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	fn process_flag_review(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let flagger = next_account_info(account_iter)?; // signer
+		let review_ai = next_account_info(account_iter)?; // write
+		let flag_ai = next_account_info(account_iter)?; // write
+		let system_program = next_account_info(account_iter)?;
+
+		if !flagger.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut review = Self::load_pda::<Review>(program_id, review_ai)?;
+		let (flag_pda, bump) = pda::find(&[seeds::FLAG, review_ai.key.as_ref(), flagger.key.as_ref()], program_id);
+		if flag_pda != *flag_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		if !flag_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
+
+		let rent = solana_program::rent::Rent::get()?;
+		let create_ix = system_instruction::create_account(flagger.key, flag_ai.key, rent.minimum_balance(ReviewFlag::space()), ReviewFlag::space() as u64, program_id);
+		solana_program::program::invoke_signed(
+			&create_ix,
+			&[flagger.clone(), flag_ai.clone(), system_program.clone()],
+			&[&[seeds::FLAG, review_ai.key.as_ref(), flagger.key.as_ref(), &[bump]]],
+		)?;
+		let flag = ReviewFlag { review: *review_ai.key, flagger: *flagger.key, flagged_at: Clock::get()?.unix_timestamp, bump };
+		flag.pack(&mut flag_ai.data.borrow_mut()[..])?;
+
+		review.flags = review.flags.checked_add(1).ok_or(DealError::Overflow)?;
+		review.pack(&mut review_ai.data.borrow_mut()[..])?;
+		DealEvent::ReviewFlagged { review: *review_ai.key, flagger: *flagger.key, flags: review.flags }.emit()
+	}
+
+	fn process_hide_review(program_id: &Pubkey, accounts: &[AccountInfo], hidden: bool) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let admin = next_account_info(account_iter)?; // signer
+		let config_ai = next_account_info(account_iter)?;
+		let review_ai = next_account_info(account_iter)?; // write
+
+		if !admin.is_signer { return Err(DealError::Unauthorized.into()); }
+		let config: Config = Self::load_pda(program_id, config_ai)?;
+		if config.admin != *admin.key { return Err(DealError::Unauthorized.into()); }
+
+		let mut review = Self::load_pda::<Review>(program_id, review_ai)?;
+		review.hidden = hidden;
+		review.pack(&mut review_ai.data.borrow_mut()[..])?;
+		DealEvent::ReviewHidden { review: *review_ai.key, hidden }.emit()
+	}
+
+	fn process_migrate_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant), write
+		let account_ai = next_account_info(account_iter)?; // write
+		let system_program = next_account_info(account_iter)?;
+		// remaining: for a merchant, every deal account it owns (legacy or current)
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		if account_ai.owner != program_id { return Err(DealError::IncorrectOwner.into()); }
+
+		let len = account_ai.data_len();
+		if len == DealV0::space() {
+			let old = DealV0::deserialize(&mut &account_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?;
+			if old.merchant != *payer.key { return Err(DealError::Unauthorized.into()); }
+			let (deal_pda, bump) = pda::find(&[seeds::DEAL, old.merchant.as_ref(), &old.deal_id.to_le_bytes()], program_id);
+			if deal_pda != *account_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+			// Legacy deals were always free, so they have no vault yet
+			let deal = Deal { bump, ..Deal::from(old) };
+			Self::rewrite_account(payer, account_ai, system_program, &deal, Deal::space())?;
+			msg!("Deal migrated to layout v{}", Deal::VERSION);
+			return Ok(());
+		}
+		if len == MerchantV0::space() {
+			let old = MerchantV0::deserialize(&mut &account_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?;
+			if old.merchant != *payer.key { return Err(DealError::Unauthorized.into()); }
+			let (merchant_pda, bump) = pda::find(&[seeds::MERCHANT, old.merchant.as_ref()], program_id);
+			if merchant_pda != *account_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+
+			// Ids were chosen by the merchant before the counter existed, so start above every deal passed in
+			let mut next_deal_id = u64::from(old.total_deals);
+			for deal_ai in account_iter {
+				let deal = Self::read_any_deal(program_id, deal_ai)?;
+				let (deal_pda, _bump) = pda::find(&[seeds::DEAL, old.merchant.as_ref(), &deal.deal_id.to_le_bytes()], program_id);
+				if deal.merchant != old.merchant || deal_pda != *deal_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+				next_deal_id = next_deal_id.max(deal.deal_id.checked_add(1).ok_or(DealError::Overflow)?);
+			}
+			Self::rewrite_account(payer, account_ai, system_program, &old.into_current(next_deal_id, bump), Merchant::space())?;
+			msg!("Merchant migrated to layout v{}", Merchant::VERSION);
+			return Ok(());
+		}
+		// Legacy reviews, redeem logs and listings only gain fields with fixed defaults, so anyone may pay to migrate them
+		if len == ReviewV0::space() {
+			let old = ReviewV0::deserialize(&mut &account_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?;
+			return Self::migrate_value(program_id, payer, account_ai, system_program, Review::from(old), Review::space());
+		}
+		if len == RedeemLogV0::space() {
+			let log = RedeemLog::from(RedeemLogV0::deserialize(&mut &account_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?);
+			// A RaffleEntry has the same length, but only a legacy log sits at the address of its own mint
+			if log.with_seeds(|seeds| pda::find(seeds, program_id)).0 == *account_ai.key {
+				return Self::migrate_value(program_id, payer, account_ai, system_program, log, RedeemLog::space());
+			}
+		}
+		if len == ListingV0::space() {
+			let old = ListingV0::deserialize(&mut &account_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?;
+			return Self::migrate_value(program_id, payer, account_ai, system_program, Listing::from(old), Listing::space());
+		}
+
+		// Every other program account was created with a header, and each kind has a single headed layout so far
+		msg!("Account already uses the current layout");
+		Ok(())
+	}
+
+	// Writes an account read from an older layout in the current one, once its address matches the seeds of its fields
+	fn migrate_value<'a, T: AccountState + ProgramAddress>(
+		program_id: &Pubkey,
+		payer: &AccountInfo<'a>,
+		account: &AccountInfo<'a>,
+		system_program: &AccountInfo<'a>,
+		mut value: T,
+		space: usize,
+	) -> ProgramResult {
+		let (address, bump) = value.with_seeds(|seeds| pda::find(seeds, program_id));
+		if address != *account.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		value.set_bump(bump);
+		Self::rewrite_account(payer, account, system_program, &value, space)?;
+		msg!("Account migrated to layout v{}", T::VERSION);
+		Ok(())
+	}
+
+	fn read_any_deal(program_id: &Pubkey, deal_ai: &AccountInfo) -> Result<Deal, ProgramError> {
+		if deal_ai.owner != program_id { return Err(DealError::IncorrectOwner.into()); }
+		if deal_ai.data_len() == DealV0::space() {
+			let old = DealV0::deserialize(&mut &deal_ai.data.borrow()[..]).map_err(|_| DealError::InvalidInput)?;
+			return Ok(old.into());
+		}
+		Self::load(program_id, deal_ai)
+	}
+
+	// Grows the account to `space`, tops it up to rent exemption from `payer` and writes `value` from a clean buffer
+	fn rewrite_account<'a, T: AccountState>(
+		payer: &AccountInfo<'a>,
+		account: &AccountInfo<'a>,
+		system_program: &AccountInfo<'a>,
+		value: &T,
+		space: usize,
+	) -> ProgramResult {
+		let rent = solana_program::rent::Rent::get()?;
+		let shortfall = rent.minimum_balance(space).saturating_sub(account.lamports());
+		if shortfall > 0 {
+			invoke(
+				&system_instruction::transfer(payer.key, account.key, shortfall),
+				&[payer.clone(), account.clone(), system_program.clone()],
+			)?;
+		}
+		account.realloc(space, true)?;
+		let mut dst = account.data.borrow_mut();
+		dst.fill(0);
+		value.pack(&mut dst[..])?;
 		Ok(())
 	}
 
@@ -331,6 +1125,8 @@ impl Processor {
 		program_id: &Pubkey,
 		accounts: &[AccountInfo],
 		deal_id: u64,
+		mint: Pubkey,
+		allowlist_proof: Option<AllowlistProof>,
 	) -> ProgramResult {
 		let account_iter = &mut accounts.iter();
 		let user = next_account_info(account_iter)?; // signer
@@ -340,23 +1136,21 @@ impl Processor {
 		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
 
 		// Read merchant to resolve original merchant pubkey and deal PDA
-		let merchant: Merchant = {
-			let data = merchant_pda_ai.data.borrow();
-			Self::read_unpacked(&data)?
-		};
-		let (expected_deal_pda, _bump) = Pubkey::find_program_address(&[seeds::DEAL, merchant.merchant.as_ref(), &deal_id.to_le_bytes()], program_id);
-		if expected_deal_pda != *deal_pda_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		let mut deal = Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
 
 		// Increment supply if available
 		{
-			let mut deal: Deal = {
-				let data = deal_pda_ai.data.borrow();
-				Self::read_unpacked(&data)?
-			};
+			let now = Clock::get()?.unix_timestamp;
+			Self::assert_deal_active(&deal, now)?;
 			if deal.minted >= deal.total_supply { return Err(DealError::DealSoldOut.into()); }
 			deal.minted = deal.minted.checked_add(1).ok_or(DealError::Overflow)?;
+			Self::claim_raffle_win(program_id, deal_pda_ai.key, &deal, user.key, account_iter)?;
+			Self::check_allowlist(program_id, deal_pda_ai.key, &deal, user, allowlist_proof, account_iter)?;
+			Self::check_gate(&deal, user.key, account_iter)?;
+			let paid = Self::record_minted_coupon(program_id, deal_pda_ai.key, &deal, user, &mint, now, account_iter)?;
 			let mut dst = deal_pda_ai.data.borrow_mut();
-			deal.serialize(&mut &mut dst[..])?;
+			deal.pack(&mut dst[..])?;
+			DealEvent::CouponMinted { deal: *deal_pda_ai.key, mint: Some(mint), owner: *user.key, paid, payment_mint: deal.token_price.map(|p| p.mint) }.emit()?;
 		}
 		Ok(())
 	}
@@ -373,9 +1167,12 @@ impl Processor {
 		let token_program_ai = next_account_info(account_iter)?; // spl-token program
 		let redeem_log_ai = next_account_info(account_iter)?; // pda
 		let system_program = next_account_info(account_iter)?;
+		let deal_pda_ai = next_account_info(account_iter)?; // read
 
 		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
 		if *mint_ai.key != mint { return Err(DealError::InvalidInput.into()); }
+		let now = Clock::get()?.unix_timestamp;
+		let deal = Self::assert_deal_redeemable(program_id, deal_pda_ai, now)?;
 
 		// Burn 1 token
 		let burn_ix = spl_token::instruction::burn(
@@ -397,7 +1194,7 @@ impl Processor {
 		)?;
 
 		// Write RedeemLog once
-		let (redeem_pda, bump) = Pubkey::find_program_address(&[seeds::REDEEM, mint.as_ref()], program_id);
+		let (redeem_pda, bump) = pda::find(&[seeds::REDEEM, mint.as_ref()], program_id);
 		if redeem_pda != *redeem_log_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
 		if !redeem_log_ai.data_is_empty() { return Err(DealError::AlreadyRedeemed.into()); }
 
@@ -416,13 +1213,614 @@ impl Processor {
 			&[&[seeds::REDEEM, mint.as_ref(), &[bump]]],
 		)?;
 
+		let log = RedeemLog { token_mint: mint, user: *user.key, redeemed_at: now, deal: *deal_pda_ai.key, bump };
+		log.pack(&mut redeem_log_ai.data.borrow_mut()[..])?;
+
+		Self::release_coupon_payment(program_id, deal_pda_ai.key, &deal, &mint, true, account_iter)?;
+		DealEvent::CouponRedeemed { deal: *deal_pda_ai.key, mint, user: *user.key, burned: true }.emit()?;
+		Ok(())
+	}
+
+	fn process_set_deal_status(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		status: DealStatus,
+	) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		// Cancellation is final
+		if deal.status == DealStatus::Cancelled { return Err(DealError::DealCancelled.into()); }
+		deal.status = status;
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		DealEvent::DealStatusChanged { deal: *deal_pda_ai.key, status }.emit()
+	}
+
+	fn process_recount_deals(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let merchant_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut merchant = Self::load_merchant(program_id, merchant_pda_ai, payer.key)?;
+
+		// Remaining accounts: every live deal PDA of this merchant, each passed once
+		let mut seen: alloc::vec::Vec<u64> = alloc::vec::Vec::new();
+		for deal_pda_ai in account_iter {
+			let deal: Deal = Self::load_pda(program_id, deal_pda_ai)?;
+			if deal.merchant != *payer.key || deal.deal_id >= merchant.next_deal_id { return Err(DealError::InvalidInput.into()); }
+			if seen.contains(&deal.deal_id) { return Err(DealError::InvalidInput.into()); }
+			seen.push(deal.deal_id);
+		}
+
+		merchant.total_deals = u32::try_from(seen.len()).map_err(|_| DealError::Overflow)?;
+		let mut dst = merchant_pda_ai.data.borrow_mut();
+		merchant.pack(&mut dst[..])?;
+		msg!("Merchant total_deals recounted to {}", merchant.total_deals);
+		Ok(())
+	}
+
+	fn process_set_redemption_schedule(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		schedule: Option<RedemptionSchedule>,
+	) -> ProgramResult {
+		if let Some(schedule) = &schedule {
+			if !schedule.is_valid() { return Err(DealError::InvalidInput.into()); }
+		}
+
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		deal.schedule = schedule;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_set_mint_price(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		mint_price_lamports: u64,
+	) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		// Changing the price under existing holders would make what they paid ambiguous
+		if deal.minted > 0 { return Err(DealError::DealHasMints.into()); }
+		if mint_price_lamports > 0 && deal.token_price.is_some() { return Err(DealError::InvalidInput.into()); }
+		if mint_price_lamports > 0 {
+			// Priced deals escrow payments in the deal vault: vault PDA (write) and system program follow
+			let vault_ai = next_account_info(account_iter)?;
+			let system_program = next_account_info(account_iter)?;
+			Self::create_vault_if_missing(program_id, payer, deal_pda_ai.key, &mut deal, vault_ai, system_program)?;
+		}
+		deal.mint_price_lamports = mint_price_lamports;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_set_token_mint_price(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		token_price: Option<TokenPrice>,
+	) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if deal.minted > 0 { return Err(DealError::DealHasMints.into()); }
+		if let Some(price) = &token_price {
+			if price.amount == 0 || deal.mint_price_lamports > 0 { return Err(DealError::InvalidInput.into()); }
+			// Same vault as SOL prices; it is the authority of the token account that receives payments
+			let vault_ai = next_account_info(account_iter)?;
+			let system_program = next_account_info(account_iter)?;
+			Self::create_vault_if_missing(program_id, payer, deal_pda_ai.key, &mut deal, vault_ai, system_program)?;
+		}
+		deal.token_price = token_price;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_claim_refund(program_id: &Pubkey, accounts: &[AccountInfo], mint: Pubkey) -> ProgramResult {
+		Self::refund_coupon(program_id, accounts, mint, |deal, now| {
+			if now <= deal.expiry && deal.status != DealStatus::Cancelled { return Err(DealError::RefundNotAvailable.into()); }
+			Ok(())
+		})
+	}
+
+	fn process_refund_group_deal(program_id: &Pubkey, accounts: &[AccountInfo], mint: Pubkey) -> ProgramResult {
+		Self::refund_coupon(program_id, accounts, mint, |deal, now| {
+			if !deal.is_group_pending() || now <= deal.funding_deadline { return Err(DealError::RefundNotAvailable.into()); }
+			// A deal that tipped by the deadline can still be finalized; it is not refundable
+			if deal.minted >= deal.min_participants { return Err(DealError::RefundNotAvailable.into()); }
+			Ok(())
+		})
+	}
+
+	// Shared by ClaimRefund and RefundGroupDeal, which only differ in when a refund is allowed
+	fn refund_coupon(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		mint: Pubkey,
+		assert_refundable: impl Fn(&Deal, i64) -> ProgramResult,
+	) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let holder = next_account_info(account_iter)?; // signer & burn authority
+		let holder_token_ai = next_account_info(account_iter)?; // holder's token account for the coupon mint
+		let mint_ai = next_account_info(account_iter)?; // coupon mint
+		let token_program = next_account_info(account_iter)?;
+		let deal_pda_ai = next_account_info(account_iter)?; // read
+		let coupon_ai = next_account_info(account_iter)?; // write
+		let vault_ai = next_account_info(account_iter)?; // write
+
+		if !holder.is_signer { return Err(DealError::Unauthorized.into()); }
+		if *mint_ai.key != mint || *token_program.key != spl_token::id() { return Err(DealError::InvalidInput.into()); }
+		let deal: Deal = Self::load(program_id, deal_pda_ai)?;
+		assert_refundable(&deal, Clock::get()?.unix_timestamp)?;
+
+		// Redeeming an escrowed coupon burns it and releases the payment, so a coupon still escrowed was never redeemed
+		let mut coupon = Self::read_coupon(program_id, coupon_ai, deal_pda_ai.key)?;
+		if coupon.mint != mint { return Err(DealError::PdaDerivationMismatch.into()); }
+		let vault_bump = Self::assert_vault(program_id, deal_pda_ai.key, &deal, vault_ai)?;
+
+		// Burning proves the signer holds the coupon and stops it from being redeemed later
+		let burn_ix = spl_token::instruction::burn(&spl_token::id(), holder_token_ai.key, mint_ai.key, holder.key, &[], 1)?;
+		invoke(&burn_ix, &[holder_token_ai.clone(), mint_ai.clone(), holder.clone(), token_program.clone()])?;
+
+		// Token-priced deals take the vault token account (write), holder's payment token account (write) and payment mint
+		match deal.token_price {
+			Some(price) => {
+				let vault_token = next_account_info(account_iter)?;
+				let holder_payment_token = next_account_info(account_iter)?;
+				let payment_mint = next_account_info(account_iter)?;
+				Self::transfer_tokens_checked(
+					vault_ai, vault_token, holder_payment_token, payment_mint, token_program, &price.mint, holder.key, coupon.paid,
+					&[&[seeds::VAULT, deal_pda_ai.key.as_ref(), &[vault_bump]]],
+				)?;
+			}
+			None => Self::move_lamports(vault_ai, holder, coupon.paid)?,
+		}
+		coupon.state = CouponState::Refunded;
+		coupon.pack(&mut coupon_ai.data.borrow_mut()[..])?;
+		DealEvent::Refunded {
+			deal: *deal_pda_ai.key,
+			mint,
+			holder: *holder.key,
+			amount: coupon.paid,
+			payment_mint: deal.token_price.map(|p| p.mint),
+		}
+		.emit()
+	}
+
+	fn process_set_group_buy(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		deal_id: u64,
+		min_participants: u32,
+		funding_deadline: i64,
+	) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if deal.minted > 0 { return Err(DealError::DealHasMints.into()); }
+		if min_participants > 0 {
+			if min_participants > deal.total_supply || funding_deadline > deal.expiry { return Err(DealError::InvalidInput.into()); }
+			if funding_deadline <= Clock::get()?.unix_timestamp { return Err(DealError::InvalidInput.into()); }
+		}
+		deal.min_participants = min_participants;
+		deal.funding_deadline = if min_participants > 0 { funding_deadline } else { 0 };
+		deal.group_finalized = false;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	// Accounts: merchant (signer, write), deal (write), vault (write), then for token prices the vault token account
+	// (write), merchant token account (write), payment mint and token program. Remaining accounts are coupon PDAs
+	// (write) whose payments are released; large deals can be finalized and then drained over several calls. Free
+	// group deals never created a vault and escrow nothing, so they are only marked finalized.
+	fn process_finalize_group_deal(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let vault_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if !deal.is_group_buy() { return Err(DealError::InvalidInput.into()); }
+		if deal.status == DealStatus::Cancelled { return Err(DealError::DealCancelled.into()); }
+		if deal.minted < deal.min_participants { return Err(DealError::ThresholdNotMet.into()); }
+		let vault_bump = match deal.vault_bump {
+			Some(_) => Some(Self::assert_vault(program_id, deal_pda_ai.key, &deal, vault_ai)?),
+			None => None,
+		};
+		let token_accounts = match deal.token_price {
+			Some(_) => Some((
+				next_account_info(account_iter)?,
+				next_account_info(account_iter)?,
+				next_account_info(account_iter)?,
+				next_account_info(account_iter)?,
+			)),
+			None => None,
+		};
+
+		let mut total: u64 = 0;
+		for coupon_ai in account_iter {
+			let mut coupon: Coupon = Self::load(program_id, coupon_ai)?;
+			if coupon.deal != *deal_pda_ai.key { return Err(DealError::InvalidInput.into()); }
+			// Already released (e.g. passed twice or in an earlier batch) or refunded after a cancellation
+			if coupon.state != CouponState::Escrowed { continue; }
+			total = total.checked_add(coupon.paid).ok_or(DealError::Overflow)?;
+			coupon.state = CouponState::Released;
+			coupon.pack(&mut coupon_ai.data.borrow_mut()[..])?;
+		}
+
+		if total > 0 {
+			// Escrow records only exist for deals that had a price, and so a vault
+			let vault_bump = vault_bump.ok_or(DealError::InvalidInput)?;
+			match (deal.token_price, token_accounts) {
+				(Some(price), Some((vault_token, merchant_token, payment_mint, token_program))) => {
+					Self::transfer_tokens_checked(
+						vault_ai, vault_token, merchant_token, payment_mint, token_program, &price.mint, payer.key, total,
+						&[&[seeds::VAULT, deal_pda_ai.key.as_ref(), &[vault_bump]]],
+					)?;
+				}
+				_ => Self::move_lamports(vault_ai, payer, total)?,
+			}
+		}
+		if !deal.group_finalized {
+			deal.group_finalized = true;
+			let mut dst = deal_pda_ai.data.borrow_mut();
+			deal.pack(&mut dst[..])?;
+		}
+		DealEvent::GroupFinalized {
+			deal: *deal_pda_ai.key,
+			participants: deal.minted,
+			released: total,
+			payment_mint: deal.token_price.map(|p| p.mint),
+		}
+		.emit()
+	}
+
+	fn process_set_price_curve(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64, curve: Option<PriceCurve>) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if deal.minted > 0 { return Err(DealError::DealHasMints.into()); }
+		if let Some(curve) = curve {
+			if !curve.is_valid() || curve.end_ts > deal.expiry { return Err(DealError::InvalidInput.into()); }
+			// Vault PDA (write) and system program follow, as for fixed prices
+			let vault_ai = next_account_info(account_iter)?;
+			let system_program = next_account_info(account_iter)?;
+			Self::create_vault_if_missing(program_id, payer, deal_pda_ai.key, &mut deal, vault_ai, system_program)?;
+		}
+		deal.price_curve = curve;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_set_raffle(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64, winners: u32, entry_deadline: i64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if deal.minted > 0 { return Err(DealError::DealHasMints.into()); }
+		// Existing entries would be orphaned by a new raffle
+		if deal.raffle.is_some_and(|r| r.entries > 0) { return Err(DealError::InvalidInput.into()); }
+		deal.raffle = if winners == 0 {
+			None
+		} else {
+			if winners > deal.total_supply || entry_deadline > deal.expiry { return Err(DealError::InvalidInput.into()); }
+			if entry_deadline <= Clock::get()?.unix_timestamp { return Err(DealError::InvalidInput.into()); }
+			Some(Raffle { winners, entry_deadline, entries: 0, seed: None })
+		};
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_enter_raffle(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let user = next_account_info(account_iter)?; // signer
+		let merchant_pda_ai = next_account_info(account_iter)?; // read
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let entry_ai = next_account_info(account_iter)?; // write
+		let system_program = next_account_info(account_iter)?;
+
+		if !user.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_merchant_deal(program_id, merchant_pda_ai, deal_pda_ai, deal_id)?;
+		let deal_pda = *deal_pda_ai.key;
+		let now = Clock::get()?.unix_timestamp;
+		Self::assert_deal_active(&deal, now)?;
+		let Some(mut raffle) = deal.raffle else { return Err(DealError::InvalidInput.into()) };
+		if raffle.is_drawn() || now > raffle.entry_deadline { return Err(DealError::RaffleClosed.into()); }
+
+		let (entry_pda, bump) = pda::find(&[seeds::ENTRY, deal_pda.as_ref(), user.key.as_ref()], program_id);
+		if entry_pda != *entry_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		if !entry_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
+		let rent = solana_program::rent::Rent::get()?;
+		let create_ix = system_instruction::create_account(
+			user.key,
+			entry_ai.key,
+			rent.minimum_balance(RaffleEntry::space()),
+			RaffleEntry::space() as u64,
+			program_id,
+		);
+		solana_program::program::invoke_signed(
+			&create_ix,
+			&[user.clone(), entry_ai.clone(), system_program.clone()],
+			&[&[seeds::ENTRY, deal_pda.as_ref(), user.key.as_ref(), &[bump]]],
+		)?;
+
+		let entry = RaffleEntry { deal: deal_pda, user: *user.key, index: raffle.entries, minted: false, bump };
+		entry.pack(&mut entry_ai.data.borrow_mut()[..])?;
+		raffle.entries = raffle.entries.checked_add(1).ok_or(DealError::Overflow)?;
+		deal.raffle = Some(raffle);
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		Ok(())
+	}
+
+	fn process_draw_raffle(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (any wallet)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let slot_hashes_ai = next_account_info(account_iter)?; // SlotHashes sysvar
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		if *slot_hashes_ai.key != solana_program::sysvar::slot_hashes::id() { return Err(DealError::InvalidInput.into()); }
+		let mut deal: Deal = Self::load_pda(program_id, deal_pda_ai)?;
+		if deal.deal_id != deal_id { return Err(DealError::PdaDerivationMismatch.into()); }
+		let Some(mut raffle) = deal.raffle else { return Err(DealError::InvalidInput.into()) };
+		if raffle.is_drawn() { return Err(DealError::AlreadyInitialized.into()); }
+		// Whoever draws picks the slot hash. Anyone can draw as soon as entries close, entrants included, so the
+		// merchant can't sit on the draw until a hash favours them.
+		if Clock::get()?.unix_timestamp <= raffle.entry_deadline { return Err(DealError::RaffleStillOpen.into()); }
+
+		// SlotHashes is too large to deserialize on-chain; read the newest (slot, hash) pair after the u64 length
+		let data = slot_hashes_ai.data.borrow();
+		if data.len() < 8 + 8 + 32 || data[..8] == [0u8; 8] { return Err(DealError::InvalidInput.into()); }
+		let recent_hash = &data[16..48];
+		let seed = solana_program::hash::hashv(&[recent_hash, deal_pda_ai.key.as_ref(), &raffle.entries.to_le_bytes()]);
+		raffle.seed = Some(seed.to_bytes());
+		drop(data);
+		deal.raffle = Some(raffle);
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		msg!("Raffle drawn: {} winners from {} entries", raffle.winners.min(raffle.entries), raffle.entries);
+		Ok(())
+	}
+
+	fn process_set_allowlist(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64, root: [u8; 32]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		// Swapping the root is allowed at any time, e.g. to add customers; allowance PDAs keep counting across roots
+		deal.allowlist_root = root;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	fn process_set_gate(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64, gate: Option<DealGate>) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		if let Some(DealGate::TokenBalance { min_amount: 0, .. }) = gate { return Err(DealError::InvalidInput.into()); }
+		deal.gate = gate;
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	// Airdropped coupons skip raffle, allowlist, gate and payment checks: the merchant is choosing the recipients
+	fn process_airdrop_coupons(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let system_program = next_account_info(account_iter)?;
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
+		Self::assert_deal_active(&deal, Clock::get()?.unix_timestamp)?;
+		// A pending group deal counts paying participants in `minted`; free coupons would tip it with nobody paying
+		if deal.is_group_pending() { return Err(DealError::GroupDealNotFinalized.into()); }
+
+		// Remaining accounts: per recipient the coupon token account holding its single coupon, the coupon mint, then
+		// the coupon's record PDA (write). The record ties the mint to this deal and can only be created once, so a
+		// coupon is never counted twice, within a batch or across batches.
+		let mut count: u32 = 0;
+		while let Some(token_ai) = account_iter.next() {
+			let mint_ai = next_account_info(account_iter)?;
+			let coupon_ai = next_account_info(account_iter)?;
+			if *token_ai.owner != spl_token::id() { return Err(DealError::InvalidInput.into()); }
+			let token = spl_token::state::Account::unpack(&token_ai.data.borrow())?;
+			if token.amount != 1 || token.mint != *mint_ai.key { return Err(DealError::InvalidInput.into()); }
+			Self::assert_coupon_mint(mint_ai)?;
+			let coupon = Coupon { mint: token.mint, deal: *deal_pda_ai.key, paid: 0, state: CouponState::Released, bump: 0 };
+			Self::create_coupon_record(program_id, payer, coupon_ai, system_program, coupon)?;
+			msg!("Airdropped coupon {} to {}", token.mint, token.owner);
+			DealEvent::CouponMinted { deal: *deal_pda_ai.key, mint: Some(token.mint), owner: token.owner, paid: 0, payment_mint: None }.emit()?;
+			count = count.checked_add(1).ok_or(DealError::Overflow)?;
+		}
+		if count == 0 { return Err(DealError::InvalidInput.into()); }
+
+		deal.minted = deal.minted.checked_add(count).ok_or(DealError::Overflow)?;
+		if deal.minted > deal.total_supply { return Err(DealError::DealSoldOut.into()); }
+		let mut dst = deal_pda_ai.data.borrow_mut();
+		deal.pack(&mut dst[..])?;
+		Ok(())
+	}
+
+	// Airdropped and parked coupons must be one-of-one NFTs
+	fn assert_coupon_mint(mint_ai: &AccountInfo) -> ProgramResult {
+		if *mint_ai.owner != spl_token::id() { return Err(DealError::InvalidInput.into()); }
+		let mint = spl_token::state::Mint::unpack(&mint_ai.data.borrow())?;
+		if mint.supply != 1 || mint.decimals != 0 { return Err(DealError::InvalidInput.into()); }
+		Ok(())
+	}
+
+	fn read_claim(program_id: &Pubkey, claim_ai: &AccountInfo, claim_hash: &[u8; 32]) -> Result<ClaimLink, ProgramError> {
+		let claim: ClaimLink = Self::load_pda(program_id, claim_ai)?;
+		if claim.claim_hash != *claim_hash { return Err(DealError::PdaDerivationMismatch.into()); }
+		Ok(claim)
+	}
+
+	// Hands the parked coupon to `destination` and closes the claim token account and claim PDA, refunding the merchant
+	#[allow(clippy::too_many_arguments)]
+	fn release_claim<'a>(
+		claim_ai: &AccountInfo<'a>,
+		claim: &ClaimLink,
+		claim_token_ai: &AccountInfo<'a>,
+		destination: &AccountInfo<'a>,
+		recipient: &Pubkey,
+		mint_ai: &AccountInfo<'a>,
+		token_program: &AccountInfo<'a>,
+		merchant: &AccountInfo<'a>,
+	) -> ProgramResult {
+		let signer_seeds: &[&[u8]] = &[seeds::CLAIM, &claim.claim_hash, &[claim.bump]];
+		Self::transfer_tokens_checked(claim_ai, claim_token_ai, destination, mint_ai, token_program, &claim.coupon_mint, recipient, 1, &[signer_seeds])?;
+		let close_ix = spl_token::instruction::close_account(&spl_token::id(), claim_token_ai.key, merchant.key, claim_ai.key, &[])?;
+		solana_program::program::invoke_signed(&close_ix, &[claim_token_ai.clone(), merchant.clone(), claim_ai.clone(), token_program.clone()], &[signer_seeds])?;
+		Self::close_program_account(claim_ai, merchant)
+	}
+
+	fn process_create_claim(program_id: &Pubkey, accounts: &[AccountInfo], deal_id: u64, claim_hash: [u8; 32], expires_at: i64) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant)
+		let deal_pda_ai = next_account_info(account_iter)?; // write
+		let claim_ai = next_account_info(account_iter)?; // write
+		let merchant_token_ai = next_account_info(account_iter)?; // write, holds the coupon
+		let claim_token_ai = next_account_info(account_iter)?; // write, token account owned by the claim PDA
+		let mint_ai = next_account_info(account_iter)?; // coupon mint
+		let token_program = next_account_info(account_iter)?;
+		let system_program = next_account_info(account_iter)?;
+		let coupon_ai = next_account_info(account_iter)?; // write, the coupon's record PDA
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let mut deal = Self::load_deal(program_id, deal_pda_ai, payer.key, deal_id)?;
 		let now = Clock::get()?.unix_timestamp;
-		let log = RedeemLog { token_mint: mint, user: *user.key, redeemed_at: now };
-		log.serialize(&mut &mut redeem_log_ai.data.borrow_mut()[..])?;
+		Self::assert_deal_active(&deal, now)?;
+		// Parked coupons are counted in `minted` like airdrops, so they also wait for a group deal to tip
+		if deal.is_group_pending() { return Err(DealError::GroupDealNotFinalized.into()); }
+		if expires_at <= now { return Err(DealError::InvalidInput.into()); }
+
+		Self::assert_coupon_mint(mint_ai)?;
+
+		let (claim_pda, bump) = pda::find(&[seeds::CLAIM, &claim_hash], program_id);
+		if claim_pda != *claim_ai.key { return Err(DealError::PdaDerivationMismatch.into()); }
+		if !claim_ai.data_is_empty() { return Err(DealError::AlreadyInitialized.into()); }
+		let rent = solana_program::rent::Rent::get()?;
+		let create_ix = system_instruction::create_account(
+			payer.key,
+			claim_ai.key,
+			rent.minimum_balance(ClaimLink::space()),
+			ClaimLink::space() as u64,
+			program_id,
+		);
+		solana_program::program::invoke_signed(
+			&create_ix,
+			&[payer.clone(), claim_ai.clone(), system_program.clone()],
+			&[&[seeds::CLAIM, &claim_hash, &[bump]]],
+		)?;
+		Self::transfer_tokens_checked(payer, merchant_token_ai, claim_token_ai, mint_ai, token_program, mint_ai.key, &claim_pda, 1, &[])?;
+		let coupon = Coupon { mint: *mint_ai.key, deal: *deal_pda_ai.key, paid: 0, state: CouponState::Released, bump: 0 };
+		Self::create_coupon_record(program_id, payer, coupon_ai, system_program, coupon)?;
+
+		let claim = ClaimLink { merchant: *payer.key, deal: *deal_pda_ai.key, coupon_mint: *mint_ai.key, claim_hash, expires_at, bump };
+		claim.pack(&mut claim_ai.data.borrow_mut()[..])?;
+		deal.minted = deal.minted.checked_add(1).ok_or(DealError::Overflow)?;
+		if deal.minted > deal.total_supply { return Err(DealError::DealSoldOut.into()); }
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		// Parked with the claim PDA until someone claims it
+		DealEvent::CouponMinted { deal: *deal_pda_ai.key, mint: Some(*mint_ai.key), owner: claim_pda, paid: 0, payment_mint: None }.emit()?;
 		Ok(())
 	}
 
-	fn process_list_nft(program_id: &Pubkey, accounts: &[AccountInfo], price: u64) -> ProgramResult {
+	fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo], claim_hash: [u8; 32]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let ephemeral = next_account_info(account_iter)?; // signer, keypair from the link
+		let claim_ai = next_account_info(account_iter)?; // write
+		let claim_token_ai = next_account_info(account_iter)?; // write
+		let destination = next_account_info(account_iter)?; // write, recipient's token account for the coupon mint
+		let recipient = next_account_info(account_iter)?; // any wallet
+		let mint_ai = next_account_info(account_iter)?; // coupon mint
+		let token_program = next_account_info(account_iter)?;
+		let merchant = next_account_info(account_iter)?; // write, gets the rent back
+
+		if !ephemeral.is_signer { return Err(DealError::Unauthorized.into()); }
+		if solana_program::hash::hash(ephemeral.key.as_ref()).to_bytes() != claim_hash { return Err(DealError::Unauthorized.into()); }
+		let claim = Self::read_claim(program_id, claim_ai, &claim_hash)?;
+		if Clock::get()?.unix_timestamp > claim.expires_at { return Err(DealError::ClaimExpired.into()); }
+		if *merchant.key != claim.merchant { return Err(DealError::InvalidInput.into()); }
+
+		Self::release_claim(claim_ai, &claim, claim_token_ai, destination, recipient.key, mint_ai, token_program, merchant)?;
+		msg!("Coupon {} claimed by {}", claim.coupon_mint, recipient.key);
+		DealEvent::Claimed { deal: claim.deal, mint: claim.coupon_mint, owner: *recipient.key }.emit()
+	}
+
+	fn process_reclaim_expired(program_id: &Pubkey, accounts: &[AccountInfo], claim_hash: [u8; 32]) -> ProgramResult {
+		let account_iter = &mut accounts.iter();
+		let payer = next_account_info(account_iter)?; // signer (merchant), write
+		let claim_ai = next_account_info(account_iter)?; // write
+		let deal_pda_ai = next_account_info(account_iter)?; // write, the claim's deal
+		let claim_token_ai = next_account_info(account_iter)?; // write
+		let merchant_token_ai = next_account_info(account_iter)?; // write
+		let mint_ai = next_account_info(account_iter)?; // coupon mint
+		let token_program = next_account_info(account_iter)?;
+		let coupon_ai = next_account_info(account_iter)?; // write, the coupon's record PDA
+
+		if !payer.is_signer { return Err(DealError::Unauthorized.into()); }
+		let claim = Self::read_claim(program_id, claim_ai, &claim_hash)?;
+		if claim.merchant != *payer.key { return Err(DealError::Unauthorized.into()); }
+		if Clock::get()?.unix_timestamp <= claim.expires_at { return Err(DealError::ClaimNotExpired.into()); }
+
+		// CreateClaim counted the parked coupon as minted; it goes back to the merchant unissued
+		if *deal_pda_ai.key != claim.deal { return Err(DealError::PdaDerivationMismatch.into()); }
+		let mut deal: Deal = Self::load(program_id, deal_pda_ai)?;
+		deal.minted = deal.minted.checked_sub(1).ok_or(DealError::Overflow)?;
+		deal.pack(&mut deal_pda_ai.data.borrow_mut()[..])?;
+		// and stops being a coupon of the deal, so it can be parked or airdropped again
+		let coupon: Coupon = Self::load_pda(program_id, coupon_ai)?;
+		if coupon.mint != claim.coupon_mint || coupon.deal != claim.deal { return Err(DealError::PdaDerivationMismatch.into()); }
+
+		Self::release_claim(claim_ai, &claim, claim_token_ai, merchant_token_ai, payer.key, mint_ai, token_program, payer)?;
+		Self::close_program_account(coupon_ai, payer)?;
+		DealEvent::CouponReclaimed { deal: claim.deal, mint: claim.coupon_mint }.emit()
+	}
+
+	fn process_list_nft(program_id: &Pubkey, accounts: &[AccountInfo], price: u64, payment_mint: Option<Pubkey>) -> ProgramResult {
 		let accounts_iter = &mut accounts.iter();
 		let seller = next_account_info(accounts_iter)?;
 		let listing_pda = next_account_info(accounts_iter)?;
@@ -436,7 +1834,7 @@ impl Processor {
 		if price == 0 { return Err(DealError::InvalidInput.into()); }
 
 		// Verify listing PDA
-		let (listing_pda_pubkey, listing_bump) = Pubkey::find_program_address(
+		let (listing_pda_pubkey, listing_bump) = pda::find(
 			&[seeds::LISTING, nft_mint.key.as_ref(), seller.key.as_ref()],
 			program_id,
 		);
@@ -480,10 +1878,16 @@ impl Processor {
 			nft_mint: *nft_mint.key,
 			price,
 			created_at: clock.unix_timestamp,
+			payment_mint,
+			bump: listing_bump,
 		};
-		listing.serialize(&mut &mut listing_pda.data.borrow_mut()[..])?;
+		listing.pack(&mut listing_pda.data.borrow_mut()[..])?;
+		DealEvent::Listed { listing: *listing_pda.key, nft_mint: listing.nft_mint, seller: listing.seller, price, payment_mint }.emit()?;
 
-		msg!("NFT listed for {} lamports", price);
+		match payment_mint {
+			Some(mint) => msg!("NFT listed for {} of token {}", price, mint),
+			None => msg!("NFT listed for {} lamports", price),
+		}
 		Ok(())
 	}
 
@@ -501,30 +1905,34 @@ impl Processor {
 
 		if !buyer.is_signer { return Err(ProgramError::MissingRequiredSignature); }
 
-		// Verify listing PDA
-		let (listing_pda_pubkey, _) = Pubkey::find_program_address(
-			&[seeds::LISTING, nft_mint.key.as_ref(), seller.key.as_ref()],
-			program_id,
-		);
-		if listing_pda_pubkey != *listing_pda.key {
+		// Deserialize listing and verify its PDA
+		let listing: Listing = Self::load(program_id, listing_pda)?;
+		if listing.nft_mint != *nft_mint.key || Self::assert_address(program_id, listing_pda, &listing).is_err() {
 			msg!("Invalid listing PDA");
 			return Err(ProgramError::InvalidAccountData);
 		}
 
-		// Deserialize listing
-		let listing = Listing::try_from_slice(&listing_pda.data.borrow())?;
-
 		// Verify seller matches
 		if listing.seller != *seller.key { return Err(DealError::Unauthorized.into()); }
 
-		// Transfer SOL from buyer to seller
-		invoke(
-			&system_instruction::transfer(buyer.key, seller.key, listing.price),
-			&[buyer.clone(), seller.clone(), system_program.clone()],
-		)?;
+		// Pay the seller: token-priced listings take the buyer's and seller's token accounts and the payment mint after the system program
+		match listing.payment_mint {
+			Some(payment_mint) => {
+				let buyer_payment_token = next_account_info(accounts_iter)?;
+				let seller_payment_token = next_account_info(accounts_iter)?;
+				let payment_mint_ai = next_account_info(accounts_iter)?;
+				Self::transfer_tokens_checked(buyer, buyer_payment_token, seller_payment_token, payment_mint_ai, token_program, &payment_mint, seller.key, listing.price, &[])?;
+			}
+			None => {
+				invoke(
+					&system_instruction::transfer(buyer.key, seller.key, listing.price),
+					&[buyer.clone(), seller.clone(), system_program.clone()],
+				)?;
+			}
+		}
 
 		// Verify and derive escrow PDA
-		let (escrow_pda_pubkey, escrow_bump) = Pubkey::find_program_address(&[seeds::ESCROW, nft_mint.key.as_ref()], program_id);
+		let (escrow_pda_pubkey, escrow_bump) = pda::find(&[seeds::ESCROW, nft_mint.key.as_ref()], program_id);
 		if escrow_pda_pubkey != *escrow_pda.key {
 			msg!("Invalid escrow PDA");
 			return Err(ProgramError::InvalidAccountData);
@@ -550,7 +1958,19 @@ impl Processor {
 		**listing_pda.lamports.borrow_mut() = 0;
 		listing_pda.data.borrow_mut().fill(0);
 
-		msg!("NFT purchased for {} lamports", listing.price);
+		match listing.payment_mint {
+			Some(mint) => msg!("NFT purchased for {} of token {}", listing.price, mint),
+			None => msg!("NFT purchased for {} lamports", listing.price),
+		}
+		DealEvent::Sold {
+			listing: *listing_pda.key,
+			nft_mint: listing.nft_mint,
+			seller: listing.seller,
+			buyer: *buyer.key,
+			price: listing.price,
+			payment_mint: listing.payment_mint,
+		}
+		.emit()?;
 		Ok(())
 	}
 }
@@ -1,15 +1,140 @@
 extern crate alloc;
 
 use alloc::string::String;
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{
+	maybestd::io::{Cursor, Error, ErrorKind, Result},
+	BorshDeserialize, BorshSerialize,
+};
 use borsh_derive::{BorshDeserialize as BorshDeserializeDerive, BorshSerialize as BorshSerializeDerive};
 use solana_program::pubkey::Pubkey;
 
+use crate::error::DealError;
+
 pub const MAX_NAME_LEN: usize = 64;
 pub const MAX_URI_LEN: usize = 200;
 pub const MAX_TITLE_LEN: usize = 80;
 pub const MAX_DESC_LEN: usize = 512;
 pub const MAX_COMMENT_LEN: usize = 280;
+pub const MAX_REPLY_LEN: usize = 500;
+pub const MAX_SKU_LEN: usize = 32;
+
+// Every program account starts with [AccountKind, layout version] ahead of its borsh body; `space()` includes it
+pub const HEADER_LEN: usize = 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum AccountKind {
+	Merchant = 1,
+	Deal = 2,
+	Review = 3,
+	RedeemLog = 4,
+	Listing = 5,
+	Coupon = 6,
+	RaffleEntry = 7,
+	AllowlistMints = 8,
+	ClaimLink = 9,
+	ReviewReply = 10,
+	ReviewFlag = 11,
+	Config = 12,
+}
+
+pub trait AccountState: BorshSerialize + BorshDeserialize {
+	const KIND: AccountKind;
+	// Bumped whenever the borsh layout changes
+	const VERSION: u8;
+
+	// Checks the [kind, version] header of `data` against this type
+	fn check_header(data: &[u8]) -> core::result::Result<(), DealError> {
+		match data {
+			[kind, ..] if *kind != Self::KIND as u8 => Err(DealError::AccountKindMismatch),
+			[_, version, ..] if *version != Self::VERSION => Err(DealError::AccountVersionMismatch),
+			[_, _, ..] => Ok(()),
+			_ => Err(DealError::InvalidInput),
+		}
+	}
+
+	// Decodes account data written by `pack`; trailing bytes are the unused tail of the allocation
+	fn unpack(data: &[u8]) -> Result<Self> {
+		match Self::check_header(data) {
+			Ok(()) => Self::deserialize_reader(&mut Cursor::new(&data[HEADER_LEN..])),
+			Err(DealError::InvalidInput) => Err(Error::new(ErrorKind::UnexpectedEof, "account too short")),
+			Err(_) => Err(Error::new(ErrorKind::InvalidData, "account header mismatch")),
+		}
+	}
+
+	fn pack(&self, dst: &mut [u8]) -> Result<()> {
+		if dst.len() < HEADER_LEN { return Err(Error::new(ErrorKind::WriteZero, "account too short")); }
+		dst[0] = Self::KIND as u8;
+		dst[1] = Self::VERSION;
+		self.serialize(&mut &mut dst[HEADER_LEN..])
+	}
+}
+
+macro_rules! account_state {
+	($($ty:ident => $version:expr),* $(,)?) => {
+		$(impl AccountState for $ty {
+			const KIND: AccountKind = AccountKind::$ty;
+			const VERSION: u8 = $version;
+		})*
+	};
+}
+
+account_state! {
+	Merchant => 1,
+	Deal => 1,
+	Review => 1,
+	RedeemLog => 1,
+	Listing => 1,
+	Coupon => 1,
+	RaffleEntry => 1,
+	AllowlistMints => 1,
+	ClaimLink => 1,
+	ReviewReply => 1,
+	ReviewFlag => 1,
+	Config => 1,
+}
+
+// Every state account sits at a PDA of seeds taken from its own fields. The canonical bump is stored at
+// creation so later instructions check the address with one `create_program_address` instead of a search.
+pub trait ProgramAddress {
+	fn with_seeds<R>(&self, f: impl FnOnce(&[&[u8]]) -> R) -> R;
+	fn bump(&self) -> u8;
+	fn set_bump(&mut self, bump: u8);
+}
+
+macro_rules! program_address {
+	($($ty:ident |$s:ident| $seeds:expr),* $(,)?) => {
+		$(impl ProgramAddress for $ty {
+			fn with_seeds<R>(&self, f: impl FnOnce(&[&[u8]]) -> R) -> R {
+				let $s = self;
+				f($seeds)
+			}
+
+			fn bump(&self) -> u8 {
+				self.bump
+			}
+
+			fn set_bump(&mut self, bump: u8) {
+				self.bump = bump;
+			}
+		})*
+	};
+}
+
+program_address! {
+	Merchant |m| &[seeds::MERCHANT, m.merchant.as_ref()],
+	Deal |d| &[seeds::DEAL, d.merchant.as_ref(), &d.deal_id.to_le_bytes()],
+	Review |r| &[seeds::REVIEW, r.deal.as_ref(), r.user.as_ref()],
+	RedeemLog |l| &[seeds::REDEEM, l.token_mint.as_ref()],
+	Listing |l| &[seeds::LISTING, l.nft_mint.as_ref(), l.seller.as_ref()],
+	Coupon |c| &[seeds::COUPON, c.mint.as_ref()],
+	RaffleEntry |e| &[seeds::ENTRY, e.deal.as_ref(), e.user.as_ref()],
+	AllowlistMints |a| &[seeds::ALLOWANCE, a.deal.as_ref(), a.user.as_ref()],
+	ClaimLink |c| &[seeds::CLAIM, &c.claim_hash],
+	ReviewReply |r| &[seeds::REPLY, r.review.as_ref()],
+	ReviewFlag |f| &[seeds::FLAG, f.review.as_ref(), f.flagger.as_ref()],
+	Config |_c| &[seeds::CONFIG],
+}
 
 #[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
 pub struct Merchant {
@@ -18,11 +143,17 @@ pub struct Merchant {
 	pub uri: String,
 	pub total_deals: u32,
 	pub collection_mint: Pubkey,
+	// Id assigned to the next deal; only ever increases so ids are never reused
+	pub next_deal_id: u64,
+	// Running totals over verified reviews of all the merchant's deals
+	pub rating_sum: u64,
+	pub rating_count: u32,
+	pub bump: u8,
 }
 
 impl Merchant {
 	pub fn space() -> usize {
-		32 + 4 + MAX_NAME_LEN + 4 + MAX_URI_LEN + 4 + 32
+		HEADER_LEN + 32 + 4 + MAX_NAME_LEN + 4 + MAX_URI_LEN + 4 + 32 + 8 + 8 + 4 + 1
 	}
 }
 
@@ -32,20 +163,464 @@ pub struct Deal {
 	pub merchant: Pubkey,
 	pub title: String,
 	pub description: String,
-	pub discount_percent: u8,
+	pub discount: DiscountKind,
 	pub expiry: i64,
 	pub total_supply: u32,
 	pub minted: u32,
 	pub image_uri: String,
 	pub metadata_uri: String,
+	pub status: DealStatus,
+	pub schedule: Option<RedemptionSchedule>,
+	// Paid by the user to the merchant on every mint; 0 for free coupons
+	pub mint_price_lamports: u64,
+	// Price in an SPL token (e.g. USDC) instead of SOL; exclusive with mint_price_lamports
+	pub token_price: Option<TokenPrice>,
+	// Group buy: 0 for regular deals. Otherwise the deal only goes live once this many coupons sell by funding_deadline
+	pub min_participants: u32,
+	pub funding_deadline: i64,
+	// Set by FinalizeGroupDeal once the threshold is met; redemptions wait for it
+	pub group_finalized: bool,
+	// Declining price that replaces the fixed price, in lamports or in base units of token_price.mint
+	pub price_curve: Option<PriceCurve>,
+	// Only drawn raffle winners can mint
+	pub raffle: Option<Raffle>,
+	// Root of a Merkle allowlist (see merkle.rs); all zeroes lets anyone mint
+	pub allowlist_root: [u8; 32],
+	// Holding requirement checked on every mint
+	pub gate: Option<DealGate>,
+	// Running totals over verified reviews; average = rating_sum / rating_count
+	pub rating_sum: u64,
+	pub rating_count: u32,
+	// Canonical bump of the [VAULT, deal] PDA, recorded when the vault is created
+	pub vault_bump: Option<u8>,
+	pub bump: u8,
 }
 
 impl Deal {
+	// Paid coupons escrow their payment in the deal vault until redeemed or refunded
+	pub fn is_paid(&self) -> bool {
+		self.mint_price_lamports > 0 || self.token_price.is_some() || self.price_curve.is_some()
+	}
+
+	// Price charged for a mint at `unix_timestamp`; the program and clients both use this
+	pub fn mint_price_at(&self, unix_timestamp: i64) -> u64 {
+		match (&self.price_curve, &self.token_price) {
+			(Some(curve), _) => curve.price_at(unix_timestamp),
+			(None, Some(price)) => price.amount,
+			(None, None) => self.mint_price_lamports,
+		}
+	}
+
+	pub fn is_group_buy(&self) -> bool {
+		self.min_participants > 0
+	}
+
+	// Group deals that have not tipped yet are still collecting payments
+	pub fn is_group_pending(&self) -> bool {
+		self.is_group_buy() && !self.group_finalized
+	}
+
+	pub fn space() -> usize {
+		HEADER_LEN + 8 + 32 + 4 + MAX_TITLE_LEN + 4 + MAX_DESC_LEN + DiscountKind::space() + 8 + 4 + 4 + 4 + MAX_URI_LEN + 4 + MAX_URI_LEN + 1
+			+ 1 + RedemptionSchedule::space() + 8 + 1 + TokenPrice::space() + 4 + 8 + 1 + 1 + PriceCurve::space()
+			+ 1 + Raffle::space() + 32 + 1 + DealGate::space() + 8 + 4 + 2 + 1
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TokenPrice {
+	pub mint: Pubkey,
+	// In base units of `mint`
+	pub amount: u64,
+}
+
+impl TokenPrice {
+	pub fn space() -> usize {
+		32 + 8
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DealGate {
+	// The user holds at least min_amount (base units) of mint
+	TokenBalance { mint: Pubkey, min_amount: u64 },
+	// The user holds an NFT verified into this Metaplex collection
+	Collection { collection: Pubkey },
+}
+
+impl DealGate {
+	pub fn space() -> usize {
+		// tag + largest variant (TokenBalance)
+		1 + 32 + 8
+	}
+}
+
+// Dutch auction: the price falls from start_price to end_price between start_ts and end_ts, every step_seconds
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PriceCurve {
+	pub start_price: u64,
+	pub end_price: u64,
+	pub start_ts: i64,
+	pub end_ts: i64,
+	pub step_seconds: u32,
+}
+
+impl PriceCurve {
+	pub fn space() -> usize {
+		8 + 8 + 8 + 8 + 4
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.end_price > 0 && self.start_price >= self.end_price && self.start_ts < self.end_ts && self.step_seconds > 0
+	}
+
+	pub fn price_at(&self, unix_timestamp: i64) -> u64 {
+		if unix_timestamp <= self.start_ts { return self.start_price; }
+		if unix_timestamp >= self.end_ts { return self.end_price; }
+		// Time is rounded down to whole steps so the price holds still within a step
+		let step = i64::from(self.step_seconds);
+		let elapsed = (unix_timestamp - self.start_ts) / step * step;
+		let drop = u128::from(self.start_price - self.end_price) * elapsed as u128 / (self.end_ts - self.start_ts) as u128;
+		self.start_price - drop as u64
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Raffle {
+	pub winners: u32,
+	// Entries are accepted up to and including this time; the draw happens after it
+	pub entry_deadline: i64,
+	pub entries: u32,
+	// Entropy recorded by DrawRaffle; None until drawn
+	pub seed: Option<[u8; 32]>,
+}
+
+impl Raffle {
+	pub fn space() -> usize {
+		4 + 8 + 4 + 1 + 32
+	}
+
+	pub fn is_drawn(&self) -> bool {
+		self.seed.is_some()
+	}
+
+	// The seed picks an affine permutation of the entry indexes; the entries landing in the first `winners` positions
+	// win. Each entry can be checked on its own and exactly min(winners, entries) of them win.
+	pub fn is_winner(&self, index: u32) -> bool {
+		let Some(seed) = self.seed else { return false };
+		if index >= self.entries { return false; }
+		if self.winners >= self.entries { return true; }
+		let n = u64::from(self.entries);
+		let mut a = u64::from_le_bytes(seed[0..8].try_into().unwrap()) % n;
+		while gcd(a, n) != 1 { a = (a + 1) % n; }
+		let b = u64::from_le_bytes(seed[8..16].try_into().unwrap()) % n;
+		let position = (u128::from(a) * u128::from(index) + u128::from(b)) % u128::from(n);
+		position < u128::from(self.winners)
+	}
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+	a
+}
+
+// One per user and raffle deal, at [ENTRY, deal, user]
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct RaffleEntry {
+	pub deal: Pubkey,
+	pub user: Pubkey,
+	pub index: u32,
+	// Winners mint once
+	pub minted: bool,
+	pub bump: u8,
+}
+
+impl RaffleEntry {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 4 + 1 + 1
+	}
+}
+
+// A coupon parked at [CLAIM, claim_hash] until whoever holds the ephemeral keypair behind claim_hash claims it
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct ClaimLink {
+	pub merchant: Pubkey,
+	pub deal: Pubkey,
+	pub coupon_mint: Pubkey,
+	// sha256 of the ephemeral pubkey shared in the link
+	pub claim_hash: [u8; 32],
+	pub expires_at: i64,
+	pub bump: u8,
+}
+
+impl ClaimLink {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 32 + 32 + 8 + 1
+	}
+}
+
+// Coupons minted by an allowlisted user with a per-user allowance, at [ALLOWANCE, deal, user]
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct AllowlistMints {
+	pub deal: Pubkey,
+	pub user: Pubkey,
+	pub minted: u32,
+	pub bump: u8,
+}
+
+impl AllowlistMints {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 4 + 1
+	}
+}
+
+// Weekly window in which coupons of a deal can be redeemed, in the merchant's local time
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RedemptionSchedule {
+	// bit 0 = Monday .. bit 6 = Sunday
+	pub weekdays: u8,
+	// Minutes after local midnight; end < start wraps past midnight
+	pub start_minute: u16,
+	pub end_minute: u16,
+	pub utc_offset_minutes: i16,
+}
+
+impl RedemptionSchedule {
+	pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+	pub fn space() -> usize {
+		1 + 2 + 2 + 2
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.weekdays != 0
+			&& self.weekdays < 0x80
+			&& self.start_minute < Self::MINUTES_PER_DAY
+			&& self.end_minute < Self::MINUTES_PER_DAY
+			&& self.start_minute != self.end_minute
+			&& (-12 * 60..=14 * 60).contains(&self.utc_offset_minutes)
+	}
+
+	pub fn is_open_at(&self, unix_timestamp: i64) -> bool {
+		let local = unix_timestamp + i64::from(self.utc_offset_minutes) * 60;
+		let minute = local.rem_euclid(86_400) / 60;
+		let (start, end) = (i64::from(self.start_minute), i64::from(self.end_minute));
+		// Minutes past midnight of a window that started the previous evening belong to that day
+		let (in_window, day_shift) = if start < end {
+			(minute >= start && minute < end, 0)
+		} else if minute >= start {
+			(true, 0)
+		} else {
+			(minute < end, 1)
+		};
+		// 1970-01-01 was a Thursday (weekday 3 when Monday is 0)
+		let weekday = (local.div_euclid(86_400) - day_shift + 3).rem_euclid(7);
+		in_window && self.weekdays & (1 << weekday) != 0
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub enum DiscountKind {
+	// 1..=100 percent off
+	Percent(u8),
+	// Flat amount off in the currency's minor units, e.g. 500 USD cents; currency is an ISO 4217 code
+	FixedAmount { amount: u64, currency_code: [u8; 3] },
+	// Buy one get one free
+	Bogo,
+	FreeItem { sku: String },
+}
+
+impl DiscountKind {
+	pub fn space() -> usize {
+		// tag + largest variant (FreeItem)
+		1 + 4 + MAX_SKU_LEN
+	}
+
+	pub fn is_valid(&self) -> bool {
+		match self {
+			DiscountKind::Percent(p) => (1..=100).contains(p),
+			DiscountKind::FixedAmount { amount, currency_code } => *amount > 0 && currency_code.iter().all(|c| c.is_ascii_uppercase()),
+			DiscountKind::Bogo => true,
+			DiscountKind::FreeItem { sku } => !sku.is_empty() && sku.len() <= MAX_SKU_LEN,
+		}
+	}
+}
+
+// Deal layout before DiscountKind and DealStatus were introduced. Accounts created with it are
+// exactly `DealV0::space()` bytes long, which is how they are told apart from current deals.
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct DealV0 {
+	pub deal_id: u64,
+	pub merchant: Pubkey,
+	pub title: String,
+	pub description: String,
+	pub discount_percent: u8,
+	pub expiry: i64,
+	pub total_supply: u32,
+	pub minted: u32,
+	pub image_uri: String,
+	pub metadata_uri: String,
+}
+
+impl DealV0 {
 	pub fn space() -> usize {
 		8 + 32 + 4 + MAX_TITLE_LEN + 4 + MAX_DESC_LEN + 1 + 8 + 4 + 4 + 4 + MAX_URI_LEN + 4 + MAX_URI_LEN
 	}
 }
 
+impl From<DealV0> for Deal {
+	fn from(old: DealV0) -> Self {
+		Deal {
+			deal_id: old.deal_id,
+			merchant: old.merchant,
+			title: old.title,
+			description: old.description,
+			discount: DiscountKind::Percent(old.discount_percent),
+			expiry: old.expiry,
+			total_supply: old.total_supply,
+			minted: old.minted,
+			image_uri: old.image_uri,
+			metadata_uri: old.metadata_uri,
+			status: DealStatus::Active,
+			schedule: None,
+			mint_price_lamports: 0,
+			token_price: None,
+			min_participants: 0,
+			funding_deadline: 0,
+			group_finalized: false,
+			price_curve: None,
+			raffle: None,
+			allowlist_root: [0; 32],
+			gate: None,
+			rating_sum: 0,
+			rating_count: 0,
+			vault_bump: None,
+			// Set by the migration, which derives the PDA anyway
+			bump: 0,
+		}
+	}
+}
+
+// Merchant layout before deal ids were assigned on chain and rating totals were kept. Like `DealV0`
+// it has no account header and is recognised by its exact length.
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct MerchantV0 {
+	pub merchant: Pubkey,
+	pub name: String,
+	pub uri: String,
+	pub total_deals: u32,
+	pub collection_mint: Pubkey,
+}
+
+impl MerchantV0 {
+	pub fn space() -> usize {
+		32 + 4 + MAX_NAME_LEN + 4 + MAX_URI_LEN + 4 + 32
+	}
+
+	// Legacy merchants picked their own deal ids, so the caller supplies an id above every existing deal
+	pub fn into_current(self, next_deal_id: u64, bump: u8) -> Merchant {
+		Merchant {
+			merchant: self.merchant,
+			name: self.name,
+			uri: self.uri,
+			total_deals: self.total_deals,
+			collection_mint: self.collection_mint,
+			next_deal_id,
+			rating_sum: 0,
+			rating_count: 0,
+			bump,
+		}
+	}
+}
+
+// Header-less Review, RedeemLog and Listing layouts from the same era as `DealV0`, also recognised by length. Their
+// addresses derive from the same seeds as today, which tells a `RedeemLogV0` apart from a `RaffleEntry` of equal size.
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct ReviewV0 {
+	pub user: Pubkey,
+	pub deal: Pubkey,
+	pub rating: u8,
+	pub comment: String,
+	pub created_at: i64,
+}
+
+impl ReviewV0 {
+	pub fn space() -> usize {
+		32 + 32 + 1 + 4 + MAX_COMMENT_LEN + 8
+	}
+}
+
+impl From<ReviewV0> for Review {
+	// Legacy reviews proved nothing and were never counted in the rating totals
+	fn from(old: ReviewV0) -> Self {
+		Review {
+			user: old.user,
+			deal: old.deal,
+			rating: old.rating,
+			comment: old.comment,
+			created_at: old.created_at,
+			verified_purchase: false,
+			edited_at: 0,
+			flags: 0,
+			hidden: false,
+			reply_bump: None,
+			bump: 0,
+		}
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct RedeemLogV0 {
+	pub token_mint: Pubkey,
+	pub user: Pubkey,
+	pub redeemed_at: i64,
+}
+
+impl RedeemLogV0 {
+	pub fn space() -> usize {
+		32 + 32 + 8
+	}
+}
+
+impl From<RedeemLogV0> for RedeemLog {
+	// The deal was never recorded, so a migrated log doesn't count as proof for a review
+	fn from(old: RedeemLogV0) -> Self {
+		RedeemLog { token_mint: old.token_mint, user: old.user, redeemed_at: old.redeemed_at, deal: Pubkey::default(), bump: 0 }
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct ListingV0 {
+	pub seller: Pubkey,
+	pub nft_mint: Pubkey,
+	pub price: u64,
+	pub created_at: i64,
+}
+
+impl ListingV0 {
+	pub fn space() -> usize {
+		32 + 32 + 8 + 8
+	}
+}
+
+impl From<ListingV0> for Listing {
+	// Legacy listings were always priced in lamports
+	fn from(old: ListingV0) -> Self {
+		Listing { seller: old.seller, nft_mint: old.nft_mint, price: old.price, created_at: old.created_at, payment_mint: None, bump: 0 }
+	}
+}
+
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DealStatus {
+	Active,
+	// Minting is stopped temporarily; existing coupons can still be redeemed
+	Paused,
+	// Terminal: no further mints or redemptions
+	Cancelled,
+}
+
 #[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
 pub struct Review {
 	pub user: Pubkey,
@@ -53,11 +628,66 @@ pub struct Review {
 	pub rating: u8,
 	pub comment: String,
 	pub created_at: i64,
+	// Set when the reviewer proved a redemption or a held coupon; older reviews read as false
+	pub verified_purchase: bool,
+	// 0 until the review is first edited
+	pub edited_at: i64,
+	// Number of distinct wallets that flagged the review
+	pub flags: u32,
+	// Set by the admin; indexers and the CLI skip hidden reviews by default
+	pub hidden: bool,
+	// Bump of the merchant's reply at [REPLY, review]; None until the merchant replies
+	pub reply_bump: Option<u8>,
+	pub bump: u8,
 }
 
 impl Review {
 	pub fn space() -> usize {
-		32 + 32 + 1 + 4 + MAX_COMMENT_LEN + 8
+		HEADER_LEN + 32 + 32 + 1 + 4 + MAX_COMMENT_LEN + 8 + 1 + 8 + 4 + 1 + 2 + 1
+	}
+}
+
+// One per flagger and review, at [FLAG, review, flagger], so each wallet flags once
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct ReviewFlag {
+	pub review: Pubkey,
+	pub flagger: Pubkey,
+	pub flagged_at: i64,
+	pub bump: u8,
+}
+
+impl ReviewFlag {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 8 + 1
+	}
+}
+
+// Program-wide settings at [CONFIG]; the admin moderates reviews
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct Config {
+	pub admin: Pubkey,
+	pub bump: u8,
+}
+
+impl Config {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 1
+	}
+}
+
+// The merchant's public answer to a review, at [REPLY, review]
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct ReviewReply {
+	pub review: Pubkey,
+	pub merchant: Pubkey,
+	pub comment: String,
+	pub created_at: i64,
+	pub bump: u8,
+}
+
+impl ReviewReply {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 4 + MAX_REPLY_LEN + 8 + 1
 	}
 }
 
@@ -66,25 +696,57 @@ pub struct RedeemLog {
 	pub token_mint: Pubkey,
 	pub user: Pubkey,
 	pub redeemed_at: i64,
+	pub deal: Pubkey,
+	pub bump: u8,
 }
 
 impl RedeemLog {
 	pub fn space() -> usize {
-		32 + 32 + 8
+		HEADER_LEN + 32 + 32 + 8 + 32 + 1
+	}
+}
+
+// Record of a coupon counted against a deal, keyed by the coupon's NFT mint. It ties the mint to its deal for
+// redemption and reviews, and holds the escrow of paid coupons; free coupons are recorded as Released with nothing paid.
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
+pub struct Coupon {
+	pub mint: Pubkey,
+	pub deal: Pubkey,
+	// Lamports, or base units of the deal's payment mint, held in the deal vault
+	pub paid: u64,
+	pub state: CouponState,
+	pub bump: u8,
+}
+
+impl Coupon {
+	pub fn space() -> usize {
+		HEADER_LEN + 32 + 32 + 8 + 1 + 1
 	}
 }
 
+#[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CouponState {
+	Escrowed,
+	// Paid out to the merchant on redemption, or nothing was paid
+	Released,
+	// Paid back to the holder after expiry or cancellation
+	Refunded,
+}
+
 #[derive(BorshSerializeDerive, BorshDeserializeDerive, Debug, PartialEq, Eq, Clone)]
 pub struct Listing {
 	pub seller: Pubkey,
 	pub nft_mint: Pubkey,
+	// Lamports, or base units of payment_mint when set
 	pub price: u64,
 	pub created_at: i64,
+	pub payment_mint: Option<Pubkey>,
+	pub bump: u8,
 }
 
 impl Listing {
 	pub fn space() -> usize {
-		32 + 32 + 8 + 8
+		HEADER_LEN + 32 + 32 + 8 + 8 + 1 + 32 + 1
 	}
 }
 
@@ -95,4 +757,12 @@ pub mod seeds {
 	pub const REDEEM: &[u8] = b"redeem";
 	pub const LISTING: &[u8] = b"listing";
 	pub const ESCROW: &[u8] = b"escrow";
+	pub const VAULT: &[u8] = b"vault";
+	pub const COUPON: &[u8] = b"coupon";
+	pub const ENTRY: &[u8] = b"entry";
+	pub const ALLOWANCE: &[u8] = b"allowance";
+	pub const CLAIM: &[u8] = b"claim";
+	pub const REPLY: &[u8] = b"reply";
+	pub const FLAG: &[u8] = b"flag";
+	pub const CONFIG: &[u8] = b"config";
 }
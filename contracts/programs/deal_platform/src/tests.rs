@@ -0,0 +1,2566 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+	account_info::AccountInfo,
+	entrypoint::ProgramResult,
+	instruction::{AccountMeta, Instruction, InstructionError},
+	clock::Clock,
+	hash::Hash,
+	slot_hashes::SlotHashes,
+	program_pack::Pack,
+	program_stubs::{self, SyscallStubs},
+	pubkey::Pubkey,
+	system_instruction, system_program,
+};
+use std::sync::{Mutex, Once, OnceLock};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+	account::Account,
+	signature::{Keypair, Signer},
+	transaction::{Transaction, TransactionError},
+};
+
+use crate::{
+	error::DealError,
+	event::{self, DealEvent},
+	instruction::DealInstruction,
+	merkle::{self, AllowlistProof},
+	metaplex::{self, Metadata},
+	state::{seeds, AccountState, Config, Coupon, CouponState, Deal, DealGate, DealStatus, DealV0, DiscountKind, Listing, Merchant, MerchantV0, PriceCurve, Raffle, RaffleEntry, RedeemLog, RedeemLogV0, RedemptionSchedule, Review, ReviewReply, ReviewV0, ListingV0, TokenPrice, MAX_REPLY_LEN},
+};
+
+fn program_test() -> (ProgramTest, Pubkey) {
+	static CAPTURE: Once = Once::new();
+	CAPTURE.call_once(|| {
+		// Program-test installs its own stubs the first time a bank starts, so start one before wrapping them. The
+		// tests calling this are on a runtime already, hence the thread.
+		std::thread::spawn(|| {
+			let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+			runtime.block_on(ProgramTest::default().start());
+		}).join().unwrap();
+		let inner = program_stubs::set_syscall_stubs(Box::new(CaptureEvents));
+		assert!(PROGRAM_TEST_STUBS.set(inner).is_ok());
+	});
+	let program_id = Pubkey::new_unique();
+	let pt = ProgramTest::new("deal_platform", program_id, processor!(crate::process_instruction));
+	(pt, program_id)
+}
+
+// Native builds print `sol_log_data` to stdout instead of the transaction logs, so tests collect events here
+static EMITTED: Mutex<Vec<DealEvent>> = Mutex::new(Vec::new());
+static PROGRAM_TEST_STUBS: OnceLock<Box<dyn SyscallStubs>> = OnceLock::new();
+
+// Every event emitted so far by all tests, including simulations and failed transactions
+fn emitted() -> Vec<DealEvent> {
+	EMITTED.lock().unwrap().clone()
+}
+
+// Program-test's stubs, with every `sol_log_data` field that decodes as an event also kept in `EMITTED`
+struct CaptureEvents;
+
+impl CaptureEvents {
+	fn inner(&self) -> &dyn SyscallStubs {
+		PROGRAM_TEST_STUBS.wait().as_ref()
+	}
+}
+
+impl SyscallStubs for CaptureEvents {
+	fn sol_log(&self, message: &str) {
+		self.inner().sol_log(message)
+	}
+	fn sol_log_compute_units(&self) {
+		self.inner().sol_log_compute_units()
+	}
+	fn sol_remaining_compute_units(&self) -> u64 {
+		self.inner().sol_remaining_compute_units()
+	}
+	fn sol_invoke_signed(&self, instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+		self.inner().sol_invoke_signed(instruction, account_infos, signers_seeds)
+	}
+	fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_clock_sysvar(var_addr)
+	}
+	fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_epoch_schedule_sysvar(var_addr)
+	}
+	fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_fees_sysvar(var_addr)
+	}
+	fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_rent_sysvar(var_addr)
+	}
+	fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_epoch_rewards_sysvar(var_addr)
+	}
+	fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+		self.inner().sol_get_last_restart_slot(var_addr)
+	}
+	unsafe fn sol_memcpy(&self, dst: *mut u8, src: *const u8, n: usize) {
+		self.inner().sol_memcpy(dst, src, n)
+	}
+	unsafe fn sol_memmove(&self, dst: *mut u8, src: *const u8, n: usize) {
+		self.inner().sol_memmove(dst, src, n)
+	}
+	unsafe fn sol_memcmp(&self, s1: *const u8, s2: *const u8, n: usize, result: *mut i32) {
+		self.inner().sol_memcmp(s1, s2, n, result)
+	}
+	unsafe fn sol_memset(&self, s: *mut u8, c: u8, n: usize) {
+		self.inner().sol_memset(s, c, n)
+	}
+	fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+		self.inner().sol_get_return_data()
+	}
+	fn sol_set_return_data(&self, data: &[u8]) {
+		self.inner().sol_set_return_data(data)
+	}
+	fn sol_log_data(&self, fields: &[&[u8]]) {
+		EMITTED.lock().unwrap().extend(fields.iter().filter_map(|field| DealEvent::try_from_slice(field).ok()));
+		self.inner().sol_log_data(fields)
+	}
+	fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+		self.inner().sol_get_processed_sibling_instruction(index)
+	}
+	fn sol_get_stack_height(&self) -> u64 {
+		self.inner().sol_get_stack_height()
+	}
+}
+
+fn merchant_pda(program_id: &Pubkey, merchant: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::MERCHANT, merchant.as_ref()], program_id).0
+}
+
+fn deal_pda(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::DEAL, merchant.as_ref(), &deal_id.to_le_bytes()], program_id).0
+}
+
+fn redeem_pda(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REDEEM, mint.as_ref()], program_id).0
+}
+
+fn vault_pda(program_id: &Pubkey, deal: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::VAULT, deal.as_ref()], program_id).0
+}
+
+fn coupon_pda(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::COUPON, mint.as_ref()], program_id).0
+}
+
+fn packed<T: AccountState>(value: &T, space: usize) -> Vec<u8> {
+	let mut data = vec![0; space];
+	value.pack(&mut data).unwrap();
+	data
+}
+
+fn ix(program_id: &Pubkey, data: DealInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+	Instruction { program_id: *program_id, accounts, data: data.try_to_vec().unwrap() }
+}
+
+async fn send(banks: &mut BanksClient, payer: &Keypair, ixs: &[Instruction]) -> Result<(), TransactionError> {
+	send_signed(banks, payer, &[], ixs).await
+}
+
+async fn send_signed(banks: &mut BanksClient, payer: &Keypair, signers: &[&Keypair], ixs: &[Instruction]) -> Result<(), TransactionError> {
+	let blockhash = banks.get_latest_blockhash().await.unwrap();
+	let mut all: Vec<&Keypair> = vec![payer];
+	all.extend_from_slice(signers);
+	let tx = Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &all, blockhash);
+	banks.process_transaction(tx).await.map_err(|e| e.unwrap())
+}
+
+fn custom_err(e: DealError) -> TransactionError {
+	TransactionError::InstructionError(0, InstructionError::Custom(e as u32))
+}
+
+fn register_merchant_ix(program_id: &Pubkey, merchant: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::RegisterMerchant { name: "Monke Cafe".into(), uri: "https://monke.cafe".into() },
+		vec![
+			AccountMeta::new(*merchant, true),
+			AccountMeta::new(merchant_pda(program_id, merchant), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+fn create_deal_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, total_supply: u32) -> Instruction {
+	create_deal_with_discount_ix(program_id, merchant, deal_id, total_supply, DiscountKind::Percent(50))
+}
+
+fn create_deal_with_discount_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, total_supply: u32, discount: DiscountKind) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::CreateDeal {
+			title: "Half off coffee".into(),
+			description: "Any size".into(),
+			discount,
+			expiry: i64::MAX,
+			total_supply,
+			image_uri: "".into(),
+			metadata_uri: "".into(),
+		},
+		vec![
+			AccountMeta::new(*merchant, true),
+			AccountMeta::new(merchant_pda(program_id, merchant), false),
+			AccountMeta::new(deal_pda(program_id, merchant, deal_id), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+fn mint_coupon_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, deal_id: u64) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::MintCouponNft { deal_id, allowlist_proof: None },
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new_readonly(merchant_pda(program_id, merchant), false),
+			AccountMeta::new(deal_pda(program_id, merchant, deal_id), false),
+		],
+	)
+}
+
+// VerifyAndCountMint on a free deal, which records `mint` as a coupon of the deal
+fn count_mint_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, deal_id: u64, mint: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::VerifyAndCountMint { deal_id, mint: mint.to_bytes(), allowlist_proof: None },
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new_readonly(merchant_pda(program_id, merchant), false),
+			AccountMeta::new(deal_pda(program_id, merchant, deal_id), false),
+			AccountMeta::new(coupon_pda(program_id, mint), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+// `user_coupon` is the user's token account holding the coupon
+fn redeem_coupon_ix(program_id: &Pubkey, user: &Pubkey, deal: &Pubkey, user_coupon: &Pubkey, mint: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::RedeemCoupon { mint: mint.to_bytes() },
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new(redeem_pda(program_id, mint), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(*deal, false),
+			AccountMeta::new_readonly(*user_coupon, false),
+			AccountMeta::new(coupon_pda(program_id, mint), false),
+		],
+	)
+}
+
+fn redeem_and_burn_ix(program_id: &Pubkey, user: &Pubkey, deal: &Pubkey, user_coupon: &Pubkey, mint: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::RedeemAndBurn { mint: mint.to_bytes() },
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new(*user_coupon, false),
+			AccountMeta::new(*mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new(redeem_pda(program_id, mint), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(*deal, false),
+			AccountMeta::new(coupon_pda(program_id, mint), false),
+		],
+	)
+}
+
+fn set_deal_status_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, status: DealStatus) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::SetDealStatus { deal_id, status },
+		vec![
+			AccountMeta::new(*merchant, true),
+			AccountMeta::new(deal_pda(program_id, merchant, deal_id), false),
+		],
+	)
+}
+
+async fn read_deal(banks: &mut BanksClient, deal: Pubkey) -> Deal {
+	let account = banks.get_account(deal).await.unwrap().unwrap();
+	Deal::unpack(&account.data).unwrap()
+}
+
+async fn read_merchant(banks: &mut BanksClient, merchant: Pubkey) -> Merchant {
+	let account = banks.get_account(merchant).await.unwrap().unwrap();
+	Merchant::unpack(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn deal_ids_are_assigned_from_merchant_counter() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+	send(&mut banks, &payer, &[create_deal_ix(&program_id, &merchant, 1, 5)]).await.unwrap();
+
+	assert_eq!(read_deal(&mut banks, deal_pda(&program_id, &merchant, 0)).await.deal_id, 0);
+	assert_eq!(read_deal(&mut banks, deal_pda(&program_id, &merchant, 1)).await.total_supply, 5);
+	let state = read_merchant(&mut banks, merchant_pda(&program_id, &merchant)).await;
+	assert_eq!(state.next_deal_id, 2);
+	assert_eq!(state.total_deals, 2);
+
+	// Addressing any PDA other than the next id is rejected
+	let err = send(&mut banks, &payer, &[create_deal_ix(&program_id, &merchant, 7, 5)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::PdaDerivationMismatch));
+}
+
+#[tokio::test]
+async fn paused_deal_rejects_mints_until_resumed() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+	send(&mut banks, &payer, &[set_deal_status_ix(&program_id, &merchant, 0, DealStatus::Paused)]).await.unwrap();
+
+	let err = send(&mut banks, &payer, &[mint_coupon_ix(&program_id, &merchant, &merchant, 0)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::DealNotActive));
+
+	send(&mut banks, &payer, &[set_deal_status_ix(&program_id, &merchant, 0, DealStatus::Active), mint_coupon_ix(&program_id, &merchant, &merchant, 0)]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deal_pda(&program_id, &merchant, 0)).await.minted, 1);
+}
+
+#[tokio::test]
+async fn cancelled_deal_blocks_redemption_and_is_final() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let mint = Pubkey::new_unique();
+
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+	send(&mut banks, &payer, &[set_deal_status_ix(&program_id, &merchant, 0, DealStatus::Cancelled)]).await.unwrap();
+
+	let err = send(&mut banks, &payer, &[redeem_coupon_ix(&program_id, &merchant, &deal, &Pubkey::new_unique(), &mint)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::DealCancelled));
+
+	let err = send(&mut banks, &payer, &[set_deal_status_ix(&program_id, &merchant, 0, DealStatus::Active)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::DealCancelled));
+	assert_eq!(read_deal(&mut banks, deal).await.status, DealStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn only_the_merchant_can_change_deal_status() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+
+	// A different signer derives a different deal PDA, so the merchant's deal cannot be addressed
+	let other = Keypair::new();
+	let ix = ix(
+		&program_id,
+		DealInstruction::SetDealStatus { deal_id: 0, status: DealStatus::Paused },
+		vec![AccountMeta::new(other.pubkey(), true), AccountMeta::new(deal, false)],
+	);
+	let blockhash = banks.get_latest_blockhash().await.unwrap();
+	let tx = Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[&payer, &other], blockhash);
+	let err = banks.process_transaction(tx).await.unwrap_err().unwrap();
+	assert_eq!(err, custom_err(DealError::PdaDerivationMismatch));
+	assert_eq!(read_deal(&mut banks, deal).await.status, DealStatus::Active);
+}
+
+fn recount_deals_ix(program_id: &Pubkey, merchant: &Pubkey, deals: &[Pubkey]) -> Instruction {
+	let mut accounts = vec![AccountMeta::new(*merchant, true), AccountMeta::new(merchant_pda(program_id, merchant), false)];
+	accounts.extend(deals.iter().map(|d| AccountMeta::new_readonly(*d, false)));
+	ix(program_id, DealInstruction::RecountDeals, accounts)
+}
+
+#[tokio::test]
+async fn recount_deals_rebuilds_total_from_deal_pdas() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deals = [deal_pda(&program_id, &merchant, 0), deal_pda(&program_id, &merchant, 1)];
+
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+	send(&mut banks, &payer, &[create_deal_ix(&program_id, &merchant, 1, 10)]).await.unwrap();
+
+	send(&mut banks, &payer, &[recount_deals_ix(&program_id, &merchant, &deals[..1])]).await.unwrap();
+	assert_eq!(read_merchant(&mut banks, merchant_pda(&program_id, &merchant)).await.total_deals, 1);
+
+	send(&mut banks, &payer, &[recount_deals_ix(&program_id, &merchant, &deals)]).await.unwrap();
+	assert_eq!(read_merchant(&mut banks, merchant_pda(&program_id, &merchant)).await.total_deals, 2);
+
+	// Duplicates and accounts that are not this merchant's deals are rejected
+	let err = send(&mut banks, &payer, &[recount_deals_ix(&program_id, &merchant, &[deals[0], deals[0]])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+	let err = send(&mut banks, &payer, &[recount_deals_ix(&program_id, &merchant, &[merchant_pda(&program_id, &merchant)])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AccountKindMismatch));
+}
+
+#[tokio::test]
+async fn create_deal_validates_discount_kind() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	send(&mut banks, &payer, &[register_merchant_ix(&program_id, &merchant)]).await.unwrap();
+
+	let invalid = [
+		DiscountKind::Percent(0),
+		DiscountKind::Percent(101),
+		DiscountKind::FixedAmount { amount: 0, currency_code: *b"USD" },
+		DiscountKind::FixedAmount { amount: 500, currency_code: *b"usd" },
+		DiscountKind::FreeItem { sku: "".into() },
+	];
+	for discount in invalid {
+		let err = send(&mut banks, &payer, &[create_deal_with_discount_ix(&program_id, &merchant, 0, 10, discount)]).await.unwrap_err();
+		assert_eq!(err, custom_err(DealError::InvalidDiscount));
+	}
+
+	let valid = [
+		DiscountKind::FixedAmount { amount: 500, currency_code: *b"USD" },
+		DiscountKind::Bogo,
+		DiscountKind::FreeItem { sku: "LATTE-L".into() },
+	];
+	for (deal_id, discount) in valid.into_iter().enumerate() {
+		send(&mut banks, &payer, &[create_deal_with_discount_ix(&program_id, &merchant, deal_id as u64, 10, discount.clone())]).await.unwrap();
+		assert_eq!(read_deal(&mut banks, deal_pda(&program_id, &merchant, deal_id as u64)).await.discount, discount);
+	}
+}
+
+#[test]
+fn legacy_deal_converts_to_percent_discount() {
+	let old = DealV0 {
+		deal_id: 3,
+		merchant: Pubkey::new_unique(),
+		title: "Old deal".into(),
+		description: "".into(),
+		discount_percent: 25,
+		expiry: 0,
+		total_supply: 10,
+		minted: 4,
+		image_uri: "".into(),
+		metadata_uri: "".into(),
+	};
+	let mut data = vec![0u8; DealV0::space()];
+	old.serialize(&mut &mut data[..]).unwrap();
+
+	let deal: Deal = DealV0::deserialize(&mut &data[..]).unwrap().into();
+	assert_eq!(deal.discount, DiscountKind::Percent(25));
+	assert_eq!(deal.minted, 4);
+	assert_eq!(deal.status, DealStatus::Active);
+	assert_ne!(DealV0::space(), Deal::space());
+}
+
+// 2024-01-01 00:00 UTC, a Monday
+const MONDAY: i64 = 1_704_067_200;
+const HOUR: i64 = 3_600;
+const DAY: i64 = 24 * HOUR;
+
+#[test]
+fn redemption_schedule_handles_offsets_and_overnight_windows() {
+	// Weekdays 11:30-14:00 at UTC-5
+	let lunch = RedemptionSchedule { weekdays: 0b001_1111, start_minute: 11 * 60 + 30, end_minute: 14 * 60, utc_offset_minutes: -300 };
+	assert!(lunch.is_valid());
+	assert!(lunch.is_open_at(MONDAY + 17 * HOUR)); // Monday 12:00 local
+	assert!(!lunch.is_open_at(MONDAY + 14 * HOUR)); // Monday 09:00 local
+	assert!(!lunch.is_open_at(MONDAY + 5 * DAY + 17 * HOUR)); // Saturday 12:00 local
+
+	// Friday 22:00 until 02:00 the next morning belongs to Friday
+	let late = RedemptionSchedule { weekdays: 1 << 4, start_minute: 22 * 60, end_minute: 2 * 60, utc_offset_minutes: 0 };
+	assert!(late.is_open_at(MONDAY + 4 * DAY + 23 * HOUR));
+	assert!(late.is_open_at(MONDAY + 5 * DAY + HOUR));
+	assert!(!late.is_open_at(MONDAY + 4 * DAY + HOUR)); // early Friday belongs to Thursday
+
+	assert!(!RedemptionSchedule { weekdays: 0, ..lunch }.is_valid());
+	assert!(!RedemptionSchedule { end_minute: 24 * 60, ..lunch }.is_valid());
+	assert!(!RedemptionSchedule { utc_offset_minutes: 15 * 60, ..lunch }.is_valid());
+}
+
+#[tokio::test]
+async fn redemption_is_rejected_outside_schedule() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let schedule = RedemptionSchedule { weekdays: 1, start_minute: 11 * 60, end_minute: 14 * 60, utc_offset_minutes: 0 };
+	let set_schedule = ix(
+		&program_id,
+		DealInstruction::SetRedemptionSchedule { deal_id: 0, schedule: Some(schedule) },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal, false)],
+	);
+
+	let fee_payer = Keypair::new();
+	send(&mut ctx.banks_client, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_schedule,
+		system_instruction::transfer(&merchant, &fee_payer.pubkey(), 100_000_000),
+	]).await.unwrap();
+	assert_eq!(read_deal(&mut ctx.banks_client, deal).await.schedule, Some(schedule));
+	let (mint, coupon) = create_coupon(&mut ctx.banks_client, &payer, &merchant).await;
+	send(&mut ctx.banks_client, &payer, &[count_mint_ix(&program_id, &merchant, &merchant, 0, &mint)]).await.unwrap();
+
+	let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+	clock.unix_timestamp = MONDAY + DAY + 12 * HOUR; // Tuesday noon
+	ctx.set_sysvar(&clock);
+	let err = send(&mut ctx.banks_client, &payer, &[redeem_coupon_ix(&program_id, &merchant, &deal, &coupon, &mint)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::OutsideRedemptionWindow));
+
+	// Another fee payer, or the retry is the same transaction and gets the rejection back. Waiting for a fresh
+	// blockhash instead could move to a new slot, which resets the clock.
+	clock.unix_timestamp = MONDAY + 12 * HOUR;
+	ctx.set_sysvar(&clock);
+	send_signed(&mut ctx.banks_client, &fee_payer, &[&payer], &[redeem_coupon_ix(&program_id, &merchant, &deal, &coupon, &mint)]).await.unwrap();
+}
+
+fn set_mint_price_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, mint_price_lamports: u64) -> Instruction {
+	let deal = deal_pda(program_id, merchant, deal_id);
+	ix(
+		program_id,
+		DealInstruction::SetMintPrice { deal_id, mint_price_lamports },
+		vec![
+			AccountMeta::new(*merchant, true),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(vault_pda(program_id, &deal), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+// Paid deals are minted through VerifyAndCountMint so the escrow record can be keyed by the coupon mint
+fn paid_mint_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, deal_id: u64, mint: &Pubkey) -> Instruction {
+	let mut mint_ix = count_mint_ix(program_id, user, merchant, deal_id, mint);
+	mint_ix.accounts.insert(4, AccountMeta::new(vault_pda(program_id, &deal_pda(program_id, merchant, deal_id)), false));
+	mint_ix
+}
+
+async fn read_coupon(banks: &mut BanksClient, mint: Pubkey, program_id: &Pubkey) -> Coupon {
+	let account = banks.get_account(coupon_pda(program_id, &mint)).await.unwrap().unwrap();
+	Coupon::unpack(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn paid_mint_is_escrowed_until_redemption() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let vault = vault_pda(&program_id, &deal);
+	let user = Keypair::new();
+	let (coupon_mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	let price = 20_000_000;
+
+	// Lamports sent to the vault address before it exists don't stop the merchant from pricing the deal
+	let rent_exempt = banks.get_rent().await.unwrap().minimum_balance(0);
+	send(&mut banks, &payer, &[system_instruction::transfer(&merchant, &vault, rent_exempt)]).await.unwrap();
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_mint_price_ix(&program_id, &merchant, 0, price),
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+	let vault_account = banks.get_account(vault).await.unwrap().unwrap();
+	assert_eq!(vault_account.owner, program_id);
+	assert!(banks.get_rent().await.unwrap().is_exempt(vault_account.lamports, 0));
+
+	// MintCouponNft carries no coupon mint, so paid deals cannot use it
+	let err = send_signed(&mut banks, &payer, &[&user], &[mint_coupon_ix(&program_id, &user.pubkey(), &merchant, 0)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+
+	let vault_before = banks.get_balance(vault).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint)]).await.unwrap();
+	assert_eq!(banks.get_balance(vault).await.unwrap(), vault_before + price);
+	assert_eq!(read_coupon(&mut banks, coupon_mint, &program_id).await.state, CouponState::Escrowed);
+	assert_eq!(read_deal(&mut banks, deal).await.minted, 1);
+
+	// The payment is only released when the coupon is burned, and only by its holder
+	let mut redeem = redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint);
+	redeem.accounts.extend([AccountMeta::new(vault, false), AccountMeta::new(merchant, false)]);
+	let err = send_signed(&mut banks, &user, &[], &[redeem]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::CouponMustBeBurned));
+	// The user pays for this one, since a failed transaction's fee can settle after the merchant's balance is read below
+	let mut redeem = redeem_coupon_ix(&program_id, &merchant, &deal, &user_coupon, &coupon_mint);
+	redeem.accounts.extend([AccountMeta::new(vault, false), AccountMeta::new(merchant, false)]);
+	let err = send_signed(&mut banks, &user, &[&payer], &[redeem]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+
+	let mut redeem = redeem_and_burn_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint);
+	redeem.accounts.extend([AccountMeta::new(vault, false), AccountMeta::new(merchant, false)]);
+	let merchant_before = banks.get_balance(merchant).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[redeem]).await.unwrap();
+	assert_eq!(token_balance(&mut banks, user_coupon).await, 0);
+	assert_eq!(banks.get_balance(merchant).await.unwrap(), merchant_before + price);
+	assert_eq!(banks.get_balance(vault).await.unwrap(), vault_before);
+	assert_eq!(read_coupon(&mut banks, coupon_mint, &program_id).await.state, CouponState::Released);
+
+	let err = send(&mut banks, &payer, &[set_mint_price_ix(&program_id, &merchant, 0, 1)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::DealHasMints));
+}
+
+async fn create_mint(banks: &mut BanksClient, payer: &Keypair, decimals: u8) -> Pubkey {
+	create_mint_with(banks, payer, &Keypair::new(), decimals).await
+}
+
+async fn create_mint_with(banks: &mut BanksClient, payer: &Keypair, mint: &Keypair, decimals: u8) -> Pubkey {
+	let rent = banks.get_rent().await.unwrap();
+	let ixs = [
+		system_instruction::create_account(&payer.pubkey(), &mint.pubkey(), rent.minimum_balance(spl_token::state::Mint::LEN), spl_token::state::Mint::LEN as u64, &spl_token::id()),
+		spl_token::instruction::initialize_mint2(&spl_token::id(), &mint.pubkey(), &payer.pubkey(), None, decimals).unwrap(),
+	];
+	send_signed(banks, payer, &[mint], &ixs).await.unwrap();
+	mint.pubkey()
+}
+
+async fn create_token_account(banks: &mut BanksClient, payer: &Keypair, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+	let account = Keypair::new();
+	let rent = banks.get_rent().await.unwrap();
+	let mut ixs = vec![
+		system_instruction::create_account(&payer.pubkey(), &account.pubkey(), rent.minimum_balance(spl_token::state::Account::LEN), spl_token::state::Account::LEN as u64, &spl_token::id()),
+		spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), mint, owner).unwrap(),
+	];
+	if amount > 0 {
+		ixs.push(spl_token::instruction::mint_to(&spl_token::id(), mint, &account.pubkey(), &payer.pubkey(), &[], amount).unwrap());
+	}
+	send_signed(banks, payer, &[&account], &ixs).await.unwrap();
+	account.pubkey()
+}
+
+// A one-of-one coupon mint and `owner`'s token account holding it
+async fn create_coupon(banks: &mut BanksClient, payer: &Keypair, owner: &Pubkey) -> (Pubkey, Pubkey) {
+	let mint = create_mint(banks, payer, 0).await;
+	let account = create_token_account(banks, payer, &mint, owner, 1).await;
+	(mint, account)
+}
+
+async fn token_balance(banks: &mut BanksClient, account: Pubkey) -> u64 {
+	let account = banks.get_account(account).await.unwrap().unwrap();
+	spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
+
+#[tokio::test]
+async fn token_priced_mint_pays_merchant_in_usdc() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let user = Keypair::new();
+	let usdc = create_mint(&mut banks, &payer, 6).await;
+	let user_usdc = create_token_account(&mut banks, &payer, &usdc, &user.pubkey(), 50_000_000).await;
+	let merchant_usdc = create_token_account(&mut banks, &payer, &usdc, &merchant, 0).await;
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let vault = vault_pda(&program_id, &deal);
+	let vault_usdc = create_token_account(&mut banks, &payer, &usdc, &vault, 0).await;
+	let (coupon_mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+
+	let set_price = ix(
+		&program_id,
+		DealInstruction::SetTokenMintPrice { deal_id: 0, payment_mint: Some(usdc.to_bytes()), amount: 20_000_000 },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(vault, false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_price,
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deal).await.token_price, Some(TokenPrice { mint: usdc, amount: 20_000_000 }));
+
+	// A SOL price cannot be combined with a token price
+	let err = send(&mut banks, &payer, &[set_mint_price_ix(&program_id, &merchant, 0, 1)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+
+	let paid_mint = |destination: Pubkey| {
+		let mut mint_ix = paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint);
+		mint_ix.accounts.extend([
+			AccountMeta::new(user_usdc, false),
+			AccountMeta::new(destination, false),
+			AccountMeta::new_readonly(usdc, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+		]);
+		mint_ix
+	};
+
+	// Paying into an account the vault does not own is rejected
+	let err = send_signed(&mut banks, &payer, &[&user], &[paid_mint(merchant_usdc)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+
+	send_signed(&mut banks, &payer, &[&user], &[paid_mint(vault_usdc)]).await.unwrap();
+	assert_eq!(token_balance(&mut banks, vault_usdc).await, 20_000_000);
+	assert_eq!(token_balance(&mut banks, user_usdc).await, 30_000_000);
+	assert_eq!(read_deal(&mut banks, deal).await.minted, 1);
+
+	let mut redeem = redeem_and_burn_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint);
+	redeem.accounts.extend([
+		AccountMeta::new(vault, false),
+		AccountMeta::new(vault_usdc, false),
+		AccountMeta::new(merchant_usdc, false),
+		AccountMeta::new_readonly(usdc, false),
+		AccountMeta::new_readonly(spl_token::id(), false),
+	]);
+	send_signed(&mut banks, &payer, &[&user], &[redeem]).await.unwrap();
+	assert_eq!(token_balance(&mut banks, merchant_usdc).await, 20_000_000);
+	assert_eq!(token_balance(&mut banks, vault_usdc).await, 0);
+}
+
+// ClaimRefund and RefundGroupDeal take the same accounts
+fn refund_ix(program_id: &Pubkey, data: DealInstruction, user: &Pubkey, user_coupon: &Pubkey, coupon_mint: &Pubkey, deal: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		data,
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new(*user_coupon, false),
+			AccountMeta::new(*coupon_mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(*deal, false),
+			AccountMeta::new(coupon_pda(program_id, coupon_mint), false),
+			AccountMeta::new(vault_pda(program_id, deal), false),
+		],
+	)
+}
+
+#[tokio::test]
+async fn escrowed_payment_is_refunded_when_deal_is_cancelled() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let price = 20_000_000;
+	let coupon_mint = create_mint(&mut banks, &payer, 0).await;
+	let user_coupon = create_token_account(&mut banks, &payer, &coupon_mint, &user.pubkey(), 1).await;
+
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_mint_price_ix(&program_id, &merchant, 0, price),
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint)]).await.unwrap();
+
+	let refund = refund_ix(&program_id, DealInstruction::ClaimRefund { mint: coupon_mint.to_bytes() }, &user.pubkey(), &user_coupon, &coupon_mint, &deal);
+
+	// The deal is still live, so the merchant keeps the chance to honour the coupon
+	let err = send_signed(&mut banks, &user, &[], std::slice::from_ref(&refund)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::RefundNotAvailable));
+
+	send(&mut banks, &payer, &[set_deal_status_ix(&program_id, &merchant, 0, DealStatus::Cancelled)]).await.unwrap();
+	let user_before = banks.get_balance(user.pubkey()).await.unwrap();
+	send_signed(&mut banks, &payer, &[&user], std::slice::from_ref(&refund)).await.unwrap();
+	assert_eq!(banks.get_balance(user.pubkey()).await.unwrap(), user_before + price);
+	assert_eq!(token_balance(&mut banks, user_coupon).await, 0);
+	assert_eq!(read_coupon(&mut banks, coupon_mint, &program_id).await.state, CouponState::Refunded);
+
+	let err = send_signed(&mut banks, &user, &[], &[refund]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::PaymentSettled));
+}
+
+#[tokio::test]
+async fn token_priced_listing_is_bought_with_usdc() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let seller = payer.pubkey();
+	let buyer = Keypair::new();
+	let usdc = create_mint(&mut banks, &payer, 6).await;
+	let buyer_usdc = create_token_account(&mut banks, &payer, &usdc, &buyer.pubkey(), 10_000_000).await;
+	let seller_usdc = create_token_account(&mut banks, &payer, &usdc, &seller, 0).await;
+
+	let nft = create_mint(&mut banks, &payer, 0).await;
+	let escrow = Pubkey::find_program_address(&[seeds::ESCROW, nft.as_ref()], &program_id).0;
+	let seller_nft = create_token_account(&mut banks, &payer, &nft, &seller, 1).await;
+	let escrow_nft = create_token_account(&mut banks, &payer, &nft, &escrow, 0).await;
+	let buyer_nft = create_token_account(&mut banks, &payer, &nft, &buyer.pubkey(), 0).await;
+	let listing = Pubkey::find_program_address(&[seeds::LISTING, nft.as_ref(), seller.as_ref()], &program_id).0;
+
+	let list = ix(
+		&program_id,
+		DealInstruction::ListNft { price: 7_500_000, payment_mint: Some(usdc.to_bytes()) },
+		vec![
+			AccountMeta::new(seller, true),
+			AccountMeta::new(listing, false),
+			AccountMeta::new_readonly(nft, false),
+			AccountMeta::new(seller_nft, false),
+			AccountMeta::new(escrow_nft, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	send(&mut banks, &payer, &[list]).await.unwrap();
+	let account = banks.get_account(listing).await.unwrap().unwrap();
+	assert_eq!(Listing::unpack(&account.data).unwrap().payment_mint, Some(usdc));
+
+	let buy = ix(
+		&program_id,
+		DealInstruction::BuyNft,
+		vec![
+			AccountMeta::new(buyer.pubkey(), true),
+			AccountMeta::new(seller, false),
+			AccountMeta::new(listing, false),
+			AccountMeta::new_readonly(nft, false),
+			AccountMeta::new_readonly(escrow, false),
+			AccountMeta::new(escrow_nft, false),
+			AccountMeta::new(buyer_nft, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new(buyer_usdc, false),
+			AccountMeta::new(seller_usdc, false),
+			AccountMeta::new_readonly(usdc, false),
+		],
+	);
+	send_signed(&mut banks, &payer, &[&buyer], &[buy]).await.unwrap();
+
+	assert_eq!(token_balance(&mut banks, seller_usdc).await, 7_500_000);
+	assert_eq!(token_balance(&mut banks, buyer_usdc).await, 2_500_000);
+	assert_eq!(token_balance(&mut banks, buyer_nft).await, 1);
+	assert!(banks.get_account(listing).await.unwrap().is_none());
+
+	let events: Vec<DealEvent> = emitted().into_iter().filter(|e| matches!(e, DealEvent::Listed { nft_mint, .. } | DealEvent::Sold { nft_mint, .. } if *nft_mint == nft)).collect();
+	assert_eq!(events, vec![
+		DealEvent::Listed { listing, nft_mint: nft, seller, price: 7_500_000, payment_mint: Some(usdc) },
+		DealEvent::Sold { listing, nft_mint: nft, seller, buyer: buyer.pubkey(), price: 7_500_000, payment_mint: Some(usdc) },
+	]);
+}
+
+fn set_group_buy_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, min_participants: u32, funding_deadline: i64) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::SetGroupBuy { deal_id, min_participants, funding_deadline },
+		vec![AccountMeta::new(*merchant, true), AccountMeta::new(deal_pda(program_id, merchant, deal_id), false)],
+	)
+}
+
+fn finalize_group_deal_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, coupon_mints: &[Pubkey]) -> Instruction {
+	let deal = deal_pda(program_id, merchant, deal_id);
+	let mut accounts = vec![AccountMeta::new(*merchant, true), AccountMeta::new(deal, false), AccountMeta::new(vault_pda(program_id, &deal), false)];
+	accounts.extend(coupon_mints.iter().map(|m| AccountMeta::new(coupon_pda(program_id, m), false)));
+	ix(program_id, DealInstruction::FinalizeGroupDeal { deal_id }, accounts)
+}
+
+#[tokio::test]
+async fn group_deal_releases_escrow_once_threshold_is_met() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let (coupon, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	let coupons = [coupon, create_mint(&mut banks, &payer, 0).await];
+	let price = 20_000_000;
+	let clock: Clock = banks.get_sysvar().await.unwrap();
+
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_mint_price_ix(&program_id, &merchant, 0, price),
+		set_group_buy_ix(&program_id, &merchant, 0, 2, clock.unix_timestamp + DAY),
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupons[0])]).await.unwrap();
+
+	let err = send_signed(&mut banks, &user, &[], &[redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupons[0])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::GroupDealNotFinalized));
+	let err = send(&mut banks, &payer, &[finalize_group_deal_ix(&program_id, &merchant, 0, &coupons[..1])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ThresholdNotMet));
+
+	send_signed(&mut banks, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupons[1])]).await.unwrap();
+	let merchant_before = banks.get_balance(merchant).await.unwrap();
+	send_signed(&mut banks, &user, &[&payer], &[finalize_group_deal_ix(&program_id, &merchant, 0, &coupons)]).await.unwrap();
+	assert_eq!(banks.get_balance(merchant).await.unwrap(), merchant_before + 2 * price);
+	assert!(read_deal(&mut banks, deal).await.group_finalized);
+	assert_eq!(read_coupon(&mut banks, coupons[0], &program_id).await.state, CouponState::Released);
+
+	// Payments released by finalization need no vault accounts or burn at redemption, which is still up to the holder
+	let err = send(&mut banks, &payer, &[redeem_coupon_ix(&program_id, &merchant, &deal, &user_coupon, &coupons[0])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+	send_signed(&mut banks, &user, &[], &[redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupons[0])]).await.unwrap();
+}
+
+#[tokio::test]
+async fn group_deal_below_threshold_refunds_after_deadline() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let price = 20_000_000;
+	let coupon_mint = create_mint(&mut ctx.banks_client, &payer, 0).await;
+	let user_coupon = create_token_account(&mut ctx.banks_client, &payer, &coupon_mint, &user.pubkey(), 1).await;
+	let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+	let deadline = clock.unix_timestamp + DAY;
+
+	send(&mut ctx.banks_client, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_mint_price_ix(&program_id, &merchant, 0, price),
+		set_group_buy_ix(&program_id, &merchant, 0, 2, deadline),
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+	send_signed(&mut ctx.banks_client, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint)]).await.unwrap();
+
+	let refund = refund_ix(&program_id, DealInstruction::RefundGroupDeal { mint: coupon_mint.to_bytes() }, &user.pubkey(), &user_coupon, &coupon_mint, &deal);
+	let err = send_signed(&mut ctx.banks_client, &user, &[], std::slice::from_ref(&refund)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::RefundNotAvailable));
+
+	clock.unix_timestamp = deadline + 1;
+	ctx.set_sysvar(&clock);
+	let err = send_signed(&mut ctx.banks_client, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &Pubkey::new_unique())]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::FundingClosed));
+	let err = send(&mut ctx.banks_client, &payer, &[finalize_group_deal_ix(&program_id, &merchant, 0, &[coupon_mint])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ThresholdNotMet));
+
+	let user_before = ctx.banks_client.get_balance(user.pubkey()).await.unwrap();
+	send_signed(&mut ctx.banks_client, &payer, &[&user], &[refund]).await.unwrap();
+	assert_eq!(ctx.banks_client.get_balance(user.pubkey()).await.unwrap(), user_before + price);
+	assert_eq!(read_coupon(&mut ctx.banks_client, coupon_mint, &program_id).await.state, CouponState::Refunded);
+}
+
+#[tokio::test]
+async fn free_group_deal_is_finalized_without_a_vault() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let (mint, coupon) = create_coupon(&mut banks, &payer, &merchant).await;
+	let clock: Clock = banks.get_sysvar().await.unwrap();
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_group_buy_ix(&program_id, &merchant, 0, 2, clock.unix_timestamp + DAY),
+		count_mint_ix(&program_id, &merchant, &merchant, 0, &mint),
+		mint_coupon_ix(&program_id, &merchant, &merchant, 0),
+	]).await.unwrap();
+	assert!(banks.get_account(vault_pda(&program_id, &deal)).await.unwrap().is_none());
+
+	send(&mut banks, &payer, &[finalize_group_deal_ix(&program_id, &merchant, 0, &[])]).await.unwrap();
+	assert!(read_deal(&mut banks, deal).await.group_finalized);
+	send(&mut banks, &payer, &[redeem_coupon_ix(&program_id, &merchant, &deal, &coupon, &mint)]).await.unwrap();
+}
+
+#[test]
+fn price_curve_declines_in_steps_to_the_floor() {
+	let curve = PriceCurve { start_price: 1_000, end_price: 400, start_ts: MONDAY, end_ts: MONDAY + 6 * HOUR, step_seconds: HOUR as u32 };
+	assert!(curve.is_valid());
+	assert_eq!(curve.price_at(MONDAY - DAY), 1_000);
+	assert_eq!(curve.price_at(MONDAY + HOUR - 1), 1_000);
+	assert_eq!(curve.price_at(MONDAY + HOUR), 900);
+	assert_eq!(curve.price_at(MONDAY + 5 * HOUR + 30 * 60), 500);
+	assert_eq!(curve.price_at(MONDAY + 6 * HOUR), 400);
+	assert_eq!(curve.price_at(i64::MAX), 400);
+
+	assert!(!PriceCurve { end_price: 0, ..curve }.is_valid());
+	assert!(!PriceCurve { start_price: 300, ..curve }.is_valid());
+	assert!(!PriceCurve { end_ts: MONDAY, ..curve }.is_valid());
+	assert!(!PriceCurve { step_seconds: 0, ..curve }.is_valid());
+}
+
+#[tokio::test]
+async fn dutch_auction_mint_escrows_the_current_price() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let curve = PriceCurve { start_price: 100_000_000, end_price: 40_000_000, start_ts: MONDAY, end_ts: MONDAY + 6 * HOUR, step_seconds: HOUR as u32 };
+	let set_curve = ix(
+		&program_id,
+		DealInstruction::SetPriceCurve { deal_id: 0, curve: Some(curve) },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(vault_pda(&program_id, &deal), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	send(&mut ctx.banks_client, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_curve,
+		system_instruction::transfer(&merchant, &user.pubkey(), 1_000_000_000),
+	]).await.unwrap();
+
+	let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+	for (offset, expected) in [(30 * 60, 100_000_000), (2 * HOUR, 80_000_000), (DAY, 40_000_000)] {
+		clock.unix_timestamp = MONDAY + offset;
+		ctx.set_sysvar(&clock);
+		assert_eq!(read_deal(&mut ctx.banks_client, deal).await.mint_price_at(clock.unix_timestamp), expected);
+		let coupon_mint = Pubkey::new_unique();
+		send_signed(&mut ctx.banks_client, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint)]).await.unwrap();
+		assert_eq!(read_coupon(&mut ctx.banks_client, coupon_mint, &program_id).await.paid, expected);
+	}
+}
+
+#[test]
+fn raffle_picks_exactly_the_requested_number_of_winners() {
+	for entries in [1, 2, 7, 10, 64, 101] {
+		for seed_byte in 0..8u8 {
+			let raffle = Raffle { winners: 5, entry_deadline: 0, entries, seed: Some([seed_byte.wrapping_mul(37); 32]) };
+			let won = (0..entries).filter(|i| raffle.is_winner(*i)).count() as u32;
+			assert_eq!(won, entries.min(5));
+			assert!(!raffle.is_winner(entries));
+		}
+	}
+	assert!(!Raffle { winners: 5, entry_deadline: 0, entries: 3, seed: None }.is_winner(0));
+}
+
+fn entry_pda(program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::ENTRY, deal.as_ref(), user.as_ref()], program_id).0
+}
+
+#[tokio::test]
+async fn only_drawn_raffle_winners_can_mint() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let users = [Keypair::new(), Keypair::new(), Keypair::new()];
+	let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+	let deadline = clock.unix_timestamp + DAY;
+
+	let set_raffle = ix(
+		&program_id,
+		DealInstruction::SetRaffle { deal_id: 0, winners: 1, entry_deadline: deadline },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal, false)],
+	);
+	let mut setup = vec![register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10), set_raffle];
+	setup.extend(users.iter().map(|u| system_instruction::transfer(&merchant, &u.pubkey(), 100_000_000)));
+	send(&mut ctx.banks_client, &payer, &setup).await.unwrap();
+
+	let enter = |user: &Pubkey| ix(
+		&program_id,
+		DealInstruction::EnterRaffle { deal_id: 0 },
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new_readonly(merchant_pda(&program_id, &merchant), false),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(entry_pda(&program_id, &deal, user), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	for user in &users {
+		send_signed(&mut ctx.banks_client, user, &[], &[enter(&user.pubkey())]).await.unwrap();
+	}
+	let mint = |user: &Pubkey| {
+		let mut mint_ix = mint_coupon_ix(&program_id, user, &merchant, 0);
+		mint_ix.accounts.push(AccountMeta::new(entry_pda(&program_id, &deal, user), false));
+		mint_ix
+	};
+	let err = send_signed(&mut ctx.banks_client, &users[0], &[], &[mint(&users[0].pubkey())]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::RaffleNotDrawn));
+
+	// Any wallet can draw, so the merchant can't hold the draw back for a slot hash they like
+	let draw = ix(
+		&program_id,
+		DealInstruction::DrawRaffle { deal_id: 0 },
+		vec![
+			AccountMeta::new(users[0].pubkey(), true),
+			AccountMeta::new(deal, false),
+			AccountMeta::new_readonly(solana_program::sysvar::slot_hashes::id(), false),
+		],
+	);
+	let err = send_signed(&mut ctx.banks_client, &users[0], &[], std::slice::from_ref(&draw)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::RaffleStillOpen));
+
+	clock.unix_timestamp = deadline + 1;
+	ctx.set_sysvar(&clock);
+	let late = Keypair::new();
+	send(&mut ctx.banks_client, &payer, &[system_instruction::transfer(&merchant, &late.pubkey(), 100_000_000)]).await.unwrap();
+	let err = send_signed(&mut ctx.banks_client, &late, &[], &[enter(&late.pubkey())]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::RaffleClosed));
+
+	ctx.set_sysvar(&SlotHashes::new(&[(clock.slot, Hash::new_from_array([7; 32]))]));
+	// Another fee payer, or the retry is the same transaction and gets the rejection back
+	send_signed(&mut ctx.banks_client, &payer, &[&users[0]], &[draw]).await.unwrap();
+	let raffle = read_deal(&mut ctx.banks_client, deal).await.raffle.unwrap();
+	assert!(raffle.is_drawn());
+
+	let mut winners = 0;
+	for user in &users {
+		let account = ctx.banks_client.get_account(entry_pda(&program_id, &deal, &user.pubkey())).await.unwrap().unwrap();
+		let entry = RaffleEntry::unpack(&account.data).unwrap();
+		if raffle.is_winner(entry.index) {
+			winners += 1;
+			send_signed(&mut ctx.banks_client, user, &[], &[mint(&user.pubkey())]).await.unwrap();
+			let err = send_signed(&mut ctx.banks_client, &payer, &[user], &[mint(&user.pubkey())]).await.unwrap_err();
+			assert_eq!(err, custom_err(DealError::NotRaffleWinner));
+		} else {
+			let err = send_signed(&mut ctx.banks_client, user, &[], &[mint(&user.pubkey())]).await.unwrap_err();
+			assert_eq!(err, custom_err(DealError::NotRaffleWinner));
+		}
+	}
+	assert_eq!(winners, 1);
+	assert_eq!(read_deal(&mut ctx.banks_client, deal).await.minted, 1);
+}
+
+#[test]
+fn merkle_proofs_verify_every_leaf_and_nothing_else() {
+	let users: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+	let leaves: Vec<[u8; 32]> = users.iter().enumerate().map(|(i, u)| merkle::leaf(u, i as u32)).collect();
+	let levels = merkle::build(&leaves);
+	let root = merkle::root(&levels);
+	for (i, leaf) in leaves.iter().enumerate() {
+		assert!(merkle::verify(&root, *leaf, &merkle::proof(&levels, i)));
+	}
+	// Wrong allowance, wrong position and an outsider are all rejected
+	assert!(!merkle::verify(&root, merkle::leaf(&users[1], 7), &merkle::proof(&levels, 1)));
+	assert!(!merkle::verify(&root, leaves[1], &merkle::proof(&levels, 2)));
+	assert!(!merkle::verify(&root, merkle::leaf(&Pubkey::new_unique(), 0), &merkle::proof(&levels, 0)));
+	// A single customer is its own root
+	let single = merkle::build(&leaves[..1]);
+	assert_eq!(merkle::root(&single), leaves[0]);
+	assert!(merkle::proof(&single, 0).is_empty());
+}
+
+#[tokio::test]
+async fn allowlisted_deal_checks_proofs_and_allowances() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let (capped, unlimited, outsider) = (Keypair::new(), Keypair::new(), Keypair::new());
+	let leaves = [merkle::leaf(&capped.pubkey(), 1), merkle::leaf(&unlimited.pubkey(), 0)];
+	let levels = merkle::build(&leaves);
+
+	let set_allowlist = ix(
+		&program_id,
+		DealInstruction::SetAllowlist { deal_id: 0, root: merkle::root(&levels) },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal, false)],
+	);
+	let mut setup = vec![register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10), set_allowlist];
+	setup.extend([&capped, &unlimited, &outsider].iter().map(|u| system_instruction::transfer(&merchant, &u.pubkey(), 100_000_000)));
+	send(&mut banks, &payer, &setup).await.unwrap();
+
+	let mint = |user: &Pubkey, allowlist_proof: Option<AllowlistProof>| {
+		let mut accounts = vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new_readonly(merchant_pda(&program_id, &merchant), false),
+			AccountMeta::new(deal, false),
+		];
+		if allowlist_proof.as_ref().is_some_and(|p| p.allowance > 0) {
+			let allowance = Pubkey::find_program_address(&[seeds::ALLOWANCE, deal.as_ref(), user.as_ref()], &program_id).0;
+			accounts.extend([AccountMeta::new(allowance, false), AccountMeta::new_readonly(system_program::id(), false)]);
+		}
+		ix(&program_id, DealInstruction::MintCouponNft { deal_id: 0, allowlist_proof }, accounts)
+	};
+
+	let err = send_signed(&mut banks, &outsider, &[], &[mint(&outsider.pubkey(), None)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::NotAllowlisted));
+	let stolen = AllowlistProof { allowance: 0, proof: merkle::proof(&levels, 1) };
+	let err = send_signed(&mut banks, &outsider, &[], &[mint(&outsider.pubkey(), Some(stolen))]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::NotAllowlisted));
+
+	let capped_proof = AllowlistProof { allowance: 1, proof: merkle::proof(&levels, 0) };
+	send_signed(&mut banks, &capped, &[], &[mint(&capped.pubkey(), Some(capped_proof.clone()))]).await.unwrap();
+	let err = send_signed(&mut banks, &payer, &[&capped], &[mint(&capped.pubkey(), Some(capped_proof))]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AllowanceExceeded));
+	// Claiming a bigger allowance than the list grants fails the proof
+	let inflated = AllowlistProof { allowance: 5, proof: merkle::proof(&levels, 0) };
+	let err = send_signed(&mut banks, &capped, &[], &[mint(&capped.pubkey(), Some(inflated))]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::NotAllowlisted));
+
+	let unlimited_proof = AllowlistProof { allowance: 0, proof: merkle::proof(&levels, 1) };
+	send_signed(&mut banks, &unlimited, &[], &[
+		mint(&unlimited.pubkey(), Some(unlimited_proof.clone())),
+		mint(&unlimited.pubkey(), Some(unlimited_proof)),
+	]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deal).await.minted, 3);
+}
+
+fn set_gate_ix(program_id: &Pubkey, merchant: &Pubkey, deal_id: u64, gate: Option<DealGate>) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::SetGate { deal_id, gate },
+		vec![AccountMeta::new(*merchant, true), AccountMeta::new(deal_pda(program_id, merchant, deal_id), false)],
+	)
+}
+
+#[tokio::test]
+async fn token_gated_deal_requires_minimum_balance() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let member = Keypair::new();
+	let community = create_mint(&mut banks, &payer, 0).await;
+	let small = create_token_account(&mut banks, &payer, &community, &member.pubkey(), 2).await;
+	let enough = create_token_account(&mut banks, &payer, &community, &member.pubkey(), 5).await;
+	let others = create_token_account(&mut banks, &payer, &community, &merchant, 5).await;
+
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_gate_ix(&program_id, &merchant, 0, Some(DealGate::TokenBalance { mint: community, min_amount: 5 })),
+		system_instruction::transfer(&merchant, &member.pubkey(), 100_000_000),
+	]).await.unwrap();
+
+	let gated_mint = |token_account: Pubkey| {
+		let mut mint_ix = mint_coupon_ix(&program_id, &member.pubkey(), &merchant, 0);
+		mint_ix.accounts.push(AccountMeta::new_readonly(token_account, false));
+		mint_ix
+	};
+	let err = send_signed(&mut banks, &member, &[], &[mint_coupon_ix(&program_id, &member.pubkey(), &merchant, 0)]).await.unwrap_err();
+	assert_eq!(err, TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys));
+	for token_account in [small, others] {
+		let err = send_signed(&mut banks, &member, &[], &[gated_mint(token_account)]).await.unwrap_err();
+		assert_eq!(err, custom_err(DealError::GateNotSatisfied));
+	}
+	send_signed(&mut banks, &member, &[], &[gated_mint(enough)]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deal_pda(&program_id, &merchant, 0)).await.minted, 1);
+}
+
+#[tokio::test]
+async fn collection_gated_deal_requires_verified_nft() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let member = Keypair::new();
+	let collection = Pubkey::new_unique();
+	let nft = create_mint(&mut ctx.banks_client, &payer, 0).await;
+	let member_nft = create_token_account(&mut ctx.banks_client, &payer, &nft, &member.pubkey(), 1).await;
+
+	let mut metadata = Metadata {
+		key: Metadata::KEY_V1,
+		update_authority: merchant,
+		mint: nft,
+		name: "Monke #1".into(),
+		symbol: "MONKE".into(),
+		uri: "".into(),
+		seller_fee_basis_points: 0,
+		creators: None,
+		primary_sale_happened: false,
+		is_mutable: true,
+		edition_nonce: None,
+		token_standard: Some(0),
+		collection: Some(metaplex::Collection { verified: false, key: collection }),
+	};
+	let set_metadata = |ctx: &mut solana_program_test::ProgramTestContext, metadata: &Metadata| {
+		let data = metadata.try_to_vec().unwrap();
+		let account = Account { lamports: 1_000_000_000, data, owner: metaplex::TOKEN_METADATA_PROGRAM_ID, executable: false, rent_epoch: 0 };
+		ctx.set_account(&metaplex::metadata_pda(&nft), &account.into());
+	};
+	set_metadata(&mut ctx, &metadata);
+
+	send(&mut ctx.banks_client, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		set_gate_ix(&program_id, &merchant, 0, Some(DealGate::Collection { collection })),
+		system_instruction::transfer(&merchant, &member.pubkey(), 100_000_000),
+	]).await.unwrap();
+
+	let mut gated_mint = mint_coupon_ix(&program_id, &member.pubkey(), &merchant, 0);
+	gated_mint.accounts.extend([AccountMeta::new_readonly(member_nft, false), AccountMeta::new_readonly(metaplex::metadata_pda(&nft), false)]);
+
+	// Anyone can claim an unverified collection, so it doesn't count
+	let err = send_signed(&mut ctx.banks_client, &member, &[], std::slice::from_ref(&gated_mint)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::GateNotSatisfied));
+
+	metadata.collection = Some(metaplex::Collection { verified: true, key: collection });
+	set_metadata(&mut ctx, &metadata);
+	send_signed(&mut ctx.banks_client, &payer, &[&member], &[gated_mint]).await.unwrap();
+	assert_eq!(read_deal(&mut ctx.banks_client, deal_pda(&program_id, &merchant, 0)).await.minted, 1);
+}
+
+#[tokio::test]
+async fn airdrop_counts_recipient_coupons_within_supply() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let clock: Clock = banks.get_sysvar().await.unwrap();
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 3),
+		create_deal_ix(&program_id, &merchant, 1, 3),
+		set_mint_price_ix(&program_id, &merchant, 1, 1_000_000),
+		set_group_buy_ix(&program_id, &merchant, 1, 2, clock.unix_timestamp + DAY),
+	]).await.unwrap();
+
+	// (token account, mint) per recipient
+	let mut coupons = Vec::new();
+	for _ in 0..4 {
+		let coupon_mint = create_mint(&mut banks, &payer, 0).await;
+		coupons.push((create_token_account(&mut banks, &payer, &coupon_mint, &Pubkey::new_unique(), 1).await, coupon_mint));
+	}
+	let airdrop = |signer: &Pubkey, recipients: &[(Pubkey, Pubkey)]| {
+		let mut accounts = vec![AccountMeta::new(*signer, true), AccountMeta::new(deal, false), AccountMeta::new_readonly(system_program::id(), false)];
+		for (token, mint) in recipients {
+			accounts.extend([
+				AccountMeta::new_readonly(*token, false),
+				AccountMeta::new_readonly(*mint, false),
+				AccountMeta::new(coupon_pda(&program_id, mint), false),
+			]);
+		}
+		ix(&program_id, DealInstruction::AirdropCoupons { deal_id: 0 }, accounts)
+	};
+
+	send(&mut banks, &payer, &[airdrop(&merchant, &coupons[..2])]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deal).await.minted, 2);
+	assert_eq!(read_coupon(&mut banks, coupons[0].1, &program_id).await.deal, deal);
+
+	// Each coupon is counted once, whether it comes back in a later batch or twice in the same one
+	let err = send(&mut banks, &payer, &[airdrop(&merchant, &coupons[..1])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+	let err = send(&mut banks, &payer, &[airdrop(&merchant, &[coupons[2], coupons[2]])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+	let err = send(&mut banks, &payer, &[airdrop(&merchant, &coupons[2..])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::DealSoldOut));
+
+	// Only one-of-one mints are coupons, and the token account must hold the mint it is paired with
+	let shared_mint = create_mint(&mut banks, &payer, 0).await;
+	let shared = create_token_account(&mut banks, &payer, &shared_mint, &Pubkey::new_unique(), 1).await;
+	create_token_account(&mut banks, &payer, &shared_mint, &Pubkey::new_unique(), 1).await;
+	let err = send(&mut banks, &payer, &[airdrop(&merchant, &[(shared, shared_mint)])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+	let err = send(&mut banks, &payer, &[airdrop(&merchant, &[(coupons[2].0, coupons[3].1)])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+
+	let other = Keypair::new();
+	let err = send_signed(&mut banks, &payer, &[&other], &[airdrop(&other.pubkey(), &coupons[2..3])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::PdaDerivationMismatch));
+	assert_eq!(read_deal(&mut banks, deal).await.minted, 2);
+
+	// Free coupons can't make up a group deal's participants
+	let mut group_airdrop = airdrop(&merchant, &coupons[2..3]);
+	group_airdrop.data = DealInstruction::AirdropCoupons { deal_id: 1 }.try_to_vec().unwrap();
+	group_airdrop.accounts[1].pubkey = deal_pda(&program_id, &merchant, 1);
+	let err = send(&mut banks, &payer, &[group_airdrop]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::GroupDealNotFinalized));
+}
+
+fn claim_pda(program_id: &Pubkey, claim_hash: &[u8; 32]) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::CLAIM, claim_hash], program_id).0
+}
+
+#[tokio::test]
+async fn claim_link_sends_coupon_to_any_wallet_until_it_expires() {
+	let (pt, program_id) = program_test();
+	let mut ctx = pt.start_with_context().await;
+	let payer = ctx.payer.insecure_clone();
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	send(&mut ctx.banks_client, &payer, &[register_merchant_ix(&program_id, &merchant), create_deal_ix(&program_id, &merchant, 0, 10)]).await.unwrap();
+	let mut clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+	clock.unix_timestamp = MONDAY;
+	ctx.set_sysvar(&clock);
+
+	// Park two coupons behind links that expire in a day
+	let mut links = Vec::new();
+	for _ in 0..2 {
+		let ephemeral = Keypair::new();
+		let claim_hash = solana_program::hash::hash(ephemeral.pubkey().as_ref()).to_bytes();
+		let claim = claim_pda(&program_id, &claim_hash);
+		let coupon_mint = create_mint(&mut ctx.banks_client, &payer, 0).await;
+		let merchant_token = create_token_account(&mut ctx.banks_client, &payer, &coupon_mint, &merchant, 1).await;
+		let claim_token = create_token_account(&mut ctx.banks_client, &payer, &coupon_mint, &claim, 0).await;
+		let create = ix(
+			&program_id,
+			DealInstruction::CreateClaim { deal_id: 0, claim_hash, expires_at: MONDAY + DAY },
+			vec![
+				AccountMeta::new(merchant, true),
+				AccountMeta::new(deal, false),
+				AccountMeta::new(claim, false),
+				AccountMeta::new(merchant_token, false),
+				AccountMeta::new(claim_token, false),
+				AccountMeta::new_readonly(coupon_mint, false),
+				AccountMeta::new_readonly(spl_token::id(), false),
+				AccountMeta::new_readonly(system_program::id(), false),
+				AccountMeta::new(coupon_pda(&program_id, &coupon_mint), false),
+			],
+		);
+		send(&mut ctx.banks_client, &payer, &[create]).await.unwrap();
+		assert_eq!(token_balance(&mut ctx.banks_client, claim_token).await, 1);
+		links.push((ephemeral, claim_hash, claim, coupon_mint, merchant_token, claim_token));
+	}
+	assert_eq!(read_deal(&mut ctx.banks_client, deal).await.minted, 2);
+
+	let claim_ix = |signer: &Pubkey, link: &(Keypair, [u8; 32], Pubkey, Pubkey, Pubkey, Pubkey), destination: &Pubkey, recipient: &Pubkey| {
+		ix(
+			&program_id,
+			DealInstruction::Claim { claim_hash: link.1 },
+			vec![
+				AccountMeta::new_readonly(*signer, true),
+				AccountMeta::new(link.2, false),
+				AccountMeta::new(link.5, false),
+				AccountMeta::new(*destination, false),
+				AccountMeta::new_readonly(*recipient, false),
+				AccountMeta::new_readonly(link.3, false),
+				AccountMeta::new_readonly(spl_token::id(), false),
+				AccountMeta::new(merchant, false),
+			],
+		)
+	};
+	let reclaim_ix = |link: &(Keypair, [u8; 32], Pubkey, Pubkey, Pubkey, Pubkey)| {
+		ix(
+			&program_id,
+			DealInstruction::ReclaimExpired { claim_hash: link.1 },
+			vec![
+				AccountMeta::new(merchant, true),
+				AccountMeta::new(link.2, false),
+				AccountMeta::new(deal, false),
+				AccountMeta::new(link.5, false),
+				AccountMeta::new(link.4, false),
+				AccountMeta::new_readonly(link.3, false),
+				AccountMeta::new_readonly(spl_token::id(), false),
+				AccountMeta::new(coupon_pda(&program_id, &link.3), false),
+			],
+		)
+	};
+
+	// Whoever holds the ephemeral key picks the wallet; anyone else is turned away
+	let recipient = Pubkey::new_unique();
+	let destination = create_token_account(&mut ctx.banks_client, &payer, &links[0].3, &recipient, 0).await;
+	let impostor = Keypair::new();
+	let err = send_signed(&mut ctx.banks_client, &payer, &[&impostor], &[claim_ix(&impostor.pubkey(), &links[0], &destination, &recipient)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+	let err = send(&mut ctx.banks_client, &payer, &[reclaim_ix(&links[0])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ClaimNotExpired));
+
+	send_signed(&mut ctx.banks_client, &payer, &[&links[0].0], &[claim_ix(&links[0].0.pubkey(), &links[0], &destination, &recipient)]).await.unwrap();
+	assert_eq!(token_balance(&mut ctx.banks_client, destination).await, 1);
+	assert!(ctx.banks_client.get_account(links[0].2).await.unwrap().is_none());
+	assert!(ctx.banks_client.get_account(links[0].5).await.unwrap().is_none());
+	let claimed = DealEvent::Claimed { deal, mint: links[0].3, owner: recipient };
+	assert_eq!(emitted().into_iter().filter(|e| *e == claimed).count(), 1);
+
+	// The second link lapses and goes back to the merchant
+	clock.unix_timestamp = MONDAY + DAY + 1;
+	ctx.set_sysvar(&clock);
+	let destination = create_token_account(&mut ctx.banks_client, &payer, &links[1].3, &recipient, 0).await;
+	let err = send_signed(&mut ctx.banks_client, &payer, &[&links[1].0], &[claim_ix(&links[1].0.pubkey(), &links[1], &destination, &recipient)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ClaimExpired));
+	send(&mut ctx.banks_client, &payer, &[reclaim_ix(&links[1])]).await.unwrap();
+	assert_eq!(token_balance(&mut ctx.banks_client, links[1].4).await, 1);
+	assert!(ctx.banks_client.get_account(links[1].2).await.unwrap().is_none());
+	// Only the claimed coupon still counts against the supply, and only it stays a coupon of the deal
+	assert_eq!(read_deal(&mut ctx.banks_client, deal).await.minted, 1);
+	assert_eq!(read_coupon(&mut ctx.banks_client, links[0].3, &program_id).await.deal, deal);
+	assert!(ctx.banks_client.get_account(coupon_pda(&program_id, &links[1].3)).await.unwrap().is_none());
+}
+
+fn review_pda(program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REVIEW, deal.as_ref(), user.as_ref()], program_id).0
+}
+
+// `proof` is the reviewer's RedeemLog or coupon token account, then the coupon's record
+fn add_review_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, deal_id: u64, rating: u8, proof: &[Pubkey]) -> Instruction {
+	let deal = deal_pda(program_id, merchant, deal_id);
+	let mut accounts = vec![
+		AccountMeta::new(*user, true),
+		AccountMeta::new(merchant_pda(program_id, merchant), false),
+		AccountMeta::new(deal, false),
+		AccountMeta::new(review_pda(program_id, &deal, user), false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	accounts.extend(proof.iter().map(|p| AccountMeta::new_readonly(*p, false)));
+	ix(program_id, DealInstruction::AddReview { deal_id, rating, comment: "Great coffee".into() }, accounts)
+}
+
+async fn read_review(banks: &mut BanksClient, program_id: &Pubkey, deal: &Pubkey, user: &Pubkey) -> Review {
+	let account = banks.get_account(review_pda(program_id, deal, user)).await.unwrap().unwrap();
+	Review::unpack(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn only_redeemers_and_coupon_holders_can_review() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let free_deal = deal_pda(&program_id, &merchant, 0);
+	let paid_deal = deal_pda(&program_id, &merchant, 1);
+	let redeemer = Keypair::new();
+	let holder = Keypair::new();
+	let stranger = Keypair::new();
+	let (redeemed_mint, redeemer_coupon) = create_coupon(&mut banks, &payer, &redeemer.pubkey()).await;
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		create_deal_ix(&program_id, &merchant, 1, 10),
+		set_mint_price_ix(&program_id, &merchant, 1, 1_000_000),
+		system_instruction::transfer(&merchant, &redeemer.pubkey(), 100_000_000),
+		system_instruction::transfer(&merchant, &holder.pubkey(), 100_000_000),
+		system_instruction::transfer(&merchant, &stranger.pubkey(), 100_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &redeemer, &[], &[count_mint_ix(&program_id, &redeemer.pubkey(), &merchant, 0, &redeemed_mint)]).await.unwrap();
+	// Redemptions are only logged for the coupon's holder, so a borrowed coupon proves nothing
+	let stranger_empty = create_token_account(&mut banks, &payer, &redeemed_mint, &stranger.pubkey(), 0).await;
+	for (token, mint) in [(stranger_empty, Pubkey::new_unique()), (stranger_empty, redeemed_mint), (redeemer_coupon, redeemed_mint)] {
+		let err = send_signed(&mut banks, &stranger, &[], &[redeem_coupon_ix(&program_id, &stranger.pubkey(), &free_deal, &token, &mint)]).await.unwrap_err();
+		assert_eq!(err, custom_err(DealError::Unauthorized));
+	}
+	send_signed(&mut banks, &redeemer, &[], &[redeem_coupon_ix(&program_id, &redeemer.pubkey(), &free_deal, &redeemer_coupon, &redeemed_mint)]).await.unwrap();
+	let redeem_log = [redeem_pda(&program_id, &redeemed_mint), coupon_pda(&program_id, &redeemed_mint)];
+
+	// A redemption proves a purchase of that deal only, and only for the redeemer
+	send_signed(&mut banks, &redeemer, &[], &[add_review_ix(&program_id, &redeemer.pubkey(), &merchant, 0, 5, &redeem_log)]).await.unwrap();
+	let review = read_review(&mut banks, &program_id, &free_deal, &redeemer.pubkey()).await;
+	assert!(review.verified_purchase);
+	assert_eq!(review.rating, 5);
+	let err = send_signed(&mut banks, &redeemer, &[], &[add_review_ix(&program_id, &redeemer.pubkey(), &merchant, 1, 4, &redeem_log)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+	let err = send_signed(&mut banks, &stranger, &[], &[add_review_ix(&program_id, &stranger.pubkey(), &merchant, 0, 1, &redeem_log)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+	let err = send_signed(&mut banks, &stranger, &[], &[add_review_ix(&program_id, &stranger.pubkey(), &merchant, 0, 1, &[stranger.pubkey()])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+
+	// Holding a paid coupon of the deal is enough before redemption
+	let coupon_mint = create_mint(&mut banks, &payer, 0).await;
+	send_signed(&mut banks, &holder, &[], &[paid_mint_ix(&program_id, &holder.pubkey(), &merchant, 1, &coupon_mint)]).await.unwrap();
+	let holder_token = create_token_account(&mut banks, &payer, &coupon_mint, &holder.pubkey(), 1).await;
+	let coupon = coupon_pda(&program_id, &coupon_mint);
+	send_signed(&mut banks, &holder, &[], &[add_review_ix(&program_id, &holder.pubkey(), &merchant, 1, 4, &[holder_token, coupon])]).await.unwrap();
+	assert!(read_review(&mut banks, &program_id, &paid_deal, &holder.pubkey()).await.verified_purchase);
+	let paid = read_deal(&mut banks, paid_deal).await;
+	assert_eq!((paid.rating_sum, paid.rating_count), (4, 1));
+	let totals = read_merchant(&mut banks, merchant_pda(&program_id, &merchant)).await;
+	assert_eq!((totals.rating_sum, totals.rating_count), (9, 2));
+	let stranger_token = create_token_account(&mut banks, &payer, &coupon_mint, &stranger.pubkey(), 0).await;
+	let err = send_signed(&mut banks, &stranger, &[], &[add_review_ix(&program_id, &stranger.pubkey(), &merchant, 1, 1, &[stranger_token, coupon])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+}
+
+#[tokio::test]
+async fn self_made_mints_cannot_be_redeemed_or_reviewed() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let (deal, other_deal) = (deal_pda(&program_id, &merchant, 0), deal_pda(&program_id, &merchant, 1));
+	let user = Keypair::new();
+	let (mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	let coupon = coupon_pda(&program_id, &mint);
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		create_deal_ix(&program_id, &merchant, 1, 10),
+		system_instruction::transfer(&merchant, &user.pubkey(), 100_000_000),
+	]).await.unwrap();
+
+	// A mint nobody counted has no record, so holding it proves nothing
+	let err = send_signed(&mut banks, &user, &[], &[redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &mint)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::CouponNotOfDeal));
+	let err = send_signed(&mut banks, &user, &[], &[add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 5, &[user_coupon, coupon])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+
+	// Once counted it belongs to that deal only
+	send_signed(&mut banks, &user, &[], &[count_mint_ix(&program_id, &user.pubkey(), &merchant, 1, &mint)]).await.unwrap();
+	let err = send_signed(&mut banks, &payer, &[&user], &[count_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &mint)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+	let err = send_signed(&mut banks, &payer, &[&user], &[redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &mint)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::CouponNotOfDeal));
+	let err = send_signed(&mut banks, &payer, &[&user], &[add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 5, &[user_coupon, coupon])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewerNotVerified));
+
+	send_signed(&mut banks, &user, &[], &[redeem_coupon_ix(&program_id, &user.pubkey(), &other_deal, &user_coupon, &mint)]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[add_review_ix(&program_id, &user.pubkey(), &merchant, 1, 5, &[redeem_pda(&program_id, &mint), coupon])]).await.unwrap();
+	assert!(read_review(&mut banks, &program_id, &other_deal, &user.pubkey()).await.verified_purchase);
+}
+
+fn edit_review_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey, data: DealInstruction) -> Instruction {
+	let deal = deal_pda(program_id, merchant, 0);
+	ix(
+		program_id,
+		data,
+		vec![
+			AccountMeta::new(*user, true),
+			AccountMeta::new(merchant_pda(program_id, merchant), false),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(review_pda(program_id, &deal, user), false),
+		],
+	)
+}
+
+fn delete_review_ix(program_id: &Pubkey, user: &Pubkey, merchant: &Pubkey) -> Instruction {
+	let mut delete = edit_review_ix(program_id, user, merchant, DealInstruction::DeleteReview { deal_id: 0 });
+	let review = delete.accounts[3].pubkey;
+	delete.accounts.push(AccountMeta::new(reply_pda(program_id, &review), false));
+	delete.accounts.push(AccountMeta::new(*merchant, false));
+	delete
+}
+
+#[tokio::test]
+async fn reviews_can_be_edited_and_deleted_by_their_author() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let (coupon_mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		system_instruction::transfer(&merchant, &user.pubkey(), 100_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[
+		count_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint),
+		redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint),
+		add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 2, &[redeem_pda(&program_id, &coupon_mint), coupon_pda(&program_id, &coupon_mint)]),
+	]).await.unwrap();
+	assert_eq!(read_review(&mut banks, &program_id, &deal, &user.pubkey()).await.edited_at, 0);
+	let ratings = |deal: Deal| (deal.rating_sum, deal.rating_count);
+	assert_eq!(ratings(read_deal(&mut banks, deal).await), (2, 1));
+
+	let update = |rating: u8| DealInstruction::UpdateReview { deal_id: 0, rating, comment: "Better the second time".into() };
+	let err = send_signed(&mut banks, &user, &[], &[edit_review_ix(&program_id, &user.pubkey(), &merchant, update(6))]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+	send_signed(&mut banks, &user, &[], &[edit_review_ix(&program_id, &user.pubkey(), &merchant, update(4))]).await.unwrap();
+	let review = read_review(&mut banks, &program_id, &deal, &user.pubkey()).await;
+	assert_eq!((review.rating, review.comment.as_str()), (4, "Better the second time"));
+	assert!(review.edited_at > 0 && review.verified_purchase);
+	assert_eq!(ratings(read_deal(&mut banks, deal).await), (4, 1));
+
+	// Nobody else can touch it
+	let mut hijack = edit_review_ix(&program_id, &payer.pubkey(), &merchant, update(1));
+	hijack.accounts[3].pubkey = review_pda(&program_id, &deal, &user.pubkey());
+	let err = send(&mut banks, &payer, &[hijack]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::PdaDerivationMismatch));
+
+	let balance = banks.get_balance(user.pubkey()).await.unwrap();
+	let review_rent = banks.get_balance(review_pda(&program_id, &deal, &user.pubkey())).await.unwrap();
+	send_signed(&mut banks, &payer, &[&user], &[delete_review_ix(&program_id, &user.pubkey(), &merchant)]).await.unwrap();
+	assert_eq!(banks.get_balance(user.pubkey()).await.unwrap(), balance + review_rent);
+	assert!(banks.get_account(review_pda(&program_id, &deal, &user.pubkey())).await.unwrap().is_none());
+	assert_eq!(ratings(read_deal(&mut banks, deal).await), (0, 0));
+	let merchant_totals = read_merchant(&mut banks, merchant_pda(&program_id, &merchant)).await;
+	assert_eq!((merchant_totals.rating_sum, merchant_totals.rating_count), (0, 0));
+}
+
+fn reply_pda(program_id: &Pubkey, review: &Pubkey) -> Pubkey {
+	Pubkey::find_program_address(&[seeds::REPLY, review.as_ref()], program_id).0
+}
+
+fn reply_ix(program_id: &Pubkey, signer: &Pubkey, deal: &Pubkey, review: &Pubkey, comment: &str) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::ReplyToReview { comment: comment.into() },
+		vec![
+			AccountMeta::new(*signer, true),
+			AccountMeta::new_readonly(*deal, false),
+			AccountMeta::new(*review, false),
+			AccountMeta::new(reply_pda(program_id, review), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+#[tokio::test]
+async fn only_the_deals_merchant_can_reply_once_to_a_review() {
+	let (pt, program_id) = program_test();
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let (coupon_mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		system_instruction::transfer(&merchant, &user.pubkey(), 100_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[
+		count_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint),
+		redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint),
+		add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 1, &[redeem_pda(&program_id, &coupon_mint), coupon_pda(&program_id, &coupon_mint)]),
+	]).await.unwrap();
+	let review = review_pda(&program_id, &deal, &user.pubkey());
+
+	let err = send_signed(&mut banks, &user, &[], &[reply_ix(&program_id, &user.pubkey(), &deal, &review, "Thanks!")]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+	let err = send(&mut banks, &payer, &[reply_ix(&program_id, &merchant, &deal, &review, &"x".repeat(MAX_REPLY_LEN + 1))]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+
+	send(&mut banks, &payer, &[reply_ix(&program_id, &merchant, &deal, &review, "Sorry, come back for a free refill")]).await.unwrap();
+	let account = banks.get_account(reply_pda(&program_id, &review)).await.unwrap().unwrap();
+	let reply = ReviewReply::unpack(&account.data).unwrap();
+	assert_eq!((reply.review, reply.merchant, reply.comment.as_str()), (review, merchant, "Sorry, come back for a free refill"));
+
+	let err = send(&mut banks, &payer, &[reply_ix(&program_id, &merchant, &deal, &review, "Again")]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+
+	// Deleting the review closes the reply and refunds its rent, so a new review at the same address starts unanswered
+	let mut delete = delete_review_ix(&program_id, &user.pubkey(), &merchant);
+	delete.accounts[5].pubkey = user.pubkey();
+	let err = send_signed(&mut banks, &user, &[], &[delete]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+	let balance = banks.get_balance(merchant).await.unwrap();
+	let reply_rent = account.lamports;
+	send_signed(&mut banks, &user, &[], &[delete_review_ix(&program_id, &user.pubkey(), &merchant)]).await.unwrap();
+	assert!(banks.get_account(reply_pda(&program_id, &review)).await.unwrap().is_none());
+	assert_eq!(banks.get_balance(merchant).await.unwrap(), balance + reply_rent);
+	send_signed(&mut banks, &user, &[], &[add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 5, &[redeem_pda(&program_id, &coupon_mint), coupon_pda(&program_id, &coupon_mint)])]).await.unwrap();
+	send(&mut banks, &payer, &[reply_ix(&program_id, &merchant, &deal, &review, "Glad you came back")]).await.unwrap();
+}
+
+fn flag_review_ix(program_id: &Pubkey, flagger: &Pubkey, review: &Pubkey) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::FlagReview,
+		vec![
+			AccountMeta::new(*flagger, true),
+			AccountMeta::new(*review, false),
+			AccountMeta::new(Pubkey::find_program_address(&[seeds::FLAG, review.as_ref(), flagger.as_ref()], program_id).0, false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	)
+}
+
+fn hide_review_ix(program_id: &Pubkey, admin: &Pubkey, config: &Pubkey, review: &Pubkey, hidden: bool) -> Instruction {
+	ix(
+		program_id,
+		DealInstruction::HideReview { hidden },
+		vec![AccountMeta::new_readonly(*admin, true), AccountMeta::new_readonly(*config, false), AccountMeta::new(*review, false)],
+	)
+}
+
+#[tokio::test]
+async fn flagged_reviews_are_counted_once_per_wallet_and_hidden_by_the_admin() {
+	let (mut pt, program_id) = program_test();
+	let admin = Keypair::new();
+	let (config, bump) = Pubkey::find_program_address(&[seeds::CONFIG], &program_id);
+	pt.add_account(config, Account {
+		lamports: 1_000_000_000,
+		data: packed(&Config { admin: admin.pubkey(), bump }, Config::space()),
+		owner: program_id,
+		..Account::default()
+	});
+	let (mut banks, payer, _) = pt.start().await;
+	let merchant = payer.pubkey();
+	let deal = deal_pda(&program_id, &merchant, 0);
+	let user = Keypair::new();
+	let (coupon_mint, user_coupon) = create_coupon(&mut banks, &payer, &user.pubkey()).await;
+	send(&mut banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		system_instruction::transfer(&merchant, &user.pubkey(), 100_000_000),
+	]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[
+		count_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &coupon_mint),
+		redeem_coupon_ix(&program_id, &user.pubkey(), &deal, &user_coupon, &coupon_mint),
+		add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 1, &[redeem_pda(&program_id, &coupon_mint), coupon_pda(&program_id, &coupon_mint)]),
+	]).await.unwrap();
+	let review = review_pda(&program_id, &deal, &user.pubkey());
+
+	send(&mut banks, &payer, &[flag_review_ix(&program_id, &merchant, &review)]).await.unwrap();
+	send_signed(&mut banks, &user, &[], &[flag_review_ix(&program_id, &user.pubkey(), &review)]).await.unwrap();
+	let err = send_signed(&mut banks, &payer, &[&user], &[flag_review_ix(&program_id, &user.pubkey(), &review)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+	assert_eq!(read_review(&mut banks, &program_id, &deal, &user.pubkey()).await.flags, 2);
+
+	let hide = |signer: &Pubkey, hidden: bool| hide_review_ix(&program_id, signer, &config, &review, hidden);
+	let err = send(&mut banks, &payer, &[hide(&merchant, true)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+	send_signed(&mut banks, &payer, &[&admin], &[hide(&admin.pubkey(), true)]).await.unwrap();
+	assert!(read_review(&mut banks, &program_id, &deal, &user.pubkey()).await.hidden);
+	// A hidden review stays until the admin restores it, so it can't be deleted and posted again
+	let delete = delete_review_ix(&program_id, &user.pubkey(), &merchant);
+	let err = send_signed(&mut banks, &user, &[], std::slice::from_ref(&delete)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::ReviewHidden));
+	send_signed(&mut banks, &payer, &[&admin], &[hide(&admin.pubkey(), false)]).await.unwrap();
+	assert!(!read_review(&mut banks, &program_id, &deal, &user.pubkey()).await.hidden);
+	// Another fee payer, or the retry is the same transaction and gets the rejection back
+	send_signed(&mut banks, &payer, &[&user], &[delete]).await.unwrap();
+
+	// Only the upgrade authority of an upgradeable deployment can name the admin
+	let set_admin = ix(
+		&program_id,
+		DealInstruction::SetAdmin { admin: merchant.to_bytes() },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(config, false),
+			AccountMeta::new_readonly(program_id, false),
+			AccountMeta::new_readonly(Pubkey::new_unique(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	let err = send(&mut banks, &payer, &[set_admin]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::InvalidInput));
+}
+
+// ProgramTest runs this program as a builtin, so SetAdmin is called directly with upgradeable loader accounts
+#[test]
+fn upgrade_authority_names_and_hands_over_the_admin() {
+	use solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+
+	let program_id = Pubkey::new_unique();
+	let loader = bpf_loader_upgradeable::id();
+	let system = system_program::id();
+	let authority = Pubkey::new_unique();
+	let programdata = Pubkey::find_program_address(&[program_id.as_ref()], &loader).0;
+	let (config, bump) = Pubkey::find_program_address(&[seeds::CONFIG], &program_id);
+	let mut config_data = packed(&Config { admin: Pubkey::new_unique(), bump }, Config::space());
+
+	let mut set_admin = |signer: Pubkey, upgrade_authority: Option<Pubkey>, admin: Pubkey| {
+		let mut program_bytes = bincode::serialize(&UpgradeableLoaderState::Program { programdata_address: programdata }).unwrap();
+		// The program's bytes follow the ProgramData header
+		let mut programdata_bytes = bincode::serialize(&UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address: upgrade_authority }).unwrap();
+		programdata_bytes.extend([0; 64]);
+		let (mut no_data, mut no_code) = ([0u8; 0], [0u8; 0]);
+		let mut lamports = [1_000_000_000u64; 5];
+		let [l0, l1, l2, l3, l4] = &mut lamports;
+		let accounts = [
+			AccountInfo::new(&signer, true, true, l0, &mut no_data, &system, false, 0),
+			AccountInfo::new(&config, false, true, l1, &mut config_data, &program_id, false, 0),
+			AccountInfo::new(&program_id, false, false, l2, &mut program_bytes, &loader, true, 0),
+			AccountInfo::new(&programdata, false, false, l3, &mut programdata_bytes, &loader, false, 0),
+			AccountInfo::new(&system, false, false, l4, &mut no_code, &system, true, 0),
+		];
+		crate::process_instruction(&program_id, &accounts, &DealInstruction::SetAdmin { admin: admin.to_bytes() }.try_to_vec().unwrap())
+	};
+
+	let admin = Pubkey::new_unique();
+	assert_eq!(set_admin(Pubkey::new_unique(), Some(authority), admin), Err(DealError::Unauthorized.into()));
+	// A program deployed as final has no upgrade authority left to name anyone
+	assert_eq!(set_admin(authority, None, admin), Err(DealError::Unauthorized.into()));
+	set_admin(authority, Some(authority), admin).unwrap();
+	assert_eq!(Config::unpack(&config_data).unwrap().admin, admin);
+}
+
+#[tokio::test]
+async fn forged_merchant_accounts_are_rejected() {
+	let (mut pt, program_id) = program_test();
+	let merchant_key = Pubkey::new_unique();
+	let forged = Merchant {
+		merchant: merchant_key,
+		name: "Monke Cafe".into(),
+		uri: "".into(),
+		total_deals: 1,
+		collection_mint: Pubkey::default(),
+		next_deal_id: 1,
+		rating_sum: 0,
+		rating_count: 0,
+		bump: Pubkey::find_program_address(&[seeds::MERCHANT, merchant_key.as_ref()], &program_id).1,
+	};
+	// Same data, once owned by another program and once by this program but away from the merchant PDA
+	let foreign = Pubkey::new_unique();
+	let misplaced = Pubkey::new_unique();
+	for (key, owner) in [(foreign, Pubkey::new_unique()), (misplaced, program_id)] {
+		pt.add_account(key, Account { lamports: 1_000_000_000, data: packed(&forged, Merchant::space()), owner, ..Account::default() });
+	}
+	let (mut banks, payer, _) = pt.start().await;
+	let user = payer.pubkey();
+
+	for (merchant_account, expected) in [(foreign, DealError::IncorrectOwner), (misplaced, DealError::PdaDerivationMismatch)] {
+		let expected = custom_err(expected);
+		let mut mint = mint_coupon_ix(&program_id, &user, &merchant_key, 0);
+		mint.accounts[1].pubkey = merchant_account;
+		let mut paid_mint = paid_mint_ix(&program_id, &user, &merchant_key, 0, &Pubkey::new_unique());
+		paid_mint.accounts[1].pubkey = merchant_account;
+		let mut review = add_review_ix(&program_id, &user, &merchant_key, 0, 5, &[Pubkey::new_unique()]);
+		review.accounts[1].pubkey = merchant_account;
+		for ix in [mint, paid_mint, review] {
+			let err = send(&mut banks, &payer, &[ix]).await.unwrap_err();
+			assert_eq!(err, expected);
+		}
+	}
+}
+
+#[test]
+fn account_headers_reject_other_kinds_and_versions() {
+	let listing = Listing { seller: Pubkey::new_unique(), nft_mint: Pubkey::new_unique(), price: 5, created_at: 0, payment_mint: None, bump: 255 };
+	let mut data = packed(&listing, Listing::space());
+	assert_eq!(Listing::unpack(&data).unwrap(), listing);
+	assert!(Merchant::unpack(&data).is_err());
+	assert!(Deal::unpack(&data).is_err());
+
+	data[1] = Listing::VERSION + 1;
+	assert!(Listing::unpack(&data).is_err());
+	assert!(Listing::unpack(&[]).is_err());
+}
+
+fn migrate_ix(program_id: &Pubkey, payer: &Pubkey, account: &Pubkey, deals: &[Pubkey]) -> Instruction {
+	let mut accounts = vec![
+		AccountMeta::new(*payer, true),
+		AccountMeta::new(*account, false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	accounts.extend(deals.iter().map(|deal| AccountMeta::new_readonly(*deal, false)));
+	ix(program_id, DealInstruction::MigrateAccount, accounts)
+}
+
+#[tokio::test]
+async fn legacy_accounts_migrate_to_current_layout() {
+	let (mut pt, program_id) = program_test();
+	let merchant = Keypair::new();
+	let key = merchant.pubkey();
+	let legacy_rent = |space: usize| solana_program::rent::Rent::default().minimum_balance(space);
+	pt.add_account(key, Account { lamports: 10_000_000_000, ..Account::default() });
+
+	let old_merchant = MerchantV0 { merchant: key, name: "Monke Cafe".into(), uri: "".into(), total_deals: 2, collection_mint: Pubkey::default() };
+	let mut data = vec![0; MerchantV0::space()];
+	old_merchant.serialize(&mut &mut data[..]).unwrap();
+	pt.add_account(merchant_pda(&program_id, &key), Account { lamports: legacy_rent(data.len()), data, owner: program_id, ..Account::default() });
+	// Before the counter existed merchants chose their own deal ids
+	for deal_id in [0, 5, 6] {
+		let old_deal = DealV0 {
+			deal_id,
+			merchant: key,
+			title: "Old deal".into(),
+			description: "".into(),
+			discount_percent: 25,
+			expiry: i64::MAX,
+			total_supply: 10,
+			minted: 4,
+			image_uri: "".into(),
+			metadata_uri: "".into(),
+		};
+		let mut data = vec![0; DealV0::space()];
+		old_deal.serialize(&mut &mut data[..]).unwrap();
+		pt.add_account(deal_pda(&program_id, &key, deal_id), Account { lamports: legacy_rent(data.len()), data, owner: program_id, ..Account::default() });
+	}
+	let (mut banks, payer, _) = pt.start().await;
+	let rent = banks.get_rent().await.unwrap();
+	let merchant_account = merchant_pda(&program_id, &key);
+	let deals = [deal_pda(&program_id, &key, 0), deal_pda(&program_id, &key, 5)];
+
+	assert!(send(&mut banks, &payer, &[mint_coupon_ix(&program_id, &payer.pubkey(), &key, 5)]).await.is_err());
+	let err = send(&mut banks, &payer, &[migrate_ix(&program_id, &payer.pubkey(), &deals[1], &[])]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::Unauthorized));
+
+	send(&mut banks, &merchant, &[migrate_ix(&program_id, &key, &merchant_account, &deals)]).await.unwrap();
+	let account = banks.get_account(merchant_account).await.unwrap().unwrap();
+	assert_eq!(account.data.len(), Merchant::space());
+	assert!(rent.is_exempt(account.lamports, account.data.len()));
+	let migrated = read_merchant(&mut banks, merchant_account).await;
+	let bump = Pubkey::find_program_address(&[seeds::MERCHANT, key.as_ref()], &program_id).1;
+	assert_eq!(migrated, old_merchant.clone().into_current(6, bump));
+
+	for deal in deals {
+		send(&mut banks, &merchant, &[migrate_ix(&program_id, &key, &deal, &[])]).await.unwrap();
+		let account = banks.get_account(deal).await.unwrap().unwrap();
+		assert_eq!(account.data.len(), Deal::space());
+		assert!(rent.is_exempt(account.lamports, account.data.len()));
+		let deal = read_deal(&mut banks, deal).await;
+		assert_eq!(deal.discount, DiscountKind::Percent(25));
+		assert_eq!((deal.minted, deal.status), (4, DealStatus::Active));
+	}
+
+	// Migrated accounts work with current instructions and new ids continue past the legacy ones
+	send(&mut banks, &payer, &[mint_coupon_ix(&program_id, &payer.pubkey(), &key, 5)]).await.unwrap();
+	assert_eq!(read_deal(&mut banks, deals[1]).await.minted, 5);
+	// Deal 6 was left out of the merchant's migration, so CreateDeal only skips it once it is migrated and passed
+	let err = send(&mut banks, &merchant, &[create_deal_ix(&program_id, &key, 6, 10)]).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AlreadyInitialized));
+	let mut create = create_deal_ix(&program_id, &key, 7, 10);
+	create.accounts.push(AccountMeta::new_readonly(deal_pda(&program_id, &key, 6), false));
+	let err = send(&mut banks, &merchant, std::slice::from_ref(&create)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AccountKindMismatch));
+	send(&mut banks, &merchant, &[migrate_ix(&program_id, &key, &deal_pda(&program_id, &key, 6), &[]), create]).await.unwrap();
+	assert_eq!(read_merchant(&mut banks, merchant_account).await.next_deal_id, 8);
+	let current = deal_pda(&program_id, &key, 7);
+	let before = banks.get_account(current).await.unwrap().unwrap();
+	send(&mut banks, &merchant, &[migrate_ix(&program_id, &key, &current, &[])]).await.unwrap();
+	assert_eq!(banks.get_account(current).await.unwrap().unwrap(), before);
+}
+
+#[tokio::test]
+async fn legacy_reviews_redeem_logs_and_listings_migrate() {
+	let (mut pt, program_id) = program_test();
+	let (seller, user, nft) = (Pubkey::new_unique(), Pubkey::new_unique(), Keypair::new());
+	let deal = deal_pda(&program_id, &Pubkey::new_unique(), 0);
+	let coupon = Pubkey::new_unique();
+	let legacy = |value: &dyn Fn(&mut [u8]), space: usize| {
+		let mut data = vec![0; space];
+		value(&mut data);
+		Account { lamports: solana_program::rent::Rent::default().minimum_balance(space), data, owner: program_id, ..Account::default() }
+	};
+
+	let old_review = ReviewV0 { user, deal, rating: 4, comment: "Nice".into(), created_at: 1 };
+	let review = review_pda(&program_id, &deal, &user);
+	pt.add_account(review, legacy(&|data| old_review.serialize(&mut &mut data[..]).unwrap(), ReviewV0::space()));
+	let old_log = RedeemLogV0 { token_mint: coupon, user, redeemed_at: 2 };
+	pt.add_account(redeem_pda(&program_id, &coupon), legacy(&|data| old_log.serialize(&mut &mut data[..]).unwrap(), RedeemLogV0::space()));
+	let old_listing = ListingV0 { seller, nft_mint: nft.pubkey(), price: 1_000_000, created_at: 3 };
+	let listing = Pubkey::find_program_address(&[seeds::LISTING, nft.pubkey().as_ref(), seller.as_ref()], &program_id).0;
+	pt.add_account(listing, legacy(&|data| old_listing.serialize(&mut &mut data[..]).unwrap(), ListingV0::space()));
+	// A current raffle entry is as long as a legacy redeem log
+	let entry_key = entry_pda(&program_id, &deal, &user);
+	let entry = RaffleEntry { deal, user, index: 0, minted: false, bump: Pubkey::find_program_address(&[seeds::ENTRY, deal.as_ref(), user.as_ref()], &program_id).1 };
+	assert_eq!(RaffleEntry::space(), RedeemLogV0::space());
+	pt.add_account(entry_key, Account { lamports: 1_000_000_000, data: packed(&entry, RaffleEntry::space()), owner: program_id, ..Account::default() });
+	let (mut banks, payer, _) = pt.start().await;
+
+	// A legacy listing can't be bought, and there is no other way to get the NFT back out of escrow
+	let nft_mint = create_mint_with(&mut banks, &payer, &nft, 0).await;
+	let escrow = Pubkey::find_program_address(&[seeds::ESCROW, nft_mint.as_ref()], &program_id).0;
+	let escrow_nft = create_token_account(&mut banks, &payer, &nft_mint, &escrow, 1).await;
+	let buyer_nft = create_token_account(&mut banks, &payer, &nft_mint, &payer.pubkey(), 0).await;
+	let buy = ix(
+		&program_id,
+		DealInstruction::BuyNft,
+		vec![
+			AccountMeta::new(payer.pubkey(), true),
+			AccountMeta::new(seller, false),
+			AccountMeta::new(listing, false),
+			AccountMeta::new_readonly(nft_mint, false),
+			AccountMeta::new_readonly(escrow, false),
+			AccountMeta::new(escrow_nft, false),
+			AccountMeta::new(buyer_nft, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	let err = send(&mut banks, &payer, std::slice::from_ref(&buy)).await.unwrap_err();
+	assert_eq!(err, custom_err(DealError::AccountKindMismatch));
+
+	// Only fields with fixed defaults are added, so any wallet can pay for the migration
+	for account in [review, redeem_pda(&program_id, &coupon), listing, entry_key] {
+		send(&mut banks, &payer, &[migrate_ix(&program_id, &payer.pubkey(), &account, &[])]).await.unwrap();
+	}
+	let migrated = read_review(&mut banks, &program_id, &deal, &user).await;
+	assert_eq!(migrated, Review { bump: Pubkey::find_program_address(&[seeds::REVIEW, deal.as_ref(), user.as_ref()], &program_id).1, ..old_review.into() });
+	assert!(!migrated.verified_purchase);
+	let account = banks.get_account(redeem_pda(&program_id, &coupon)).await.unwrap().unwrap();
+	assert_eq!(RedeemLog::unpack(&account.data).unwrap(), RedeemLog { bump: Pubkey::find_program_address(&[seeds::REDEEM, coupon.as_ref()], &program_id).1, ..old_log.into() });
+	assert_eq!(RaffleEntry::unpack(&banks.get_account(entry_key).await.unwrap().unwrap().data).unwrap(), entry);
+
+	send(&mut banks, &payer, &[buy]).await.unwrap();
+	assert_eq!(token_balance(&mut banks, buyer_nft).await, 1);
+	// The seller gets the price and the listing's rent, now sized for the current layout
+	let listing_rent = banks.get_rent().await.unwrap().minimum_balance(Listing::space());
+	assert_eq!(banks.get_account(seller).await.unwrap().unwrap().lamports, 1_000_000 + listing_rent);
+	assert!(banks.get_account(listing).await.unwrap().is_none());
+}
+
+fn seeded(seed: u8) -> Keypair {
+	solana_sdk::signer::keypair::keypair_from_seed(&[seed; 32]).unwrap()
+}
+
+// Units the runtime meters for one instruction
+async fn bench(banks: &mut BanksClient, name: &'static str, payer: &Keypair, signers: &[&Keypair], ix: Instruction) -> (&'static str, u64) {
+	let blockhash = banks.get_latest_blockhash().await.unwrap();
+	let mut all: Vec<&Keypair> = vec![payer];
+	all.extend_from_slice(signers);
+	let tx = Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &all, blockhash);
+	let simulated = banks.simulate_transaction(tx.clone()).await.unwrap();
+	let details = simulated.simulation_details.unwrap();
+	assert_eq!(simulated.result, Some(Ok(())), "{} failed: {:#?}", name, details.logs);
+	banks.process_transaction(tx).await.unwrap();
+	(name, details.units_consumed)
+}
+
+// Units each instruction consumed before accounts stored their bumps, recorded when the bumps were introduced
+const UNITS_BEFORE_STORED_BUMPS: &[(&str, u64)] = &[
+	("RegisterMerchant", 1768),
+	("CreateDeal", 3268),
+	("SetCollectionMint", 1501),
+	("MintCouponNft", 3141),
+	("SetRedemptionSchedule", 1501),
+	("RedeemCoupon", 4908),
+	("RedeemAndBurn", 8115),
+	("AddReview", 7908),
+	("UpdateReview", 4641),
+	("ReplyToReview", 4908),
+	("FlagReview", 3408),
+	("HideReview", 4501),
+	("DeleteReview", 4501),
+	("ListNft", 8053),
+	("BuyNft", 9296),
+	("SetMintPrice", 3268),
+	("VerifyAndCountMint", 12558),
+	("SetDealStatus", 1501),
+	("ClaimRefund", 15348),
+	("SetTokenMintPrice", 4768),
+	("SetGroupBuy", 1641),
+	("FinalizeGroupDeal", 3001),
+	("SetPriceCurve", 6268),
+	("SetRaffle", 1641),
+	("EnterRaffle", 4908),
+	("SetAllowlist", 1501),
+	("SetGate", 1501),
+	("AirdropCoupons", 1641),
+	("CreateClaim", 11082),
+	("Claim", 12330),
+	("DrawRaffle", 1641),
+	("RefundGroupDeal", 13848),
+	("ReclaimExpired", 12330),
+	("RecountDeals", 16501),
+	("MigrateAccount", 7618),
+];
+
+// Runs every instruction once on its success path against the BPF build, since native programs aren't metered:
+// `cargo build-sbf`, then `cargo test compute_units_per_instruction -- --ignored --nocapture`. Keys, mints and the
+// program id are fixed because the cost of `find_program_address` depends on how many bumps it tries for each
+// address. SetAdmin is left out: it needs an upgradeable deployment, which ProgramTest doesn't create.
+#[tokio::test]
+#[ignore = "needs the program built with `cargo build-sbf`"]
+async fn compute_units_per_instruction() {
+	let program_id = Pubkey::new_from_array([9; 32]);
+	let mut pt = ProgramTest::new("deal_platform", program_id, None);
+	pt.prefer_bpf(true);
+	let (payer, user, legacy) = (seeded(1), seeded(2), seeded(3));
+	let merchant = payer.pubkey();
+	for key in [merchant, user.pubkey(), legacy.pubkey()] {
+		pt.add_account(key, Account { lamports: 100_000_000_000, ..Account::default() });
+	}
+	let (config, bump) = Pubkey::find_program_address(&[seeds::CONFIG], &program_id);
+	pt.add_account(config, Account { lamports: 1_000_000_000, data: packed(&Config { admin: merchant, bump }, Config::space()), owner: program_id, ..Account::default() });
+	let old_merchant = MerchantV0 { merchant: legacy.pubkey(), name: "Old cafe".into(), uri: "".into(), total_deals: 1, collection_mint: Pubkey::default() };
+	let mut data = vec![0; MerchantV0::space()];
+	old_merchant.serialize(&mut &mut data[..]).unwrap();
+	pt.add_account(merchant_pda(&program_id, &legacy.pubkey()), Account { lamports: 1_000_000_000, data, owner: program_id, ..Account::default() });
+	let old_deal = DealV0 {
+		deal_id: 0,
+		merchant: legacy.pubkey(),
+		title: "Old deal".into(),
+		description: "".into(),
+		discount_percent: 25,
+		expiry: i64::MAX,
+		total_supply: 10,
+		minted: 0,
+		image_uri: "".into(),
+		metadata_uri: "".into(),
+	};
+	let mut data = vec![0; DealV0::space()];
+	old_deal.serialize(&mut &mut data[..]).unwrap();
+	pt.add_account(deal_pda(&program_id, &legacy.pubkey(), 0), Account { lamports: 1_000_000_000, data, owner: program_id, ..Account::default() });
+
+	let ctx = pt.start_with_context().await;
+	let mut banks = ctx.banks_client.clone();
+	let mut clock: Clock = banks.get_sysvar().await.unwrap();
+	let deadline = clock.unix_timestamp + DAY;
+	let deal = |deal_id: u64| deal_pda(&program_id, &merchant, deal_id);
+	let mut mints = (10..).map(seeded);
+	let mut units = Vec::new();
+
+	units.push(bench(&mut banks, "RegisterMerchant", &payer, &[], register_merchant_ix(&program_id, &merchant)).await);
+	units.push(bench(&mut banks, "CreateDeal", &payer, &[], create_deal_ix(&program_id, &merchant, 0, 100)).await);
+	// 1 refunded, 2 token priced and scheduled, 3 and 4 group buys, 5 Dutch auction, 6 raffle, 7 allowlist, 8 gated
+	send(&mut banks, &payer, &(1..5).map(|id| create_deal_ix(&program_id, &merchant, id, 100)).collect::<Vec<_>>()).await.unwrap();
+	send(&mut banks, &payer, &(5..9).map(|id| create_deal_ix(&program_id, &merchant, id, 100)).collect::<Vec<_>>()).await.unwrap();
+
+	let set_collection = ix(
+		&program_id,
+		DealInstruction::SetCollectionMint { collection_mint: Pubkey::new_from_array([4; 32]).to_bytes() },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(merchant_pda(&program_id, &merchant), false)],
+	);
+	units.push(bench(&mut banks, "SetCollectionMint", &payer, &[], set_collection).await);
+	units.push(bench(&mut banks, "MintCouponNft", &user, &[], mint_coupon_ix(&program_id, &user.pubkey(), &merchant, 0)).await);
+	let schedule = RedemptionSchedule { weekdays: 0x7f, start_minute: 9 * 60, end_minute: 17 * 60, utc_offset_minutes: 0 };
+	let set_schedule = ix(
+		&program_id,
+		DealInstruction::SetRedemptionSchedule { deal_id: 2, schedule: Some(schedule) },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal(2), false)],
+	);
+	units.push(bench(&mut banks, "SetRedemptionSchedule", &payer, &[], set_schedule).await);
+
+	// Redemptions and reviews on the free deal
+	let redeemed_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let user_redeemed = create_token_account(&mut banks, &payer, &redeemed_mint, &user.pubkey(), 1).await;
+	units.push(bench(&mut banks, "RedeemCoupon", &user, &[], redeem_coupon_ix(&program_id, &user.pubkey(), &deal(0), &user_redeemed, &redeemed_mint)).await);
+	let burn_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let user_burn = create_token_account(&mut banks, &payer, &burn_mint, &user.pubkey(), 1).await;
+	let counts = [redeemed_mint, burn_mint].map(|mint| count_mint_ix(&program_id, &user.pubkey(), &merchant, 0, &mint));
+	send_signed(&mut banks, &user, &[], &counts).await.unwrap();
+	units.push(bench(&mut banks, "RedeemAndBurn", &user, &[], redeem_and_burn_ix(&program_id, &user.pubkey(), &deal(0), &user_burn, &burn_mint)).await);
+	let redeem_log = [redeem_pda(&program_id, &redeemed_mint), coupon_pda(&program_id, &redeemed_mint)];
+	units.push(bench(&mut banks, "AddReview", &user, &[], add_review_ix(&program_id, &user.pubkey(), &merchant, 0, 4, &redeem_log)).await);
+	let update = DealInstruction::UpdateReview { deal_id: 0, rating: 5, comment: "Even better".into() };
+	units.push(bench(&mut banks, "UpdateReview", &user, &[], edit_review_ix(&program_id, &user.pubkey(), &merchant, update)).await);
+	let review = review_pda(&program_id, &deal(0), &user.pubkey());
+	units.push(bench(&mut banks, "ReplyToReview", &payer, &[], reply_ix(&program_id, &merchant, &deal(0), &review, "Thanks!")).await);
+	units.push(bench(&mut banks, "FlagReview", &payer, &[], flag_review_ix(&program_id, &merchant, &review)).await);
+	units.push(bench(&mut banks, "HideReview", &payer, &[], hide_review_ix(&program_id, &merchant, &config, &review, true)).await);
+	// Hidden reviews can't be deleted
+	send(&mut banks, &payer, &[hide_review_ix(&program_id, &merchant, &config, &review, false)]).await.unwrap();
+	let delete = delete_review_ix(&program_id, &user.pubkey(), &merchant);
+	units.push(bench(&mut banks, "DeleteReview", &user, &[], delete).await);
+
+	// Marketplace
+	let nft = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let escrow = Pubkey::find_program_address(&[seeds::ESCROW, nft.as_ref()], &program_id).0;
+	let seller_nft = create_token_account(&mut banks, &payer, &nft, &merchant, 1).await;
+	let escrow_nft = create_token_account(&mut banks, &payer, &nft, &escrow, 0).await;
+	let buyer_nft = create_token_account(&mut banks, &payer, &nft, &user.pubkey(), 0).await;
+	let listing = Pubkey::find_program_address(&[seeds::LISTING, nft.as_ref(), merchant.as_ref()], &program_id).0;
+	let list = ix(
+		&program_id,
+		DealInstruction::ListNft { price: 1_000_000, payment_mint: None },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(listing, false),
+			AccountMeta::new_readonly(nft, false),
+			AccountMeta::new(seller_nft, false),
+			AccountMeta::new(escrow_nft, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "ListNft", &payer, &[], list).await);
+	let buy = ix(
+		&program_id,
+		DealInstruction::BuyNft,
+		vec![
+			AccountMeta::new(user.pubkey(), true),
+			AccountMeta::new(merchant, false),
+			AccountMeta::new(listing, false),
+			AccountMeta::new_readonly(nft, false),
+			AccountMeta::new_readonly(escrow, false),
+			AccountMeta::new(escrow_nft, false),
+			AccountMeta::new(buyer_nft, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "BuyNft", &user, &[], buy).await);
+
+	// Paid deal 1: escrowed mint, then a refund once it is cancelled
+	units.push(bench(&mut banks, "SetMintPrice", &payer, &[], set_mint_price_ix(&program_id, &merchant, 1, 1_000_000)).await);
+	let refunded_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let user_refunded = create_token_account(&mut banks, &payer, &refunded_mint, &user.pubkey(), 1).await;
+	units.push(bench(&mut banks, "VerifyAndCountMint", &user, &[], paid_mint_ix(&program_id, &user.pubkey(), &merchant, 1, &refunded_mint)).await);
+	units.push(bench(&mut banks, "SetDealStatus", &payer, &[], set_deal_status_ix(&program_id, &merchant, 1, DealStatus::Cancelled)).await);
+	let refund = refund_ix(&program_id, DealInstruction::ClaimRefund { mint: refunded_mint.to_bytes() }, &user.pubkey(), &user_refunded, &refunded_mint, &deal(1));
+	units.push(bench(&mut banks, "ClaimRefund", &user, &[], refund).await);
+
+	// Token price on deal 2
+	let usdc = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 6).await;
+	let set_token_price = ix(
+		&program_id,
+		DealInstruction::SetTokenMintPrice { deal_id: 2, payment_mint: Some(usdc.to_bytes()), amount: 1_000_000 },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal(2), false),
+			AccountMeta::new(vault_pda(&program_id, &deal(2)), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "SetTokenMintPrice", &payer, &[], set_token_price).await);
+
+	// Group buys: deal 3 tips, deal 4 misses its threshold and is refunded after the deadline
+	send(&mut banks, &payer, &[set_mint_price_ix(&program_id, &merchant, 3, 1_000_000), set_mint_price_ix(&program_id, &merchant, 4, 1_000_000)]).await.unwrap();
+	units.push(bench(&mut banks, "SetGroupBuy", &payer, &[], set_group_buy_ix(&program_id, &merchant, 3, 2, deadline)).await);
+	send(&mut banks, &payer, &[set_group_buy_ix(&program_id, &merchant, 4, 2, deadline)]).await.unwrap();
+	let group_coupons = [Pubkey::new_from_array([6; 32]), Pubkey::new_from_array([7; 32])];
+	send_signed(&mut banks, &user, &[], &group_coupons.map(|mint| paid_mint_ix(&program_id, &user.pubkey(), &merchant, 3, &mint))).await.unwrap();
+	units.push(bench(&mut banks, "FinalizeGroupDeal", &payer, &[], finalize_group_deal_ix(&program_id, &merchant, 3, &group_coupons)).await);
+	let stranded_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let user_stranded = create_token_account(&mut banks, &payer, &stranded_mint, &user.pubkey(), 1).await;
+	send_signed(&mut banks, &user, &[], &[paid_mint_ix(&program_id, &user.pubkey(), &merchant, 4, &stranded_mint)]).await.unwrap();
+
+	// Dutch auction on deal 5
+	let curve = PriceCurve { start_price: 2_000_000, end_price: 1_000_000, start_ts: clock.unix_timestamp, end_ts: deadline, step_seconds: HOUR as u32 };
+	let set_curve = ix(
+		&program_id,
+		DealInstruction::SetPriceCurve { deal_id: 5, curve: Some(curve) },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal(5), false),
+			AccountMeta::new(vault_pda(&program_id, &deal(5)), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "SetPriceCurve", &payer, &[], set_curve).await);
+
+	// Raffle on deal 6, drawn after the deadline
+	let set_raffle = ix(
+		&program_id,
+		DealInstruction::SetRaffle { deal_id: 6, winners: 1, entry_deadline: deadline },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal(6), false)],
+	);
+	units.push(bench(&mut banks, "SetRaffle", &payer, &[], set_raffle).await);
+	let enter = ix(
+		&program_id,
+		DealInstruction::EnterRaffle { deal_id: 6 },
+		vec![
+			AccountMeta::new(user.pubkey(), true),
+			AccountMeta::new_readonly(merchant_pda(&program_id, &merchant), false),
+			AccountMeta::new(deal(6), false),
+			AccountMeta::new(entry_pda(&program_id, &deal(6), &user.pubkey()), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "EnterRaffle", &user, &[], enter).await);
+
+	// Allowlist on deal 7 and token gate on deal 8
+	let set_allowlist = ix(
+		&program_id,
+		DealInstruction::SetAllowlist { deal_id: 7, root: merkle::leaf(&user.pubkey(), 0) },
+		vec![AccountMeta::new(merchant, true), AccountMeta::new(deal(7), false)],
+	);
+	units.push(bench(&mut banks, "SetAllowlist", &payer, &[], set_allowlist).await);
+	let set_gate = set_gate_ix(&program_id, &merchant, 8, Some(DealGate::TokenBalance { mint: usdc, min_amount: 1 }));
+	units.push(bench(&mut banks, "SetGate", &payer, &[], set_gate).await);
+
+	// Airdrop and claim links on the free deal
+	let airdropped_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+	let recipient = create_token_account(&mut banks, &payer, &airdropped_mint, &Pubkey::new_from_array([8; 32]), 1).await;
+	let airdrop = ix(
+		&program_id,
+		DealInstruction::AirdropCoupons { deal_id: 0 },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal(0), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new_readonly(recipient, false),
+			AccountMeta::new_readonly(airdropped_mint, false),
+			AccountMeta::new(coupon_pda(&program_id, &airdropped_mint), false),
+		],
+	);
+	units.push(bench(&mut banks, "AirdropCoupons", &payer, &[], airdrop).await);
+	let mut links = Vec::new();
+	for (i, ephemeral) in [seeded(4), seeded(5)].into_iter().enumerate() {
+		let claim_hash = solana_program::hash::hash(ephemeral.pubkey().as_ref()).to_bytes();
+		let claim = claim_pda(&program_id, &claim_hash);
+		let coupon_mint = create_mint_with(&mut banks, &payer, &mints.next().unwrap(), 0).await;
+		let merchant_token = create_token_account(&mut banks, &payer, &coupon_mint, &merchant, 1).await;
+		let claim_token = create_token_account(&mut banks, &payer, &coupon_mint, &claim, 0).await;
+		let create = ix(
+			&program_id,
+			DealInstruction::CreateClaim { deal_id: 0, claim_hash, expires_at: deadline },
+			vec![
+				AccountMeta::new(merchant, true),
+				AccountMeta::new(deal(0), false),
+				AccountMeta::new(claim, false),
+				AccountMeta::new(merchant_token, false),
+				AccountMeta::new(claim_token, false),
+				AccountMeta::new_readonly(coupon_mint, false),
+				AccountMeta::new_readonly(spl_token::id(), false),
+				AccountMeta::new_readonly(system_program::id(), false),
+				AccountMeta::new(coupon_pda(&program_id, &coupon_mint), false),
+			],
+		);
+		let created = bench(&mut banks, "CreateClaim", &payer, &[], create).await;
+		if i == 0 { units.push(created); }
+		links.push((ephemeral, claim_hash, claim, coupon_mint, merchant_token, claim_token));
+	}
+	let (ephemeral, claim_hash, claim, coupon_mint, _, claim_token) = &links[0];
+	let destination = create_token_account(&mut banks, &payer, coupon_mint, &user.pubkey(), 0).await;
+	let claim_ix = ix(
+		&program_id,
+		DealInstruction::Claim { claim_hash: *claim_hash },
+		vec![
+			AccountMeta::new_readonly(ephemeral.pubkey(), true),
+			AccountMeta::new(*claim, false),
+			AccountMeta::new(*claim_token, false),
+			AccountMeta::new(destination, false),
+			AccountMeta::new_readonly(user.pubkey(), false),
+			AccountMeta::new_readonly(*coupon_mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new(merchant, false),
+		],
+	);
+	units.push(bench(&mut banks, "Claim", &payer, &[ephemeral], claim_ix).await);
+
+	// Past every deadline: draw the raffle, refund the failed group buy and reclaim the lapsed link
+	clock.unix_timestamp = deadline + 1;
+	ctx.set_sysvar(&clock);
+	ctx.set_sysvar(&SlotHashes::new(&[(clock.slot, Hash::new_from_array([7; 32]))]));
+	let draw = ix(
+		&program_id,
+		DealInstruction::DrawRaffle { deal_id: 6 },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal(6), false),
+			AccountMeta::new_readonly(solana_program::sysvar::slot_hashes::id(), false),
+		],
+	);
+	units.push(bench(&mut banks, "DrawRaffle", &payer, &[], draw).await);
+	let refund = refund_ix(&program_id, DealInstruction::RefundGroupDeal { mint: stranded_mint.to_bytes() }, &user.pubkey(), &user_stranded, &stranded_mint, &deal(4));
+	units.push(bench(&mut banks, "RefundGroupDeal", &user, &[], refund).await);
+	let (_, claim_hash, claim, coupon_mint, merchant_token, claim_token) = &links[1];
+	let reclaim = ix(
+		&program_id,
+		DealInstruction::ReclaimExpired { claim_hash: *claim_hash },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(*claim, false),
+			AccountMeta::new(deal(0), false),
+			AccountMeta::new(*claim_token, false),
+			AccountMeta::new(*merchant_token, false),
+			AccountMeta::new_readonly(*coupon_mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new(coupon_pda(&program_id, coupon_mint), false),
+		],
+	);
+	units.push(bench(&mut banks, "ReclaimExpired", &payer, &[], reclaim).await);
+
+	let all_deals: Vec<Pubkey> = (0..9).map(deal).collect();
+	units.push(bench(&mut banks, "RecountDeals", &payer, &[], recount_deals_ix(&program_id, &merchant, &all_deals)).await);
+	let legacy_merchant = merchant_pda(&program_id, &legacy.pubkey());
+	let legacy_deal = deal_pda(&program_id, &legacy.pubkey(), 0);
+	units.push(bench(&mut banks, "MigrateAccount", &legacy, &[], migrate_ix(&program_id, &legacy.pubkey(), &legacy_merchant, &[legacy_deal])).await);
+
+	println!("{:<24}{:>10}{:>10}", "instruction", "before", "after");
+	let (mut total_before, mut total_after) = (0, 0);
+	for (name, after) in &units {
+		let before = UNITS_BEFORE_STORED_BUMPS.iter().find(|(n, _)| n == name).map(|(_, u)| *u).unwrap();
+		println!("{:<24}{:>10}{:>10}", name, before, after);
+		assert!(*after <= before, "{name} got more expensive");
+		total_before += before;
+		total_after += after;
+	}
+	println!("{:<24}{:>10}{:>10}", "total", total_before, total_after);
+	assert_eq!(units.len(), UNITS_BEFORE_STORED_BUMPS.len());
+}
+
+#[tokio::test]
+async fn handlers_emit_events_for_indexers() {
+	let (mut pt, program_id) = program_test();
+	let payer = Keypair::new();
+	let merchant = payer.pubkey();
+	pt.add_account(merchant, Account { lamports: 10_000_000_000, ..Account::default() });
+	let (config, bump) = Pubkey::find_program_address(&[seeds::CONFIG], &program_id);
+	pt.add_account(config, Account { lamports: 1_000_000_000, data: packed(&Config { admin: merchant, bump }, Config::space()), owner: program_id, ..Account::default() });
+	let mut ctx = pt.start_with_context().await;
+	let banks = &mut ctx.banks_client;
+	let deals = [0, 1, 2].map(|id| deal_pda(&program_id, &merchant, id));
+	let deal = deals[0];
+	let review = review_pda(&program_id, &deal, &merchant);
+	let price = 20_000_000;
+	let mut clock: Clock = banks.get_sysvar().await.unwrap();
+	let (mint, coupon) = create_coupon(banks, &payer, &merchant).await;
+	let (refunded_mint, refunded_coupon) = create_coupon(banks, &payer, &merchant).await;
+	let group_mint = Pubkey::new_unique();
+	send(banks, &payer, &[
+		register_merchant_ix(&program_id, &merchant),
+		create_deal_ix(&program_id, &merchant, 0, 10),
+		create_deal_ix(&program_id, &merchant, 1, 10),
+		create_deal_ix(&program_id, &merchant, 2, 10),
+		set_mint_price_ix(&program_id, &merchant, 1, price),
+		set_group_buy_ix(&program_id, &merchant, 1, 1, clock.unix_timestamp + DAY),
+		set_mint_price_ix(&program_id, &merchant, 2, price),
+	]).await.unwrap();
+
+	// A review's whole life, from the redemption that proves the purchase to its deletion
+	send(banks, &payer, &[
+		count_mint_ix(&program_id, &merchant, &merchant, 0, &mint),
+		redeem_coupon_ix(&program_id, &merchant, &deal, &coupon, &mint),
+		add_review_ix(&program_id, &merchant, &merchant, 0, 4, &[redeem_pda(&program_id, &mint), coupon_pda(&program_id, &mint)]),
+		edit_review_ix(&program_id, &merchant, &merchant, DealInstruction::UpdateReview { deal_id: 0, rating: 5, comment: "Even better".into() }),
+		reply_ix(&program_id, &merchant, &deal, &review, "Thanks!"),
+	]).await.unwrap();
+	send(banks, &payer, &[
+		flag_review_ix(&program_id, &merchant, &review),
+		hide_review_ix(&program_id, &merchant, &config, &review, true),
+		hide_review_ix(&program_id, &merchant, &config, &review, false),
+		delete_review_ix(&program_id, &merchant, &merchant),
+	]).await.unwrap();
+
+	// A group buy that tips and a paid deal that is cancelled and refunded
+	send(banks, &payer, &[
+		paid_mint_ix(&program_id, &merchant, &merchant, 1, &group_mint),
+		finalize_group_deal_ix(&program_id, &merchant, 1, &[group_mint]),
+		paid_mint_ix(&program_id, &merchant, &merchant, 2, &refunded_mint),
+		set_deal_status_ix(&program_id, &merchant, 2, DealStatus::Cancelled),
+		refund_ix(&program_id, DealInstruction::ClaimRefund { mint: refunded_mint.to_bytes() }, &merchant, &refunded_coupon, &refunded_mint, &deals[2]),
+	]).await.unwrap();
+
+	// A claim link nobody opens before it lapses
+	let claim_hash = [9; 32];
+	let claim = claim_pda(&program_id, &claim_hash);
+	let claim_mint = create_mint(banks, &payer, 0).await;
+	let merchant_token = create_token_account(banks, &payer, &claim_mint, &merchant, 1).await;
+	let claim_token = create_token_account(banks, &payer, &claim_mint, &claim, 0).await;
+	let create_claim = ix(
+		&program_id,
+		DealInstruction::CreateClaim { deal_id: 0, claim_hash, expires_at: clock.unix_timestamp + DAY },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(claim, false),
+			AccountMeta::new(merchant_token, false),
+			AccountMeta::new(claim_token, false),
+			AccountMeta::new_readonly(claim_mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new_readonly(system_program::id(), false),
+			AccountMeta::new(coupon_pda(&program_id, &claim_mint), false),
+		],
+	);
+	send(banks, &payer, &[create_claim]).await.unwrap();
+	clock.unix_timestamp += DAY + 1;
+	ctx.set_sysvar(&clock);
+	let reclaim = ix(
+		&program_id,
+		DealInstruction::ReclaimExpired { claim_hash },
+		vec![
+			AccountMeta::new(merchant, true),
+			AccountMeta::new(claim, false),
+			AccountMeta::new(deal, false),
+			AccountMeta::new(claim_token, false),
+			AccountMeta::new(merchant_token, false),
+			AccountMeta::new_readonly(claim_mint, false),
+			AccountMeta::new_readonly(spl_token::id(), false),
+			AccountMeta::new(coupon_pda(&program_id, &claim_mint), false),
+		],
+	);
+	send(&mut ctx.banks_client, &payer, &[reclaim]).await.unwrap();
+
+	// Tests run in parallel, so keep the events of this test's merchant, deals and review
+	let ours: Vec<DealEvent> = emitted().into_iter().filter(|e| match e {
+		DealEvent::MerchantRegistered { merchant: m, .. } | DealEvent::DealCreated { merchant: m, .. } => *m == merchant,
+		DealEvent::CouponMinted { deal: d, .. }
+		| DealEvent::CouponRedeemed { deal: d, .. }
+		| DealEvent::ReviewAdded { deal: d, .. }
+		| DealEvent::ReviewUpdated { deal: d, .. }
+		| DealEvent::ReviewDeleted { deal: d, .. }
+		| DealEvent::Refunded { deal: d, .. }
+		| DealEvent::CouponReclaimed { deal: d, .. }
+		| DealEvent::GroupFinalized { deal: d, .. }
+		| DealEvent::DealStatusChanged { deal: d, .. } => deals.contains(d),
+		DealEvent::ReviewReplied { review: r, .. } | DealEvent::ReviewFlagged { review: r, .. } | DealEvent::ReviewHidden { review: r, .. } => *r == review,
+		DealEvent::Listed { .. } | DealEvent::Sold { .. } | DealEvent::Claimed { .. } => false,
+	}).collect();
+	let created = |deal_id: u64| DealEvent::DealCreated {
+		merchant,
+		deal: deals[deal_id as usize],
+		deal_id,
+		title: "Half off coffee".into(),
+		total_supply: 10,
+		expiry: i64::MAX,
+	};
+	assert_eq!(ours, vec![
+		DealEvent::MerchantRegistered { merchant, name: "Monke Cafe".into(), uri: "https://monke.cafe".into() },
+		created(0),
+		created(1),
+		created(2),
+		DealEvent::CouponMinted { deal, mint: Some(mint), owner: merchant, paid: 0, payment_mint: None },
+		DealEvent::CouponRedeemed { deal, mint, user: merchant, burned: false },
+		DealEvent::ReviewAdded { deal, review, user: merchant, rating: 4 },
+		DealEvent::ReviewUpdated { deal, review, user: merchant, rating: 5 },
+		DealEvent::ReviewReplied { review, reply: reply_pda(&program_id, &review), merchant },
+		DealEvent::ReviewFlagged { review, flagger: merchant, flags: 1 },
+		DealEvent::ReviewHidden { review, hidden: true },
+		DealEvent::ReviewHidden { review, hidden: false },
+		DealEvent::ReviewDeleted { deal, review, user: merchant },
+		DealEvent::CouponMinted { deal: deals[1], mint: Some(group_mint), owner: merchant, paid: price, payment_mint: None },
+		DealEvent::GroupFinalized { deal: deals[1], participants: 1, released: price, payment_mint: None },
+		DealEvent::CouponMinted { deal: deals[2], mint: Some(refunded_mint), owner: merchant, paid: price, payment_mint: None },
+		DealEvent::DealStatusChanged { deal: deals[2], status: DealStatus::Cancelled },
+		DealEvent::Refunded { deal: deals[2], mint: refunded_mint, holder: merchant, amount: price, payment_mint: None },
+		DealEvent::CouponMinted { deal, mint: Some(claim_mint), owner: claim, paid: 0, payment_mint: None },
+		DealEvent::CouponReclaimed { deal, mint: claim_mint },
+	]);
+}
+
+#[test]
+fn event_parser_skips_data_logged_by_other_programs() {
+	use base64::{engine::general_purpose::STANDARD, Engine};
+
+	let program_id = Pubkey::new_unique();
+	let other = Pubkey::new_unique();
+	let listed = DealEvent::Listed { listing: Pubkey::new_unique(), nft_mint: Pubkey::new_unique(), seller: Pubkey::new_unique(), price: 5, payment_mint: None };
+	let sold = DealEvent::Sold { listing: Pubkey::new_unique(), nft_mint: Pubkey::new_unique(), seller: Pubkey::new_unique(), buyer: Pubkey::new_unique(), price: 5, payment_mint: Some(Pubkey::new_unique()) };
+	let data = |event: &DealEvent| format!("Program data: {}", STANDARD.encode(event.try_to_vec().unwrap()));
+	let logs = [
+		format!("Program {} invoke [1]", other),
+		data(&sold),
+		format!("Program {} success", other),
+		format!("Program {} invoke [1]", program_id),
+		"Program log: NFT listed for 5 lamports".into(),
+		format!("Program {} invoke [2]", other),
+		data(&sold),
+		format!("Program {} consumed 100 of 200000 compute units", other),
+		format!("Program {} success", other),
+		"Program data: not-an-event".into(),
+		data(&listed),
+		format!("Program {} success", program_id),
+		format!("Program {} invoke [1]", program_id),
+		data(&sold),
+		format!("Program {} failed: custom program error: 0x1", program_id),
+		data(&listed),
+	];
+	assert_eq!(event::parse_logs(&program_id, &logs), vec![listed.clone(), sold.clone()]);
+	assert_eq!(DealEvent::decode(&STANDARD.encode(listed.try_to_vec().unwrap())), Some(listed));
+}
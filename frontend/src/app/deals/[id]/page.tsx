@@ -4,7 +4,7 @@ import { useCallback, useEffect, useMemo, useState, useRef } from 'react';
 import { useParams } from 'next/navigation';
 import { useConnection, useWallet } from '@solana/wallet-adapter-react';
 import { PublicKey, Transaction } from '@solana/web3.js';
-import { deriveDealPda, deriveMerchantPda, deriveReviewPda, fetchAllDeals, fetchReviewsForDeal, ixVerifyAndCountMint, ixAddReview, type DealAccount, type ReviewAccount } from '@/lib/solana/instructions';
+import { deriveDealPda, deriveMerchantPda, deriveReviewPda, fetchAllDeals, fetchReviewsForDeal, findPurchaseProof, ixVerifyAndCountMint, ixAddReview, formatDiscount, type DealAccount, type ReviewAccount } from '@/lib/solana/instructions';
 import { useUmi } from '@/lib/umi/client';
 import { generateSigner, percentAmount } from '@metaplex-foundation/umi';
 import { createNft } from '@metaplex-foundation/mpl-token-metadata';
@@ -168,9 +168,15 @@ export default function DealDetailPage() {
 			const dealPda = new PublicKey(deal.pubkey);
 			const reviewPda = deriveReviewPda(programId, dealPda, publicKey);
 
+			const proof = await findPurchaseProof(connection, programId, publicKey, dealPda);
+			if (!proof) {
+				showToast('error', 'Cannot Review', 'Only customers who hold or redeemed a coupon of this deal can review it');
+				return;
+			}
+
 			toastId = showToast('loading', 'Submitting Review', 'Creating review on-chain...');
 
-			const ix = ixAddReview(programId, publicKey, merchantPda, dealPda, reviewPda, dealId, newRating, newComment);
+			const ix = ixAddReview(programId, publicKey, merchantPda, dealPda, reviewPda, dealId, newRating, newComment, proof);
 			const tx = new Transaction().add(ix);
 			tx.feePayer = publicKey;
 			tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
@@ -392,7 +398,7 @@ export default function DealDetailPage() {
 						</div>
 						<div className="rounded-lg border border-neutral-800 bg-neutral-900/50 p-4">
 							<div className="text-xs text-neutral-500 mb-1">Discount</div>
-							<div className="text-2xl font-bold text-green-400">{deal.account.discount_percent}% OFF</div>
+							<div className="text-2xl font-bold text-green-400">{formatDiscount(deal.account.discount)}</div>
 						</div>
 					</div>
 
@@ -484,8 +490,7 @@ export default function DealDetailPage() {
 						<div className="flex items-start justify-between gap-4 mb-3">
 							<h1 className="text-4xl font-bold">{deal.account.title}</h1>
 							<div className="px-4 py-2 rounded-lg bg-green-900/50 border border-green-700">
-								<div className="text-3xl font-bold text-green-200">{deal.account.discount_percent}%</div>
-								<div className="text-xs text-green-300/70">OFF</div>
+								<div className="text-3xl font-bold text-green-200">{formatDiscount(deal.account.discount)}</div>
 							</div>
 						</div>
 						<p className="text-lg text-neutral-300">{deal.account.description}</p>
@@ -3,7 +3,7 @@
 import { useEffect, useMemo, useState } from 'react';
 import { useConnection } from '@solana/wallet-adapter-react';
 import { PublicKey } from '@solana/web3.js';
-import { fetchAllDeals, type DealAccount } from '@/lib/solana/instructions';
+import { fetchAllDeals, formatDiscount, type DealAccount } from '@/lib/solana/instructions';
 import Link from 'next/link';
 import { DealCardSkeleton } from '@/components/Loader';
 
@@ -103,7 +103,7 @@ export default function DealsPage() {
 					<div className="flex items-start justify-between gap-2">
 						<div className="font-medium text-lg group-hover:text-white transition-colors">{d.account.title}</div>
 						<div className="px-2 py-0.5 rounded bg-green-900/50 text-green-200 text-xs font-medium whitespace-nowrap">
-							{d.account.discount_percent}% OFF
+							{formatDiscount(d.account.discount)}
 						</div>
 					</div>
 					<p className="text-sm text-neutral-400 line-clamp-2">{d.account.description}</p>
@@ -3,11 +3,18 @@
 import { useCallback, useEffect, useMemo, useState, useRef } from 'react';
 import { useConnection, useWallet } from '@solana/wallet-adapter-react';
 import { PublicKey, Transaction, LAMPORTS_PER_SOL } from '@solana/web3.js';
-import { fetchAllDeals, type DealAccount, ixBuyNft, fetchAllListings, type ListingAccount, deriveEscrowPda } from '@/lib/solana/instructions';
+import { fetchAllDeals, formatDiscount, type DealAccount, ixBuyNft, fetchAllListings, type ListingAccount, deriveEscrowPda } from '@/lib/solana/instructions';
 import { useToast } from '@/lib/toast/ToastContext';
 import { parseContractError, getShortTxSignature, getExplorerUrl } from '@/lib/solana/errors';
 import { DealCardSkeleton } from '@/components/Loader';
 
+// Lamports, or base units of the payment mint for token-priced listings
+function formatListingPrice(listing: ListingAccount): string {
+	if (!listing.payment_mint) return `${(Number(listing.price) / LAMPORTS_PER_SOL).toFixed(2)} SOL`;
+	const mint = new PublicKey(listing.payment_mint).toBase58();
+	return `${listing.price.toString()} ${mint.slice(0, 4)}…${mint.slice(-4)}`;
+}
+
 interface ListingWithMetadata {
 	pubkey: PublicKey;
 	listing: ListingAccount;
@@ -155,7 +162,7 @@ export default function MarketplacePage() {
 		const toastId = showToast('loading', 'Buying NFT...', 'Preparing transaction');
 
 		try {
-			const { getAssociatedTokenAddressSync, createAssociatedTokenAccountInstruction, TOKEN_PROGRAM_ID } = await import('@solana/spl-token');
+			const { getAssociatedTokenAddressSync, createAssociatedTokenAccountInstruction, createAssociatedTokenAccountIdempotentInstruction, TOKEN_PROGRAM_ID } = await import('@solana/spl-token');
 			const buyerATA = getAssociatedTokenAddressSync(nftMint, publicKey);
 			const [escrowPda] = deriveEscrowPda(programId, nftMint);
 			const escrowATA = getAssociatedTokenAddressSync(nftMint, escrowPda, true);
@@ -173,7 +180,16 @@ export default function MarketplacePage() {
 				));
 			}
 
-			const buyIx = ixBuyNft(programId, publicKey, seller, listingData.pubkey, nftMint, escrowPda, escrowATA, buyerATA, TOKEN_PROGRAM_ID);
+			// Token-priced listings are paid from the buyer's token account into the seller's
+			let payment: { buyerToken: PublicKey; sellerToken: PublicKey; mint: PublicKey } | undefined;
+			if (listingData.listing.payment_mint) {
+				const paymentMint = new PublicKey(listingData.listing.payment_mint);
+				const sellerToken = getAssociatedTokenAddressSync(paymentMint, seller);
+				tx.add(createAssociatedTokenAccountIdempotentInstruction(publicKey, sellerToken, seller, paymentMint));
+				payment = { buyerToken: getAssociatedTokenAddressSync(paymentMint, publicKey), sellerToken, mint: paymentMint };
+			}
+
+			const buyIx = ixBuyNft(programId, publicKey, seller, listingData.pubkey, nftMint, escrowPda, escrowATA, buyerATA, TOKEN_PROGRAM_ID, payment);
 			tx.add(buyIx);
 			
 			tx.feePayer = publicKey;
@@ -188,11 +204,10 @@ export default function MarketplacePage() {
 			// Refresh listings
 			await fetchListings();
 			
-			const priceSOL = Number(listingData.listing.price) / LAMPORTS_PER_SOL;
 			updateToast(toastId, {
 				type: 'success',
 				title: 'NFT Purchased Successfully!',
-				message: `Paid ${priceSOL.toFixed(2)} SOL`,
+				message: `Paid ${formatListingPrice(listingData.listing)}`,
 				txLink: getExplorerUrl(sig),
 				duration: 10000
 			});
@@ -254,7 +269,6 @@ export default function MarketplacePage() {
 				<div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4">
 					{listings.map((listingData) => {
 						const isBuying = buying === listingData.pubkey.toBase58();
-						const priceSOL = Number(listingData.listing.price) / LAMPORTS_PER_SOL;
 						const isOwnListing = publicKey && new PublicKey(listingData.listing.seller).equals(publicKey);
 						
 						return (
@@ -302,7 +316,7 @@ export default function MarketplacePage() {
 											<div className="flex items-center gap-1 mb-1">
 												<span>💰</span>
 												<span className="font-medium text-green-400">
-													{listingData.dealInfo.account.discount_percent}% OFF
+													{formatDiscount(listingData.dealInfo.account.discount)}
 												</span>
 											</div>
 											<div className="text-xs text-neutral-500">
@@ -315,7 +329,7 @@ export default function MarketplacePage() {
 									<div className="flex items-center justify-between p-3 rounded-lg bg-blue-900/20 border border-blue-800/30">
 										<span className="text-sm text-blue-300">Price</span>
 										<span className="font-bold text-xl text-blue-100">
-											{priceSOL.toFixed(2)} SOL
+											{formatListingPrice(listingData.listing)}
 										</span>
 									</div>
 									
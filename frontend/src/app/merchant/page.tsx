@@ -3,7 +3,7 @@
 import { useCallback, useEffect, useMemo, useState, useRef } from 'react';
 import { useConnection, useWallet } from '@solana/wallet-adapter-react';
 import { PublicKey, Transaction } from '@solana/web3.js';
-import { deriveDealPda, deriveMerchantPda, fetchMerchant, fetchAllDeals, ixCreateDeal, ixRegisterMerchant, type MerchantAccount, type DealAccount } from '@/lib/solana/instructions';
+import { deriveDealPda, deriveMerchantPda, fetchMerchant, fetchAllDeals, ixCreateDeal, ixRegisterMerchant, formatDiscount, type MerchantAccount, type DealAccount } from '@/lib/solana/instructions';
 import { useToast } from '@/lib/toast/ToastContext';
 import { parseContractError, getShortTxSignature, getExplorerUrl } from '@/lib/solana/errors';
 import Link from 'next/link';
@@ -154,8 +154,8 @@ export default function MerchantPage() {
 				throw new Error('You must register as a merchant first! Please register above.');
 			}
 			
-			// Auto-calculate next deal ID
-			const dealId = BigInt(merchantAcc.total_deals + 1);
+			// The program assigns the next id from the merchant account; a deal created in between fails the PDA check
+			const dealId = BigInt(merchantAcc.next_deal_id);
 			setUploadStatus('Preparing deal...');
 			updateToast(toastId, { title: `Creating Deal #${dealId}...`, message: 'Preparing upload' });
 			
@@ -229,10 +229,9 @@ export default function MerchantPage() {
 
 			const dealPda = deriveDealPda(programId, publicKey, dealId);
 			const ix = ixCreateDeal(programId, publicKey, merchantPda, dealPda, {
-				deal_id: dealId,
 				title,
 				description,
-				discount_percent: discount,
+				discount: { Percent: discount },
 				expiry,
 				total_supply: total,
 				image_uri: imageUri || '',
@@ -459,7 +458,6 @@ export default function MerchantPage() {
 								const minted = typeof deal.minted === 'bigint' ? deal.minted : BigInt(deal.minted);
 								const totalSupply = typeof deal.total_supply === 'bigint' ? deal.total_supply : BigInt(deal.total_supply);
 								const expiry = typeof deal.expiry === 'bigint' ? deal.expiry : BigInt(deal.expiry);
-								const discountPercent = typeof deal.discount_percent === 'bigint' ? deal.discount_percent : BigInt(deal.discount_percent);
 								
 								const isExpired = Number(expiry) * 1000 < Date.now();
 								const isSoldOut = minted >= totalSupply;
@@ -513,7 +511,7 @@ export default function MerchantPage() {
 													</div>
 												</div>
 												<div className="ml-2 px-2 py-1 rounded-full bg-green-600 text-white text-xs font-bold shrink-0">
-													{Number(discountPercent)}% OFF
+													{formatDiscount(deal.discount)}
 												</div>
 											</div>
 
@@ -575,7 +573,7 @@ export default function MerchantPage() {
 							<div>
 								<div className="text-2xl font-bold text-purple-200 mb-1">🎁 Create New Deal</div>
 								<p className="text-sm text-purple-300/70">
-									Your next deal will be <span className="font-mono font-bold text-purple-200">Deal #{merchantAcc.total_deals + 1}</span>
+									Your next deal will be <span className="font-mono font-bold text-purple-200">Deal #{String(merchantAcc.next_deal_id)}</span>
 								</p>
 								<p className="text-xs text-purple-300/50 mt-1">Image & metadata uploaded once and reused for all NFT mints</p>
 							</div>
@@ -3,7 +3,7 @@
 import { useCallback, useEffect, useMemo, useState, useRef } from 'react';
 import { useConnection, useWallet } from '@solana/wallet-adapter-react';
 import { PublicKey, Transaction } from '@solana/web3.js';
-import { deriveRedeemPda, ixRedeemAndBurn, fetchAllDeals, type DealAccount, ixListNft, deriveEscrowPda, deriveListingPda } from '@/lib/solana/instructions';
+import { deriveRedeemPda, fetchCoupon, ixRedeemAndBurn, fetchAllDeals, formatDiscount, type DealAccount, ixListNft, deriveEscrowPda, deriveListingPda } from '@/lib/solana/instructions';
 import { useToast } from '@/lib/toast/ToastContext';
 import { parseContractError, getShortTxSignature, getExplorerUrl } from '@/lib/solana/errors';
 import { TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync } from '@solana/spl-token';
@@ -195,11 +195,16 @@ export default function RedeemPage() {
 			);
 			
 			const redeemPda = deriveRedeemPda(programId, mint);
+			// Only coupons counted for a deal can be redeemed, against that deal
+			const coupon = await fetchCoupon(connection, programId, mint);
+			if (!coupon) {
+				throw new Error('This NFT was not counted as a coupon of any deal');
+			}
 			
 			toastId = showToast('loading', `Redeeming ${nft.name}`, 'Burning NFT and creating redemption log');
 			
 			// Use RedeemAndBurn instruction (burns NFT + creates log)
-			const ix = ixRedeemAndBurn(programId, publicKey, userTokenAccount, mint, TOKEN_PROGRAM_ID, redeemPda);
+			const ix = ixRedeemAndBurn(programId, publicKey, userTokenAccount, mint, TOKEN_PROGRAM_ID, redeemPda, new PublicKey(coupon.deal));
 			const tx = new Transaction().add(ix);
 			tx.feePayer = publicKey;
 			tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
@@ -465,7 +470,7 @@ export default function RedeemPage() {
 												<div className="flex items-center gap-1 mb-1">
 													<span>💰</span>
 													<span className="font-medium text-green-400">
-														{nft.dealInfo.account.discount_percent}% OFF
+														{formatDiscount(nft.dealInfo.account.discount)}
 													</span>
 												</div>
 												<div className="text-xs text-neutral-500">
@@ -531,7 +536,7 @@ export default function RedeemPage() {
 								<div className="font-medium">{showListModal.name}</div>
 								{showListModal.dealInfo && (
 									<div className="text-xs text-green-400 mt-1">
-										{showListModal.dealInfo.account.discount_percent}% OFF - {showListModal.dealInfo.account.title}
+										{formatDiscount(showListModal.dealInfo.account.discount)} - {showListModal.dealInfo.account.title}
 									</div>
 								)}
 							</div>
@@ -1,4 +1,5 @@
 import { PublicKey, SystemProgram, TransactionInstruction, Connection } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID } from '@solana/spl-token';
 import * as borsh from 'borsh';
 
 const IX = {
@@ -14,27 +15,101 @@ const IX = {
 	BuyNft: 9,
 } as const;
 
+// Values are objects with the variant name as their only key, e.g. { Percent: 25 } or { Bogo: {} }
+const discountKind = {
+	enum: [
+		{ struct: { Percent: 'u8' } },
+		{ struct: { FixedAmount: { struct: { amount: 'u64', currency_code: { array: { type: 'u8', len: 3 } } } } } },
+		{ struct: { Bogo: { struct: {} } } },
+		{ struct: { FreeItem: { struct: { sku: 'string' } } } },
+	],
+} as const;
+
+export type DiscountKind =
+	| { Percent: number }
+	| { FixedAmount: { amount: bigint; currency_code: number[] } }
+	| { Bogo: Record<string, never> }
+	| { FreeItem: { sku: string } };
+
+export function formatDiscount(discount: DiscountKind): string {
+	if ('Percent' in discount) return `${discount.Percent}% OFF`;
+	if ('FixedAmount' in discount) {
+		const { amount, currency_code } = discount.FixedAmount;
+		const currency = String.fromCharCode(...currency_code);
+		return `${(Number(amount) / 100).toFixed(2)} ${currency} OFF`;
+	}
+	if ('Bogo' in discount) return 'Buy one, get one';
+	return `Free ${discount.FreeItem.sku}`;
+}
+
+const dealStatus = {
+	enum: [{ struct: { Active: { struct: {} } } }, { struct: { Paused: { struct: {} } } }, { struct: { Cancelled: { struct: {} } } }],
+} as const;
+
+export type DealStatus = { Active: Record<string, never> } | { Paused: Record<string, never> } | { Cancelled: Record<string, never> };
+
+const dealGate = {
+	enum: [
+		{ struct: { TokenBalance: { struct: { mint: { array: { type: 'u8', len: 32 } }, min_amount: 'u64' } } } },
+		{ struct: { Collection: { struct: { collection: { array: { type: 'u8', len: 32 } } } } } },
+	],
+} as const;
+
+const allowlistProof = { struct: { allowance: 'u32', proof: { array: { type: { array: { type: 'u8', len: 32 } } } } } } as const;
+
+// Proof for a deal with an allowlist, as written by `cli allowlist-build`
+export type AllowlistProof = { allowance: number; proof: number[][] };
+
+export function allowlistProofFromJson(json: any, user: PublicKey): AllowlistProof | null {
+	const entry = json?.entries?.[user.toBase58()];
+	if (!entry) return null;
+	return { allowance: Number(entry.allowance), proof: (entry.proof as string[]).map((h) => Array.from(new PublicKey(h).toBytes())) };
+}
+
+export function deriveAllowancePda(programId: PublicKey, dealPda: PublicKey, user: PublicKey): PublicKey {
+	return PublicKey.findProgramAddressSync([Buffer.from('allowance'), dealPda.toBuffer(), user.toBuffer()], programId)[0];
+}
+
+// A per-user allowance is counted in the user's allowance PDA, which follows the deal with the system program
+function allowlistKeys(programId: PublicKey, dealPda: PublicKey, user: PublicKey, allowlist?: AllowlistProof) {
+	if (!allowlist || allowlist.allowance === 0) return [];
+	return [
+		{ pubkey: deriveAllowancePda(programId, dealPda, user), isSigner: false, isWritable: true },
+		{ pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+	];
+}
+
 const schemas = {
 	RegisterMerchantArgs: { struct: { name: 'string', uri: 'string' } },
+	// The deal id is assigned by the program from Merchant.next_deal_id
 	CreateDealArgs: {
 		struct: {
-			deal_id: 'u64',
 			title: 'string',
 			description: 'string',
-			discount_percent: 'u8',
+			discount: discountKind,
 			expiry: 'i64',
 			total_supply: 'u32',
 			image_uri: 'string',
 			metadata_uri: 'string',
 		},
 	},
-	MintCouponArgs: { struct: { deal_id: 'u64' } },
+	MintCouponArgs: { struct: { deal_id: 'u64', allowlist_proof: { option: allowlistProof } } },
 	RedeemCouponArgs: { struct: { mint: { array: { type: 'u8', len: 32 } } } },
 	AddReviewArgs: { struct: { deal_id: 'u64', rating: 'u8', comment: 'string' } },
-	VerifyAndCountMintArgs: { struct: { deal_id: 'u64', mint: { array: { type: 'u8', len: 32 } } } },
+	VerifyAndCountMintArgs: { struct: { deal_id: 'u64', mint: { array: { type: 'u8', len: 32 } }, allowlist_proof: { option: allowlistProof } } },
 	RedeemAndBurnArgs: { struct: { mint: { array: { type: 'u8', len: 32 } } } },
 	SetCollectionMintArgs: { struct: { collection_mint: { array: { type: 'u8', len: 32 } } } },
 
+	Coupon: {
+		struct: {
+			mint: { array: { type: 'u8', len: 32 } },
+			deal: { array: { type: 'u8', len: 32 } },
+			paid: 'u64',
+			state: 'u8',
+			bump: 'u8',
+		},
+	},
+
 	Merchant: {
 		struct: {
 			merchant: { array: { type: 'u8', len: 32 } },
@@ -42,6 +117,10 @@ const schemas = {
 			uri: 'string',
 			total_deals: 'u32',
 			collection_mint: { array: { type: 'u8', len: 32 } },
+			next_deal_id: 'u64',
+			rating_sum: 'u64',
+			rating_count: 'u32',
+			bump: 'u8',
 		},
 	},
 	Deal: {
@@ -50,12 +129,27 @@ const schemas = {
 			merchant: { array: { type: 'u8', len: 32 } },
 			title: 'string',
 			description: 'string',
-			discount_percent: 'u8',
+			discount: discountKind,
 			expiry: 'i64',
 			total_supply: 'u32',
 			minted: 'u32',
 			image_uri: 'string',
 			metadata_uri: 'string',
+			status: dealStatus,
+			schedule: { option: { struct: { weekdays: 'u8', start_minute: 'u16', end_minute: 'u16', utc_offset_minutes: 'i16' } } },
+			mint_price_lamports: 'u64',
+			token_price: { option: { struct: { mint: { array: { type: 'u8', len: 32 } }, amount: 'u64' } } },
+			min_participants: 'u32',
+			funding_deadline: 'i64',
+			group_finalized: 'bool',
+			price_curve: { option: { struct: { start_price: 'u64', end_price: 'u64', start_ts: 'i64', end_ts: 'i64', step_seconds: 'u32' } } },
+			raffle: { option: { struct: { winners: 'u32', entry_deadline: 'i64', entries: 'u32', seed: { option: { array: { type: 'u8', len: 32 } } } } } },
+			allowlist_root: { array: { type: 'u8', len: 32 } },
+			gate: { option: dealGate },
+			rating_sum: 'u64',
+			rating_count: 'u32',
+			vault_bump: { option: 'u8' },
+			bump: 'u8',
 		},
 	},
 	Review: {
@@ -65,15 +159,23 @@ const schemas = {
 			rating: 'u8',
 			comment: 'string',
 			created_at: 'i64',
+			verified_purchase: 'bool',
+			edited_at: 'i64',
+			flags: 'u32',
+			hidden: 'bool',
+			reply_bump: { option: 'u8' },
+			bump: 'u8',
 		},
 	},
-	ListNftArgs: { struct: { price: 'u64' } },
+	ListNftArgs: { struct: { price: 'u64', payment_mint: { option: { array: { type: 'u8', len: 32 } } } } },
 	Listing: {
 		struct: {
 			seller: { array: { type: 'u8', len: 32 } },
 			nft_mint: { array: { type: 'u8', len: 32 } },
 			price: 'u64',
 			created_at: 'i64',
+			payment_mint: { option: { array: { type: 'u8', len: 32 } } },
+			bump: 'u8',
 		},
 	},
 } as const;
@@ -109,6 +211,11 @@ export function deriveRedeemPda(programId: PublicKey, mint: PublicKey) {
 	return PublicKey.findProgramAddressSync([Buffer.from('redeem'), mint.toBuffer()], programId)[0];
 }
 
+// Record of a counted coupon; it ties the mint to its deal for redemption and reviews
+export function deriveCouponPda(programId: PublicKey, mint: PublicKey) {
+	return PublicKey.findProgramAddressSync([Buffer.from('coupon'), mint.toBuffer()], programId)[0];
+}
+
 export function ixRegisterMerchant(programId: PublicKey, payer: PublicKey, merchantPda: PublicKey, name: string, uri: string) {
 	const data = Buffer.concat([Buffer.from([IX.RegisterMerchant]), serialize(schemas.RegisterMerchantArgs as any, { name, uri })]);
 	return new TransactionInstruction({
@@ -140,10 +247,9 @@ export function ixCreateDeal(
 	merchantPda: PublicKey,
 	dealPda: PublicKey,
 	args: {
-		deal_id: bigint;
 		title: string;
 		description: string;
-		discount_percent: number;
+		discount: DiscountKind;
 		expiry: bigint;
 		total_supply: number;
 		image_uri: string;
@@ -163,27 +269,47 @@ export function ixCreateDeal(
 	});
 }
 
-export function ixMintCoupon(programId: PublicKey, user: PublicKey, merchantPda: PublicKey, dealPda: PublicKey, dealId: bigint) {
-	const data = Buffer.concat([Buffer.from([IX.MintCoupon]), serialize(schemas.MintCouponArgs as any, { deal_id: dealId })]);
+export function ixMintCoupon(programId: PublicKey, user: PublicKey, merchantPda: PublicKey, dealPda: PublicKey, dealId: bigint, allowlist?: AllowlistProof) {
+	const data = Buffer.concat([Buffer.from([IX.MintCoupon]), serialize(schemas.MintCouponArgs as any, { deal_id: dealId, allowlist_proof: allowlist ?? null })]);
 	return new TransactionInstruction({
 		programId,
 		keys: [
-			{ pubkey: user, isSigner: true, isWritable: false },
+			// Pays for the allowance PDA on an allowlisted user's first mint
+			{ pubkey: user, isSigner: true, isWritable: true },
 			{ pubkey: merchantPda, isSigner: false, isWritable: false },
 			{ pubkey: dealPda, isSigner: false, isWritable: true },
+			...allowlistKeys(programId, dealPda, user, allowlist),
 		],
 		data,
 	});
 }
 
-export function ixVerifyAndCountMint(programId: PublicKey, user: PublicKey, merchantPda: PublicKey, dealPda: PublicKey, dealId: bigint, mint: PublicKey) {
-	const data = Buffer.concat([Buffer.from([IX.VerifyAndCountMint]), serialize(schemas.VerifyAndCountMintArgs as any, { deal_id: dealId, mint: Array.from(mint.toBytes()) })]);
+// Records the mint in its coupon PDA; paid deals also pass the deal vault the price is escrowed in, and allowlisted
+// deals the user's proof
+export function ixVerifyAndCountMint(
+	programId: PublicKey,
+	user: PublicKey,
+	merchantPda: PublicKey,
+	dealPda: PublicKey,
+	dealId: bigint,
+	mint: PublicKey,
+	vault?: PublicKey,
+	allowlist?: AllowlistProof
+) {
+	const data = Buffer.concat([
+		Buffer.from([IX.VerifyAndCountMint]),
+		serialize(schemas.VerifyAndCountMintArgs as any, { deal_id: dealId, mint: Array.from(mint.toBytes()), allowlist_proof: allowlist ?? null }),
+	]);
 	return new TransactionInstruction({
 		programId,
 		keys: [
-			{ pubkey: user, isSigner: true, isWritable: false },
+			{ pubkey: user, isSigner: true, isWritable: true },
 			{ pubkey: merchantPda, isSigner: false, isWritable: false },
 			{ pubkey: dealPda, isSigner: false, isWritable: true },
+			...allowlistKeys(programId, dealPda, user, allowlist),
+			{ pubkey: deriveCouponPda(programId, mint), isSigner: false, isWritable: true },
+			...(vault ? [{ pubkey: vault, isSigner: false, isWritable: true }] : []),
+			{ pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
 		],
 		data,
 	});
@@ -202,33 +328,71 @@ export function ixRedeem(programId: PublicKey, user: PublicKey, redeemPda: Publi
 	});
 }
 
-export function ixRedeemAndBurn(programId: PublicKey, user: PublicKey, userToken: PublicKey, mint: PublicKey, tokenProgram: PublicKey, redeemPda: PublicKey) {
+// `dealPda` is the deal the coupon was counted for, as read from its coupon PDA
+export function ixRedeemAndBurn(programId: PublicKey, user: PublicKey, userToken: PublicKey, mint: PublicKey, tokenProgram: PublicKey, redeemPda: PublicKey, dealPda: PublicKey) {
 	const data = Buffer.concat([Buffer.from([IX.RedeemAndBurn]), serialize(schemas.RedeemAndBurnArgs as any, { mint: Array.from(mint.toBytes()) })]);
 	return new TransactionInstruction({
 		programId,
 		keys: [
-			{ pubkey: user, isSigner: true, isWritable: false },
+			{ pubkey: user, isSigner: true, isWritable: true },
 			{ pubkey: userToken, isSigner: false, isWritable: true },
 			{ pubkey: mint, isSigner: false, isWritable: true },
 			{ pubkey: tokenProgram, isSigner: false, isWritable: false },
 			{ pubkey: redeemPda, isSigner: false, isWritable: true },
 			{ pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+			{ pubkey: dealPda, isSigner: false, isWritable: false },
+			{ pubkey: deriveCouponPda(programId, mint), isSigner: false, isWritable: true },
 		],
 		data,
 	});
 }
 
+export type CouponAccount = {
+	mint: Uint8Array;
+	deal: Uint8Array;
+	paid: bigint;
+	// 0 escrowed, 1 released, 2 refunded
+	state: number;
+	bump: number;
+};
+
+// Accounts start with [kind, version]; coupon records are kind 6
+export async function fetchCoupon(connection: Connection, programId: PublicKey, mint: PublicKey): Promise<CouponAccount | null> {
+	const info = await connection.getAccountInfo(deriveCouponPda(programId, mint));
+	if (!info?.data || info.data[0] !== 6) return null;
+	try {
+		return deserialize<CouponAccount>(schemas.Coupon as any, info.data.subarray(2));
+	} catch {
+		return null;
+	}
+}
+
 export type DealAccount = {
 	deal_id: bigint;
 	merchant: Uint8Array;
 	title: string;
 	description: string;
-	discount_percent: number;
+	discount: DiscountKind;
 	expiry: bigint;
 	total_supply: number;
 	minted: number;
 	image_uri: string;
 	metadata_uri: string;
+	status: DealStatus;
+	schedule: { weekdays: number; start_minute: number; end_minute: number; utc_offset_minutes: number } | null;
+	mint_price_lamports: bigint;
+	token_price: { mint: Uint8Array; amount: bigint } | null;
+	min_participants: number;
+	funding_deadline: bigint;
+	group_finalized: boolean;
+	price_curve: { start_price: bigint; end_price: bigint; start_ts: bigint; end_ts: bigint; step_seconds: number } | null;
+	raffle: { winners: number; entry_deadline: bigint; entries: number; seed: Uint8Array | null } | null;
+	allowlist_root: Uint8Array;
+	gate: { TokenBalance: { mint: Uint8Array; min_amount: bigint } } | { Collection: { collection: Uint8Array } } | null;
+	rating_sum: bigint;
+	rating_count: number;
+	vault_bump: number | null;
+	bump: number;
 };
 
 export type MerchantAccount = {
@@ -237,20 +401,32 @@ export type MerchantAccount = {
 	uri: string;
 	total_deals: number;
 	collection_mint: Uint8Array;
+	next_deal_id: bigint;
+	rating_sum: bigint;
+	rating_count: number;
+	bump: number;
 };
 
 export async function fetchDeal(connection: Connection, dealPda: PublicKey): Promise<DealAccount | null> {
 	const info = await connection.getAccountInfo(dealPda);
 	if (!info?.data) return null;
-	const decoded = deserialize<DealAccount>(schemas.Deal as any, info.data);
-	return decoded;
+	// Accounts start with [kind, version]; deals are kind 2
+	if (info.data[0] !== 2) return null;
+	try {
+		const decoded = deserialize<DealAccount>(schemas.Deal as any, info.data.subarray(2));
+		return decoded;
+	} catch {
+		return null;
+	}
 }
 
 export async function fetchMerchant(connection: Connection, merchantPda: PublicKey): Promise<MerchantAccount | null> {
 	const info = await connection.getAccountInfo(merchantPda);
 	if (!info?.data) return null;
+	// Accounts start with [kind, version]; merchants are kind 1
+	if (info.data[0] !== 1) return null;
 	try {
-		const decoded = deserialize<MerchantAccount>(schemas.Merchant as any, info.data);
+		const decoded = deserialize<MerchantAccount>(schemas.Merchant as any, info.data.subarray(2));
 		return decoded;
 	} catch {
 		return null;
@@ -261,8 +437,9 @@ export async function fetchAllDeals(connection: Connection, programId: PublicKey
 	const accounts = await connection.getProgramAccounts(programId, { commitment: 'confirmed' });
 	const out: Array<{ pubkey: PublicKey; account: DealAccount }> = [];
 	for (const acc of accounts) {
+		if (acc.account.data[0] !== 2) continue;
 		try {
-			const decoded = deserialize<DealAccount>(schemas.Deal as any, acc.account.data);
+			const decoded = deserialize<DealAccount>(schemas.Deal as any, acc.account.data.subarray(2));
 			out.push({ pubkey: acc.pubkey, account: decoded });
 		} catch (e) {
 			// not a Deal account; skip
@@ -277,28 +454,67 @@ export type ReviewAccount = {
 	rating: number;
 	comment: string;
 	created_at: bigint;
+	verified_purchase: boolean;
+	// 0 until the review is first edited
+	edited_at: bigint;
+	flags: number;
+	hidden: boolean;
+	// Set once the merchant replies
+	reply_bump: number | null;
+	bump: number;
 };
 
-export function ixAddReview(programId: PublicKey, user: PublicKey, merchantPda: PublicKey, dealPda: PublicKey, reviewPda: PublicKey, dealId: bigint, rating: number, comment: string) {
+// `proof` comes from findPurchaseProof
+export function ixAddReview(programId: PublicKey, user: PublicKey, merchantPda: PublicKey, dealPda: PublicKey, reviewPda: PublicKey, dealId: bigint, rating: number, comment: string, proof: PublicKey[]) {
 	const data = Buffer.concat([Buffer.from([IX.AddReview]), serialize(schemas.AddReviewArgs as any, { deal_id: dealId, rating, comment })]);
 	return new TransactionInstruction({
 		programId,
 		keys: [
 			{ pubkey: user, isSigner: true, isWritable: true },
-			{ pubkey: merchantPda, isSigner: false, isWritable: false },
-			{ pubkey: dealPda, isSigner: false, isWritable: false },
+			{ pubkey: merchantPda, isSigner: false, isWritable: true },
+			{ pubkey: dealPda, isSigner: false, isWritable: true },
 			{ pubkey: reviewPda, isSigner: false, isWritable: true },
 			{ pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+			...proof.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })),
 		],
 		data,
 	});
 }
 
+// A review is verified by the user's redeem log for the deal, or by a coupon of the deal they still hold, followed by
+// the coupon's record. Returns null when the user has neither.
+export async function findPurchaseProof(connection: Connection, programId: PublicKey, user: PublicKey, dealPda: PublicKey): Promise<PublicKey[] | null> {
+	// RedeemLog: [kind, version], token_mint, user, redeemed_at, deal, bump
+	const logs = await connection.getProgramAccounts(programId, {
+		filters: [
+			{ dataSize: 2 + 32 + 32 + 8 + 32 + 1 },
+			{ memcmp: { offset: 2 + 32, bytes: user.toBase58() } },
+			{ memcmp: { offset: 2 + 32 + 32 + 8, bytes: dealPda.toBase58() } },
+		],
+	});
+	for (const log of logs) {
+		const mint = new PublicKey(log.account.data.subarray(2, 2 + 32));
+		return [log.pubkey, deriveCouponPda(programId, mint)];
+	}
+	const held = await connection.getParsedTokenAccountsByOwner(user, { programId: TOKEN_PROGRAM_ID });
+	for (const { pubkey, account } of held.value) {
+		const info = account.data.parsed?.info;
+		if (info?.tokenAmount?.amount !== '1') continue;
+		const mint = new PublicKey(info.mint);
+		const coupon = await fetchCoupon(connection, programId, mint);
+		if (coupon && coupon.state !== 2 && new PublicKey(coupon.deal).equals(dealPda)) {
+			return [pubkey, deriveCouponPda(programId, mint)];
+		}
+	}
+	return null;
+}
+
 export async function fetchReview(connection: Connection, reviewPda: PublicKey): Promise<ReviewAccount | null> {
 	const info = await connection.getAccountInfo(reviewPda);
-	if (!info?.data) return null;
+	// Reviews are kind 3
+	if (!info?.data || info.data[0] !== 3) return null;
 	try {
-		const decoded = deserialize<ReviewAccount>(schemas.Review as any, info.data);
+		const decoded = deserialize<ReviewAccount>(schemas.Review as any, info.data.subarray(2));
 		return decoded;
 	} catch {
 		return null;
@@ -309,11 +525,12 @@ export async function fetchReviewsForDeal(connection: Connection, programId: Pub
 	const accounts = await connection.getProgramAccounts(programId, { commitment: 'confirmed' });
 	const out: Array<{ pubkey: PublicKey; account: ReviewAccount }> = [];
 	for (const acc of accounts) {
+		if (acc.account.data[0] !== 3) continue;
 		try {
-			const decoded = deserialize<ReviewAccount>(schemas.Review as any, acc.account.data);
-			// Filter by deal PDA
+			const decoded = deserialize<ReviewAccount>(schemas.Review as any, acc.account.data.subarray(2));
+			// Filter by deal PDA, skipping reviews the admin hid
 			const dealBytes = dealPda.toBytes();
-			if (Buffer.from(decoded.deal).equals(Buffer.from(dealBytes))) {
+			if (!decoded.hidden && Buffer.from(decoded.deal).equals(Buffer.from(dealBytes))) {
 				out.push({ pubkey: acc.pubkey, account: decoded });
 			}
 		} catch (e) {
@@ -331,6 +548,8 @@ export type ListingAccount = {
 	nft_mint: number[];
 	price: bigint;
 	created_at: bigint;
+	payment_mint: number[] | null;
+	bump: number;
 };
 
 export function deriveListingPda(programId: PublicKey, nftMint: PublicKey, seller: PublicKey): [PublicKey, number] {
@@ -349,9 +568,14 @@ export function ixListNft(
 	sellerTokenAccount: PublicKey,
 	escrowTokenAccount: PublicKey,
 	tokenProgram: PublicKey,
-	price: bigint
+	price: bigint,
+	// Prices the listing in base units of this SPL token instead of lamports
+	paymentMint?: PublicKey
 ) {
-	const data = Buffer.concat([Buffer.from([IX.ListNft]), serialize(schemas.ListNftArgs as any, { price })]);
+	const data = Buffer.concat([
+		Buffer.from([IX.ListNft]),
+		serialize(schemas.ListNftArgs as any, { price, payment_mint: paymentMint ? Array.from(paymentMint.toBytes()) : null }),
+	]);
 	return new TransactionInstruction({
 		programId,
 		keys: [
@@ -376,7 +600,9 @@ export function ixBuyNft(
 	escrowPda: PublicKey,
 	escrowTokenAccount: PublicKey,
 	buyerTokenAccount: PublicKey,
-	tokenProgram: PublicKey
+	tokenProgram: PublicKey,
+	// Token-priced listings: the buyer's and seller's token accounts of the payment mint, and the mint
+	payment?: { buyerToken: PublicKey; sellerToken: PublicKey; mint: PublicKey }
 ) {
 	const data = Buffer.from([IX.BuyNft]);
 	return new TransactionInstruction({
@@ -391,6 +617,13 @@ export function ixBuyNft(
 			{ pubkey: buyerTokenAccount, isSigner: false, isWritable: true },
 			{ pubkey: tokenProgram, isSigner: false, isWritable: false },
 			{ pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+			...(payment
+				? [
+						{ pubkey: payment.buyerToken, isSigner: false, isWritable: true },
+						{ pubkey: payment.sellerToken, isSigner: false, isWritable: true },
+						{ pubkey: payment.mint, isSigner: false, isWritable: false },
+				  ]
+				: []),
 		],
 		data,
 	});
@@ -398,9 +631,10 @@ export function ixBuyNft(
 
 export async function fetchListing(connection: Connection, listingPda: PublicKey): Promise<ListingAccount | null> {
 	const info = await connection.getAccountInfo(listingPda);
-	if (!info?.data) return null;
+	// Listings are kind 5
+	if (!info?.data || info.data[0] !== 5) return null;
 	try {
-		const decoded = deserialize<ListingAccount>(schemas.Listing as any, info.data);
+		const decoded = deserialize<ListingAccount>(schemas.Listing as any, info.data.subarray(2));
 		return decoded;
 	} catch {
 		return null;
@@ -411,25 +645,25 @@ export async function fetchAllListings(connection: Connection, programId: Public
 	const accounts = await connection.getProgramAccounts(programId, { commitment: 'confirmed' });
 	const out: Array<{ pubkey: PublicKey; account: ListingAccount }> = [];
 	
-	// Listing account size is 80 bytes (32 + 32 + 8 + 8)
-	const LISTING_SIZE = 80;
+	// Accounts start with [kind, version]; listings are kind 5
+	const LISTING_KIND = 5;
 	
 	console.log(`Total program accounts: ${accounts.length}`);
 	
 	for (const acc of accounts) {
-		// Filter by account size first
-		if (acc.account.data.length !== LISTING_SIZE) {
-			console.log(`Skipping account ${acc.pubkey.toBase58()}: wrong size (${acc.account.data.length} bytes, expected ${LISTING_SIZE})`);
+		// Filter by account kind first
+		if (acc.account.data[0] !== LISTING_KIND) {
+			console.log(`Skipping account ${acc.pubkey.toBase58()}: not a listing (kind ${acc.account.data[0]})`);
 			continue;
 		}
 		
 		try {
-			const decoded = deserialize<ListingAccount>(schemas.Listing as any, acc.account.data);
+			const decoded = deserialize<ListingAccount>(schemas.Listing as any, acc.account.data.subarray(2));
 			
 			// Additional validation: check if price is reasonable (not crazy high)
-			// Max reasonable price: 1000 SOL = 1,000,000,000,000 lamports
+			// Max reasonable price: 1000 SOL = 1,000,000,000,000 lamports (token-priced listings are in the mint's base units)
 			const MAX_REASONABLE_PRICE = BigInt(1_000_000_000_000);
-			if (decoded.price > MAX_REASONABLE_PRICE) {
+			if (!decoded.payment_mint && decoded.price > MAX_REASONABLE_PRICE) {
 				console.log(`Skipping account ${acc.pubkey.toBase58()}: unreasonable price (${decoded.price})`);
 				continue;
 			}